@@ -0,0 +1,41 @@
+/*
+clock.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! The local date and time, for daily limits and the order history.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the current local time, broken down into its parts.
+fn local_now() -> libc::tm {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()) as libc::time_t;
+    // SAFETY: tm is plain data, and localtime_r only writes to the tm it's given
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    }
+}
+
+/// Returns today's date, as `YYYY-MM-DD`.
+pub fn local_date() -> String {
+    let tm = local_now();
+    format!("{:04}-{:02}-{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
+/// Returns the current date and time, as `YYYY-MM-DD HH:MM:SS`.
+pub fn local_date_time() -> String {
+    let tm = local_now();
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
@@ -0,0 +1,65 @@
+/*
+coin.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Pulse inputs from coin acceptors.
+//!
+//! Multi-coin acceptors send a number of short pulses for each coin, depending on what it's worth (e.g. 1 pulse
+//! for 5 cents, 5 pulses for a quarter). Every pulse is worth the same amount, so all that matters is counting them.
+
+use std::{sync::mpsc::Receiver, time::Duration};
+
+use crate::{error::Result, gpio::DebouncedInput};
+
+/// Something that produces pulses, like a coin acceptor.
+pub trait PulseSource {
+    /// Returns the number of pulses since the last call. This needs to be called more often than the pulses
+    /// come in, about once a millisecond.
+    fn poll(&mut self) -> u32;
+}
+
+/// A coin acceptor's pulse output on a GPIO pin.
+pub struct CoinAcceptor {
+    input: DebouncedInput,
+    active: bool,
+}
+
+impl CoinAcceptor {
+    /// Sets up a coin acceptor on a pin. Most acceptors pull their output low for each pulse.
+    pub fn new(pin: u8, active_high: bool, debounce: Duration) -> Result<CoinAcceptor> {
+        let mut input = DebouncedInput::new(pin, active_high, debounce)?;
+        let active = input.poll();
+        Ok(Self { input, active })
+    }
+}
+
+impl PulseSource for CoinAcceptor {
+    fn poll(&mut self) -> u32 {
+        // a pulse is counted when it starts
+        let active = self.input.poll();
+        let pulse = active && !self.active;
+        self.active = active;
+        pulse as u32
+    }
+}
+
+/// Pulses sent from elsewhere in the program, for testing without a coin acceptor.
+pub struct SimulatedPulses {
+    receiver: Receiver<u32>,
+}
+
+impl SimulatedPulses {
+    /// Creates a pulse source that produces every pulse sent down the channel.
+    pub fn new(receiver: Receiver<u32>) -> SimulatedPulses {
+        Self { receiver }
+    }
+}
+
+impl PulseSource for SimulatedPulses {
+    fn poll(&mut self) -> u32 {
+        self.receiver.try_iter().fold(0, u32::saturating_add)
+    }
+}
@@ -0,0 +1,674 @@
+/*
+config.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Settings for the dispenser, loaded from a TOML file at startup.
+//!
+//! The file is `dispenser.toml` in the working directory, unless the `DISPENSER_CONFIG` environment variable
+//! points somewhere else. Every setting is optional; missing settings (or a missing file) use the defaults.
+
+use std::{collections::BTreeMap, env, error::Error, fmt, fs, io};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    display::DisplayKind,
+    i18n::Language,
+    leds::{Pattern, Rgb},
+    music::{try_note2midi, SongTransform},
+};
+
+/// Default location of the config file.
+const DEFAULT_PATH: &str = "dispenser.toml";
+
+/// All settings for the dispenser.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub music: MusicConfig,
+    pub shutdown: ShutdownConfig,
+    pub persist: PersistConfig,
+    pub estop: EstopConfig,
+    pub panel: PanelConfig,
+    pub leds: LedsConfig,
+    pub display: DisplayConfig,
+    pub payment: PaymentConfig,
+    pub scanner: ScannerConfig,
+    pub badges: BadgesConfig,
+    pub admin: AdminConfig,
+    pub inventory: InventoryConfig,
+    /// Orders shown as one-tap buttons on the order screen, written as `[[presets]]` tables.
+    pub presets: Vec<Preset>,
+    pub surprise: SurpriseConfig,
+    pub limits: LimitsConfig,
+    /// Theme file for the GUI's colours, fonts, logo and names. The default look is used if this isn't set.
+    pub theme_file: Option<String>,
+    /// Language the GUI starts in, e.g. `"en"` or `"fr"`. Customers can switch it from the order screen.
+    pub language: Language,
+    pub attract: AttractConfig,
+}
+
+/// Settings for the music played on the buzzers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MusicConfig {
+    /// The song to play: either the name of a built-in song, or the path to a song file.
+    pub song: String,
+    /// Number of semitones to transpose the song by.
+    pub transpose: i32,
+    /// Factor to multiply the song's tempo by.
+    #[serde(deserialize_with = "positive")]
+    pub tempo_scale: f64,
+    /// Lowest note the buzzers can play well, e.g. `"C4"`. Lower notes are moved up by octaves.
+    #[serde(deserialize_with = "note_name")]
+    pub lowest_note: Option<u32>,
+    /// Highest note the buzzers can play well. Higher notes are moved down by octaves.
+    #[serde(deserialize_with = "note_name")]
+    pub highest_note: Option<u32>,
+    /// Pins with a buzzer attached; only 18 and 19 can do PWM. The first buzzer plays the melody, and the last
+    /// one arpeggiates any voices left over, so with a single buzzer every voice is arpeggiated on it.
+    pub buzzer_pins: Vec<u8>,
+}
+
+impl Default for MusicConfig {
+    fn default() -> Self {
+        Self {
+            song: "badapple".to_owned(),
+            transpose: 0,
+            tempo_scale: 1.0,
+            lowest_note: None,
+            highest_note: None,
+            buzzer_pins: vec![18, 19],
+        }
+    }
+}
+
+impl MusicConfig {
+    /// Returns the transform to apply to songs when they're loaded.
+    pub fn transform(&self) -> SongTransform {
+        SongTransform {
+            transpose: self.transpose,
+            tempo_scale: self.tempo_scale,
+            lowest: self.lowest_note,
+            highest: self.highest_note,
+        }
+    }
+}
+
+/// Settings for shutting down when the service is stopped.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Longest time to wait for the hardware to get into a safe state after SIGTERM/SIGINT,
+    /// in seconds. After this, the program exits anyway.
+    pub deadline_secs: f64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { deadline_secs: 5.0 }
+    }
+}
+
+/// Settings for files the dispenser keeps its state in.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistConfig {
+    /// File the active order and its progress are saved to, so it can be resumed after a crash or power loss.
+    pub order_file: String,
+    /// File the servo calibration from the admin screen is saved to.
+    pub calibration_file: String,
+    /// File that keeps track of how many items are left in each slot.
+    pub inventory_file: String,
+    /// File every finished order is added to, one line each.
+    pub history_file: String,
+    /// File the presets saved from the admin screen are kept in.
+    pub presets_file: String,
+    /// File the unspent credit is saved to, so coins paid in aren't lost if the app stops.
+    pub credit_file: String,
+    /// File the IDs of scanned order codes are kept in, so a code can't be used again after a restart.
+    pub used_codes_file: String,
+}
+
+impl Default for PersistConfig {
+    fn default() -> Self {
+        Self {
+            order_file: "dispenser-order.toml".to_owned(),
+            calibration_file: "dispenser-calibration.toml".to_owned(),
+            inventory_file: "dispenser-inventory.toml".to_owned(),
+            history_file: "dispenser-history.csv".to_owned(),
+            presets_file: "dispenser-presets.toml".to_owned(),
+            credit_file: "dispenser-credit.toml".to_owned(),
+            used_codes_file: "dispenser-used-codes.toml".to_owned(),
+        }
+    }
+}
+
+/// Settings for the emergency stop input.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EstopConfig {
+    /// GPIO pin the emergency stop switch is wired to. If this isn't set, there is no emergency stop.
+    pub pin: Option<u8>,
+    /// True if the input is active when high, false if it's active when pulled low.
+    pub active_high: bool,
+    /// How long the input has to stay at a level before it counts, in milliseconds.
+    pub debounce_ms: u64,
+}
+
+impl Default for EstopConfig {
+    fn default() -> Self {
+        Self { pin: None, active_high: false, debounce_ms: 20 }
+    }
+}
+
+/// Settings for physical buttons and a rotary encoder, for enclosures without a touchscreen.
+/// Each input is optional; only the ones with a pin set are used.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanelConfig {
+    /// Buttons that add or remove one item from each slot. Holding them repeats.
+    pub red_plus: Option<u8>,
+    pub red_minus: Option<u8>,
+    pub green_plus: Option<u8>,
+    pub green_minus: Option<u8>,
+    /// Buttons that do the same thing as START, PAUSE/RESUME and CANCEL on the screen.
+    pub start: Option<u8>,
+    pub pause: Option<u8>,
+    pub cancel: Option<u8>,
+    /// Pins for a rotary encoder, which changes the count of the selected slot.
+    pub encoder_a: Option<u8>,
+    pub encoder_b: Option<u8>,
+    /// Push switch on the encoder. A short press selects the next slot, and a long press starts the order.
+    pub encoder_switch: Option<u8>,
+    /// Buttons that fill in the counters from a preset: the first pin is for the first preset, and so on.
+    /// Presets from the config file come first, then the ones saved on the admin screen.
+    pub presets: Vec<u8>,
+    /// True if the buttons are active when high, false if they're active when pulled low.
+    pub active_high: bool,
+    /// How long a button has to stay at a level before it counts, in milliseconds.
+    pub debounce_ms: u64,
+    /// How long a button has to be held to count as a long press, in milliseconds.
+    pub long_press_ms: u64,
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        Self {
+            red_plus: None,
+            red_minus: None,
+            green_plus: None,
+            green_minus: None,
+            start: None,
+            pause: None,
+            cancel: None,
+            encoder_a: None,
+            encoder_b: None,
+            encoder_switch: None,
+            presets: Vec::new(),
+            active_high: false,
+            debounce_ms: 20,
+            long_press_ms: 600,
+        }
+    }
+}
+
+/// Settings for the status lights.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedsConfig {
+    /// GPIO pins with plain LEDs on them.
+    pub pins: Vec<u8>,
+    /// Number of LEDs on a WS2812 strip connected to SPI0 (data on GPIO 10). 0 if there's no strip.
+    pub strip_length: usize,
+    /// Overall brightness, from 0 to 1.
+    pub brightness: f64,
+    /// Pattern for each state of the dispenser.
+    pub idle: Pattern,
+    pub dispensing: Pattern,
+    pub paused: Pattern,
+    pub fault: Pattern,
+    /// Colour for each state. While dispensing, the colour of the slot being dispensed is used instead.
+    pub idle_colour: Rgb,
+    pub paused_colour: Rgb,
+    pub fault_colour: Rgb,
+    /// Colour for each slot.
+    pub red_colour: Rgb,
+    pub green_colour: Rgb,
+}
+
+impl Default for LedsConfig {
+    fn default() -> Self {
+        Self {
+            pins: Vec::new(),
+            strip_length: 0,
+            brightness: 0.5,
+            idle: Pattern::Breathe,
+            dispensing: Pattern::Chase,
+            paused: Pattern::Solid,
+            fault: Pattern::Blink,
+            idle_colour: Rgb(0x40, 0x80, 0xff),
+            paused_colour: Rgb(0xff, 0xa0, 0x00),
+            fault_colour: Rgb(0xff, 0x00, 0x00),
+            red_colour: Rgb(0xff, 0x00, 0x00),
+            green_colour: Rgb(0x00, 0xff, 0x00),
+        }
+    }
+}
+
+/// Settings for a small text display on the front panel.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Kind of display: `"hd44780"` or `"ssd1306"`. If this isn't set, there is no display.
+    pub kind: Option<DisplayKind>,
+    /// I2C bus the display is on. Bus 1 is on pins 2 and 3.
+    pub bus: u8,
+    /// I2C address of the display, if it isn't the usual one for its kind.
+    pub address: Option<u16>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self { kind: None, bus: 1, address: None }
+    }
+}
+
+/// Settings for taking payment with a coin acceptor. Prices and credit are counted in cents.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaymentConfig {
+    /// GPIO pin the coin acceptor's pulse output is wired to.
+    pub pin: Option<u8>,
+    /// True if the pulses are high, false if they pull the pin low.
+    pub active_high: bool,
+    /// How long the input has to stay at a level before it counts, in milliseconds.
+    /// This has to be shorter than the acceptor's pulses.
+    pub debounce_ms: u64,
+    /// Credit added for each pulse.
+    pub pulse_value: u64,
+    /// Price of one item from each slot. If both are 0, the dispenser is free and credit isn't shown.
+    pub red_price: u64,
+    pub green_price: u64,
+    /// Symbol shown with amounts of money. Where it goes depends on the language.
+    pub currency: String,
+    /// Adds a button to the admin screen that simulates a pulse, for testing without a coin acceptor.
+    pub simulate: bool,
+}
+
+impl Default for PaymentConfig {
+    fn default() -> Self {
+        Self {
+            pin: None,
+            active_high: false,
+            debounce_ms: 5,
+            pulse_value: 5,
+            red_price: 0,
+            green_price: 0,
+            currency: "$".to_owned(),
+            simulate: false,
+        }
+    }
+}
+
+impl PaymentConfig {
+    /// Returns true if orders have to be paid for.
+    pub fn enabled(&self) -> bool {
+        self.red_price > 0 || self.green_price > 0
+    }
+
+    /// Returns the price of an order.
+    pub fn order_cost(&self, red: u64, green: u64) -> u64 {
+        red.saturating_mul(self.red_price).saturating_add(green.saturating_mul(self.green_price))
+    }
+}
+
+/// Where scanned codes come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScannerSource {
+    /// The scanner types into the window like a keyboard. Codes are only picked up while no text box is focused.
+    Keyboard,
+    /// The scanner's input device is read directly.
+    Evdev,
+}
+
+/// Settings for a barcode/QR code scanner, for orders made in advance.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScannerConfig {
+    /// Where scanned codes come from. If this isn't set, scanning is off.
+    pub source: Option<ScannerSource>,
+    /// Input device of the scanner, when reading it directly. Paths in `/dev/input/by-id` don't change between boots.
+    pub device: String,
+    /// Key that order codes must be signed with. If this isn't set, signatures aren't checked. Signed codes also
+    /// need an ID, so each one can only be used once, and preset codes are refused since they can't be signed.
+    pub key: Option<String>,
+}
+
+/// Kinds of badge reader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BadgeReaderKind {
+    /// RC522 module on SPI0.
+    Rc522,
+    /// No reader; badges are tapped from the admin screen, for testing.
+    Simulated,
+}
+
+/// Settings for RFID badges, which limit how much each person can take per day.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BadgesConfig {
+    /// Badge reader. If this isn't set, anyone can order without a badge.
+    pub reader: Option<BadgeReaderKind>,
+    /// SPI chip select the RC522's SDA pin is connected to (0 or 1).
+    pub chip_select: u8,
+    /// Most items each person can take per day, from all slots together. If this isn't set, there's no limit.
+    pub daily_limit: Option<u64>,
+    /// Most items each person can take per day from each slot.
+    pub red_daily_limit: Option<u64>,
+    pub green_daily_limit: Option<u64>,
+    /// File that keeps track of how much everyone has taken today.
+    pub usage_file: String,
+    /// Time without any input after which whoever tapped their badge is logged out, in seconds.
+    /// 0 keeps them logged in until they order or log out.
+    pub idle_logout_secs: u64,
+    /// Who each badge belongs to, by UID, e.g. `"04A1B2C3" = "Alice"`. Other badges can't order.
+    pub users: BTreeMap<String, String>,
+}
+
+impl Default for BadgesConfig {
+    fn default() -> Self {
+        Self {
+            reader: None,
+            chip_select: 0,
+            daily_limit: None,
+            red_daily_limit: None,
+            green_daily_limit: None,
+            usage_file: "dispenser-usage.toml".to_owned(),
+            idle_logout_secs: 30,
+            users: BTreeMap::new(),
+        }
+    }
+}
+
+/// Settings for the admin screen.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// PIN that unlocks the admin screen. Change this from the default!
+    pub pin: String,
+    /// Time without any input after which the admin screen goes back to the order screen, in seconds.
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self { pin: "0000".to_owned(), idle_timeout_secs: 60 }
+    }
+}
+
+/// Settings for keeping track of what's left in each slot.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InventoryConfig {
+    /// Number of items each slot holds when full. Slots without a capacity aren't tracked,
+    /// so orders are never refused for running out.
+    pub red_capacity: Option<u64>,
+    pub green_capacity: Option<u64>,
+}
+
+impl InventoryConfig {
+    /// Returns the capacity of each slot.
+    pub fn capacities(&self) -> [Option<u64>; 2] {
+        [self.red_capacity, self.green_capacity]
+    }
+}
+
+/// Settings for "surprise me" orders, where the slots are picked randomly.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurpriseConfig {
+    /// How likely each slot is to be picked, compared to the other. A slot with a weight of 0 is never picked.
+    #[serde(deserialize_with = "non_negative")]
+    pub red_weight: f64,
+    #[serde(deserialize_with = "non_negative")]
+    pub green_weight: f64,
+    /// Seed for the random numbers, so the same orders come out every time. Random on every start if not set.
+    pub seed: Option<u64>,
+    /// Most items a surprise order can have.
+    pub max_items: u64,
+}
+
+impl Default for SurpriseConfig {
+    fn default() -> Self {
+        Self { red_weight: 1.0, green_weight: 1.0, seed: None, max_items: 10 }
+    }
+}
+
+impl SurpriseConfig {
+    /// Returns the weight of each slot.
+    pub fn weights(&self) -> [f64; 2] {
+        [self.red_weight, self.green_weight]
+    }
+}
+
+/// Settings for attract mode, shown when nobody has used the dispenser for a while.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttractConfig {
+    /// Time without any input after which attract mode starts, in seconds. 0 turns it off.
+    pub idle_secs: u64,
+    /// Song played every so often in attract mode to get people's attention, either the name of a
+    /// built-in song or a song file. Attract mode is quiet if this isn't set.
+    pub tune: Option<String>,
+    /// Time between plays of the attention tune, in seconds.
+    pub tune_interval_secs: u64,
+}
+
+impl Default for AttractConfig {
+    fn default() -> Self {
+        Self { idle_secs: 120, tune: None, tune_interval_secs: 300 }
+    }
+}
+
+/// Limits on how big an order can be.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Most items that can be ordered from each slot.
+    pub red_max: u64,
+    pub green_max: u64,
+    /// Most items an order can have in total.
+    pub order_max: u64,
+    /// Fewest items an order can have in total.
+    pub min_total: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self { red_max: 20, green_max: 20, order_max: 30, min_total: 1 }
+    }
+}
+
+/// Ways an order can break the limits. Each one has the limit that was broken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    SlotMax(u64),
+    OrderMax(u64),
+    OrderMin(u64),
+}
+
+impl LimitsConfig {
+    /// Returns what's wrong with the count for a slot (0 is red, 1 is green), if anything.
+    pub fn check_slot(&self, slot: usize, count: u64) -> Option<LimitError> {
+        let max = if slot == 0 { self.red_max } else { self.green_max };
+        (count > max).then_some(LimitError::SlotMax(max))
+    }
+
+    /// Returns what's wrong with the order as a whole, if anything. The slots are checked separately.
+    pub fn check_order(&self, red: u64, green: u64) -> Option<LimitError> {
+        let total = red.saturating_add(green);
+        if total > self.order_max {
+            Some(LimitError::OrderMax(self.order_max))
+        } else if total < self.min_total {
+            Some(LimitError::OrderMin(self.min_total))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if an order is within all the limits.
+    pub fn allows(&self, red: u64, green: u64) -> bool {
+        self.check_slot(0, red).is_none() && self.check_slot(1, green).is_none() && self.check_order(red, green).is_none()
+    }
+}
+
+/// A named order that can be filled in with one tap.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub name: String,
+    /// Number of red and green items. A missing slot counts as 0.
+    #[serde(default)]
+    pub red: u64,
+    #[serde(default)]
+    pub green: u64,
+}
+
+/// Deserializes a note name (like `"C4"`) into a MIDI note.
+fn note_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    try_note2midi(&name).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Deserializes a number that has to be positive, like a factor to multiply something by.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::custom("must be a positive number"))
+    }
+}
+
+/// Deserializes a number that can't be negative, like a weight.
+fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if value.is_finite() && value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::custom("must be a number that isn't negative"))
+    }
+}
+
+/// Error while loading the config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file exists, but couldn't be read.
+    Io(String, io::Error),
+    /// The file isn't valid TOML, or has invalid settings.
+    Toml(String, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read config file {}: {}", path, err),
+            ConfigError::Toml(path, err) => write!(f, "invalid config file {}: {}", path, err),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Config {
+    /// Loads the config file. If the file doesn't exist, the default settings are used.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = env::var("DISPENSER_CONFIG").unwrap_or_else(|_| DEFAULT_PATH.to_owned());
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(ConfigError::Io(path, err)),
+        };
+        toml::from_str(&text).map_err(|err| ConfigError::Toml(path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_surprise_weights() {
+        let config: Config = toml::from_str("[surprise]\nred_weight = 0\ngreen_weight = 2.5\n").unwrap();
+        assert_eq!(config.surprise.weights(), [0.0, 2.5]);
+        for weight in ["-1", "inf", "nan"] {
+            let text = format!("[surprise]\nred_weight = {}\n", weight);
+            assert!(toml::from_str::<Config>(&text).is_err(), "accepted {}", weight);
+        }
+    }
+
+    #[test]
+    fn checks_slot_limits() {
+        let limits = LimitsConfig { red_max: 5, green_max: 3, ..Default::default() };
+        assert_eq!(limits.check_slot(0, 0), None);
+        assert_eq!(limits.check_slot(0, 5), None);
+        assert_eq!(limits.check_slot(0, 6), Some(LimitError::SlotMax(5)));
+        assert_eq!(limits.check_slot(1, 3), None);
+        assert_eq!(limits.check_slot(1, 4), Some(LimitError::SlotMax(3)));
+        assert_eq!(limits.check_slot(1, u64::MAX), Some(LimitError::SlotMax(3)));
+    }
+
+    #[test]
+    fn checks_order_limits() {
+        let limits = LimitsConfig { order_max: 10, min_total: 2, ..Default::default() };
+        assert_eq!(limits.check_order(0, 0), Some(LimitError::OrderMin(2)));
+        assert_eq!(limits.check_order(1, 0), Some(LimitError::OrderMin(2)));
+        assert_eq!(limits.check_order(1, 1), None);
+        assert_eq!(limits.check_order(4, 6), None);
+        assert_eq!(limits.check_order(5, 6), Some(LimitError::OrderMax(10)));
+        // a total that would overflow is still too big
+        assert_eq!(limits.check_order(u64::MAX, 1), Some(LimitError::OrderMax(10)));
+    }
+
+    #[test]
+    fn allows_orders_within_every_limit() {
+        let limits = LimitsConfig { red_max: 5, green_max: 5, order_max: 8, min_total: 1 };
+        assert!(limits.allows(5, 3));
+        assert!(limits.allows(0, 1));
+        assert!(!limits.allows(0, 0));
+        assert!(!limits.allows(6, 0));
+        assert!(!limits.allows(0, 6));
+        assert!(!limits.allows(5, 4));
+    }
+
+    #[test]
+    fn handles_zero_limits() {
+        // a slot with a max of 0 is turned off, but the other one still works
+        let limits = LimitsConfig { red_max: 0, ..Default::default() };
+        assert_eq!(limits.check_slot(0, 1), Some(LimitError::SlotMax(0)));
+        assert!(limits.allows(0, 1));
+        assert!(!limits.allows(1, 0));
+        // with no minimum, an empty order is fine
+        let limits = LimitsConfig { min_total: 0, ..Default::default() };
+        assert!(limits.allows(0, 0));
+        // and a maximum of 0 only allows an empty order
+        let limits = LimitsConfig { order_max: 0, min_total: 0, ..Default::default() };
+        assert!(limits.allows(0, 0));
+        assert_eq!(limits.check_order(0, 1), Some(LimitError::OrderMax(0)));
+    }
+
+    #[test]
+    fn has_sensible_default_limits() {
+        let limits = LimitsConfig::default();
+        assert!(limits.allows(20, 10));
+        assert!(!limits.allows(21, 0));
+        assert!(!limits.allows(20, 11));
+        assert!(!limits.allows(0, 0));
+    }
+}
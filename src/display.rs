@@ -0,0 +1,352 @@
+/*
+display.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Small text displays for the front of the dispenser, for builds without a screen.
+//!
+//! Two kinds of displays are supported, both on the I2C bus: HD44780 character LCDs (with a PCF8574 backpack),
+//! and SSD1306 128x64 OLEDs, which show text with a built-in 5x7 font.
+
+use std::{thread, time::Duration};
+
+use rppal::i2c::I2c;
+use serde::Deserialize;
+
+use crate::error::Result;
+
+/// A display that shows a few lines of text.
+pub trait FrontPanel {
+    /// Size of the display in characters, as (columns, rows).
+    fn size(&self) -> (usize, usize);
+    /// Shows lines of text. Lines that are too long are cut off, and missing lines are left blank.
+    fn show(&mut self, lines: &[String]) -> Result<()>;
+}
+
+/// Kinds of display that can be connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayKind {
+    /// HD44780 16x2 character LCD with a PCF8574 I2C backpack.
+    Hd44780,
+    /// SSD1306 128x64 OLED.
+    Ssd1306,
+}
+
+/// Pads or cuts lines to exactly fit a display, so every character on it gets overwritten.
+/// Characters that the displays can't show are replaced with `?`.
+pub fn fit_lines(lines: &[String], (cols, rows): (usize, usize)) -> Vec<String> {
+    (0..rows)
+        .map(|row| {
+            let line = lines.get(row).map(String::as_str).unwrap_or("");
+            let fitted: String = line
+                .chars()
+                .map(|c| if c == ' ' || c.is_ascii_graphic() { c } else { '?' })
+                .take(cols)
+                .collect();
+            format!("{:<1$}", fitted, cols)
+        })
+        .collect()
+}
+
+/// A display that only exists in memory. The display thread draws to one of these first, so the real display
+/// is only updated when something changes; it can also stand in for a real display when testing.
+pub struct MemoryPanel {
+    size: (usize, usize),
+    lines: Vec<String>,
+}
+
+impl MemoryPanel {
+    /// Creates a blank display of the given size, in characters.
+    pub fn new(cols: usize, rows: usize) -> MemoryPanel {
+        Self { size: (cols, rows), lines: fit_lines(&[], (cols, rows)) }
+    }
+
+    /// Returns what's on the display, one string per row.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+}
+
+impl FrontPanel for MemoryPanel {
+    fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    fn show(&mut self, lines: &[String]) -> Result<()> {
+        self.lines = fit_lines(lines, self.size);
+        Ok(())
+    }
+}
+
+/// An HD44780 16x2 character LCD, through a PCF8574 I2C backpack.
+///
+/// The backpack turns each byte written to it into levels on 8 pins: RS, RW, E and the backlight on the
+/// low 4 bits, and the LCD's D4-D7 on the high 4 bits. The LCD is run in 4-bit mode, so each byte for it
+/// is sent as two halves, each latched by pulsing E.
+pub struct Hd44780 {
+    i2c: I2c,
+}
+
+impl Hd44780 {
+    /// Address most PCF8574 backpacks use.
+    pub const DEFAULT_ADDRESS: u16 = 0x27;
+    const COLS: usize = 16;
+    const ROWS: usize = 2;
+
+    const RS: u8 = 0x01;
+    const ENABLE: u8 = 0x04;
+    const BACKLIGHT: u8 = 0x08;
+
+    /// Sets up the LCD on an I2C bus at the given address.
+    pub fn new(bus: u8, address: u16) -> Result<Hd44780> {
+        let mut i2c = I2c::with_bus(bus)?;
+        i2c.set_slave_address(address)?;
+        let mut lcd = Self { i2c };
+
+        // the LCD could be in 8-bit or 4-bit mode, so it's put in 8-bit mode 3 times, then switched to 4-bit
+        thread::sleep(Duration::from_millis(50));
+        for delay in [4500, 4500, 150] {
+            lcd.write_nibble(0x30, 0)?;
+            thread::sleep(Duration::from_micros(delay));
+        }
+        lcd.write_nibble(0x20, 0)?;
+        // 2 lines with a 5x8 font; display on without a cursor; clear; text goes left to right
+        lcd.command(0x28)?;
+        lcd.command(0x0c)?;
+        lcd.command(0x01)?;
+        thread::sleep(Duration::from_millis(2));
+        lcd.command(0x06)?;
+        Ok(lcd)
+    }
+
+    /// Sends the high 4 bits of `data` to the LCD.
+    fn write_nibble(&mut self, data: u8, flags: u8) -> Result<()> {
+        let byte = (data & 0xf0) | flags | Self::BACKLIGHT;
+        // each I2C write takes ~100us, which is plenty of time for the LCD to see E go high and low
+        self.i2c.write(&[byte | Self::ENABLE])?;
+        self.i2c.write(&[byte])?;
+        Ok(())
+    }
+
+    fn write_byte(&mut self, data: u8, flags: u8) -> Result<()> {
+        self.write_nibble(data, flags)?;
+        self.write_nibble(data << 4, flags)
+    }
+
+    fn command(&mut self, command: u8) -> Result<()> {
+        self.write_byte(command, 0)
+    }
+}
+
+impl FrontPanel for Hd44780 {
+    fn size(&self) -> (usize, usize) {
+        (Self::COLS, Self::ROWS)
+    }
+
+    fn show(&mut self, lines: &[String]) -> Result<()> {
+        // the second row starts at address 0x40 in the LCD's memory
+        for (row, line) in fit_lines(lines, self.size()).iter().enumerate() {
+            self.command(0x80 | (row as u8 * 0x40))?;
+            for byte in line.bytes() {
+                self.write_byte(byte, Self::RS)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An SSD1306 128x64 OLED on the I2C bus.
+///
+/// The OLED's memory is split into 8 "pages" of 8 pixel rows each; every byte is one column of a page, with the
+/// lowest bit at the top. With a 5x7 font plus a column of space, that fits 21 characters on each of 8 lines.
+pub struct Ssd1306 {
+    i2c: I2c,
+    buffer: Vec<u8>,
+}
+
+impl Ssd1306 {
+    /// Address most SSD1306 modules use.
+    pub const DEFAULT_ADDRESS: u16 = 0x3c;
+    const WIDTH: usize = 128;
+    const PAGES: usize = 8;
+    const CHAR_WIDTH: usize = 6;
+
+    // The first byte of each I2C write says whether the rest are commands or pixel data.
+    const COMMAND: u8 = 0x00;
+    const DATA: u8 = 0x40;
+
+    /// Sets up the OLED on an I2C bus at the given address.
+    pub fn new(bus: u8, address: u16) -> Result<Ssd1306> {
+        let mut i2c = I2c::with_bus(bus)?;
+        i2c.set_slave_address(address)?;
+        let mut oled = Self { i2c, buffer: Vec::with_capacity(Self::WIDTH + 1) };
+        oled.commands(&[
+            0xae, // display off while setting up
+            0xd5, 0x80, // default clock
+            0xa8, 0x3f, // 64 rows
+            0xd3, 0x00, // no vertical offset
+            0x40, // start at row 0
+            0x8d, 0x14, // turn on the charge pump
+            0x20, 0x00, // horizontal addressing: data wraps from the end of one page to the next
+            0xa1, 0xc8, // flip both ways, so (0, 0) is the top left when the pins are at the top
+            0xda, 0x12, // COM pin layout for 128x64 modules
+            0x81, 0xcf, // contrast
+            0xd9, 0xf1, // precharge period
+            0xdb, 0x40, // VCOMH level
+            0xa4, // show the memory contents
+            0xa6, // not inverted
+            0xaf, // display on
+        ])?;
+        Ok(oled)
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> Result<()> {
+        for &command in commands {
+            self.i2c.write(&[Self::COMMAND, command])?;
+        }
+        Ok(())
+    }
+}
+
+impl FrontPanel for Ssd1306 {
+    fn size(&self) -> (usize, usize) {
+        (Self::WIDTH / Self::CHAR_WIDTH, Self::PAGES)
+    }
+
+    fn show(&mut self, lines: &[String]) -> Result<()> {
+        // draw over the whole screen, one page (line of text) at a time
+        self.commands(&[0x21, 0, Self::WIDTH as u8 - 1, 0x22, 0, Self::PAGES as u8 - 1])?;
+        for line in fit_lines(lines, self.size()) {
+            self.buffer.clear();
+            self.buffer.push(Self::DATA);
+            for c in line.chars() {
+                self.buffer.extend_from_slice(&glyph(c));
+                self.buffer.push(0);
+            }
+            self.buffer.resize(Self::WIDTH + 1, 0);
+            self.i2c.write(&self.buffer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the 5 columns of pixels for a character. Lowercase letters are shown as uppercase, and characters
+/// that aren't in the font as `?`.
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    match c {
+        ' '..='_' => FONT[c as usize - ' ' as usize],
+        _ => FONT['?' as usize - ' ' as usize],
+    }
+}
+
+/// 5x7 font for ASCII characters from space to underscore. Each byte is a column, with the lowest bit at the top.
+const FONT: [[u8; 5]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x14, 0x08, 0x3e, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x01, 0x01], // F
+    [0x3e, 0x41, 0x41, 0x51, 0x32], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x04, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x7f, 0x20, 0x18, 0x20, 0x7f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // backslash
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn starts_blank() {
+        let panel = MemoryPanel::new(4, 2);
+        assert_eq!(panel.size(), (4, 2));
+        assert_eq!(panel.lines(), lines(&["    ", "    "]));
+    }
+
+    #[test]
+    fn clips_columns_and_rows() {
+        let mut panel = MemoryPanel::new(4, 2);
+        panel.show(&lines(&["abcdef", "gh", "ijkl"])).unwrap();
+        assert_eq!(panel.lines(), lines(&["abcd", "gh  "]));
+    }
+
+    #[test]
+    fn blanks_missing_rows() {
+        let mut panel = MemoryPanel::new(3, 3);
+        panel.show(&lines(&["abc", "def", "ghi"])).unwrap();
+        panel.show(&lines(&["x"])).unwrap();
+        assert_eq!(panel.lines(), lines(&["x  ", "   ", "   "]));
+        panel.show(&[]).unwrap();
+        assert_eq!(panel.lines(), lines(&["   ", "   ", "   "]));
+    }
+
+    #[test]
+    fn replaces_characters_displays_cant_show() {
+        let mut panel = MemoryPanel::new(6, 1);
+        panel.show(&lines(&["é\tb ✓x"])).unwrap();
+        assert_eq!(panel.lines(), lines(&["??b ?x"]));
+    }
+}
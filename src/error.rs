@@ -0,0 +1,92 @@
+/*
+error.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! The error type used by all the hardware code in this crate.
+
+use std::{error, fmt};
+
+/// Anything that can go wrong while talking to the hardware.
+#[derive(Debug)]
+pub enum Error {
+    /// The GPIO interface returned an error.
+    Gpio(rppal::gpio::Error),
+    /// The PWM interface returned an error.
+    Pwm(rppal::pwm::Error),
+    /// The I2C interface returned an error.
+    I2c(rppal::i2c::Error),
+    /// The SPI interface returned an error.
+    Spi(rppal::spi::Error),
+    /// A pin that doesn't support hardware PWM was used for it. Only pins 18 and 19 do.
+    NotPwmPin(u8),
+    /// A servo was asked to move outside of its range (0 to 1).
+    ServoPosition(f32),
+    /// An LED strip has more LEDs than can be sent in one SPI transfer (the most it can have is given).
+    StripTooLong(usize, usize),
+    /// The emergency stop was pressed, so the hardware was stopped on purpose.
+    EmergencyStop,
+}
+
+/// Shorthand for results using this crate's error type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Gpio(rppal::gpio::Error::PermissionDenied(msg)) => write!(
+                f,
+                "GPIO permission denied. You may need to add yourself to the GPIO group. msg: {}",
+                msg
+            ),
+            Error::Gpio(err) => write!(f, "GPIO error: {}", err),
+            Error::Pwm(err) => write!(f, "PWM error: {}", err),
+            Error::I2c(err) => write!(f, "I2C error: {}", err),
+            Error::Spi(err) => write!(f, "SPI error: {}", err),
+            Error::NotPwmPin(pin) => write!(f, "pin {} cannot be configured for PWM", pin),
+            Error::ServoPosition(pos) => write!(f, "servo position {} is outside the range 0-1", pos),
+            Error::StripTooLong(len, max) => {
+                write!(f, "LED strip of {} LEDs is too long; SPI can only send {} at once", len, max)
+            }
+            Error::EmergencyStop => write!(f, "emergency stop activated"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Gpio(err) => Some(err),
+            Error::Pwm(err) => Some(err),
+            Error::I2c(err) => Some(err),
+            Error::Spi(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rppal::gpio::Error> for Error {
+    fn from(err: rppal::gpio::Error) -> Self {
+        Error::Gpio(err)
+    }
+}
+
+impl From<rppal::pwm::Error> for Error {
+    fn from(err: rppal::pwm::Error) -> Self {
+        Error::Pwm(err)
+    }
+}
+
+impl From<rppal::i2c::Error> for Error {
+    fn from(err: rppal::i2c::Error) -> Self {
+        Error::I2c(err)
+    }
+}
+
+impl From<rppal::spi::Error> for Error {
+    fn from(err: rppal::spi::Error) -> Self {
+        Error::Spi(err)
+    }
+}
@@ -7,23 +7,22 @@ Date: Jun. 17, 2024
 
 //! Classes that deal directly with the GPIO interface.
 
-use rppal::{gpio::{Error, Gpio, OutputPin}, pwm::{self, Pwm}};
-use std::{sync::OnceLock, time::Duration};
+use rppal::gpio::{Gpio, InputPin, OutputPin};
+use std::{sync::OnceLock, time::{Duration, Instant}};
 
-type GpioError = rppal::gpio::Error;
+use crate::error::{Error, Result};
 
 // In Rust, static variables cannot be directly modified. To make the internal value initializable exactly once, we use OnceLock.
 static INSTANCE: OnceLock<Gpio> = OnceLock::new();
 
 /// Obtains a base object needed to create other GPIO objects. This is a singleton used during the creation of all GPIO objects.
-fn instance() -> &'static Gpio {
-    INSTANCE.get_or_init(|| {
-        match Gpio::new() {
-            Ok(gpio) => gpio,
-            Err(Error::PermissionDenied(msg)) => panic!("GPIO permission denied. You may need to add yourself to the GPIO group. msg:\n{}", msg),
-            Err(err) => panic!("GPIO init error!\nmsg: {}", err)
-        }
-    })
+/// If the GPIO interface can't be opened (e.g. due to permissions), this returns an error, and the next call tries again.
+pub(crate) fn instance() -> Result<&'static Gpio> {
+    if let Some(gpio) = INSTANCE.get() {
+        return Ok(gpio);
+    }
+    let gpio = Gpio::new()?;
+    Ok(INSTANCE.get_or_init(|| gpio))
 }
 
 /// Represents a Tower Pro SG90 Micro Servo on a GPIO pin. Uses software PWM to implement position, because
@@ -38,25 +37,211 @@ impl ServoSg90 {
 
     /// Converts a position from 0-1 to a pulse width. SG90 servos require a 1-2ms pulse (defined on datasheet); 
     /// this does restrict their range to 90 degrees.
-    fn pulse_width(pos: f32) -> Duration {
-        assert!(0.0 <= pos && pos <= 1.0);
-        Duration::from_micros(((pos + 1.0) * 1.0e3) as u64)
+    fn pulse_width(pos: f32) -> Result<Duration> {
+        if !(0.0..=1.0).contains(&pos) {
+            return Err(Error::ServoPosition(pos));
+        }
+        Ok(Duration::from_micros(((pos + 1.0) * 1.0e3) as u64))
     }
 
     /// Constructs a new servo, given a pin and initial position.
     /// The initial position must range from 0 to 1, if it is outside this range, this
-    /// function panics.
-    pub fn new(pin: u8, initial_pos: f32) -> Result<ServoSg90, GpioError> {
-        let mut  pin = instance().get(pin)?.into_output_low();
-        pin.set_pwm(Self::PWM_PERIOD, Self::pulse_width(initial_pos))?;
+    /// function returns an error.
+    pub fn new(pin: u8, initial_pos: f32) -> Result<ServoSg90> {
+        let mut  pin = instance()?.get(pin)?.into_output_low();
+        pin.set_pwm(Self::PWM_PERIOD, Self::pulse_width(initial_pos)?)?;
 
         Ok(Self { pin })
     }
 
     /// Moves the servo to the specified position. The position must be between 0 and 1.
-    /// If it isn't, the function panics.
-    pub fn set_pos(&mut self, pos: f32) {
-        self.pin.set_pwm(Self::PWM_PERIOD, Self::pulse_width(pos)).unwrap();
+    /// If it isn't, the function returns an error.
+    pub fn set_pos(&mut self, pos: f32) -> Result<()> {
+        self.pin.set_pwm(Self::PWM_PERIOD, Self::pulse_width(pos)?)?;
+        Ok(())
+    }
+}
+
+/// Software debouncing for a digital input. The input has to stay at a new level for the whole debounce time
+/// before the change is accepted, so contact bounce and short glitches are ignored.
+///
+/// This only works on levels and times, so it can be tested without a pin.
+pub struct Debouncer {
+    debounce: Duration,
+    active: bool,
+    // when the raw input first started disagreeing with `active`
+    changing_since: Option<Instant>,
+}
+
+impl Debouncer {
+    /// Creates a debouncer that starts out settled at `active`.
+    pub fn new(active: bool, debounce: Duration) -> Debouncer {
+        Self { debounce, active, changing_since: None }
+    }
+
+    /// Takes a raw sample of the input at time `now`, and returns the debounced state.
+    pub fn update(&mut self, raw: bool, now: Instant) -> bool {
+        if raw == self.active {
+            self.changing_since = None;
+        } else {
+            let since = *self.changing_since.get_or_insert(now);
+            if now.saturating_duration_since(since) >= self.debounce {
+                self.active = raw;
+                self.changing_since = None;
+            }
+        }
+        self.active
+    }
+}
+
+/// A digital input, like a button or switch, with software debouncing (see [`Debouncer`]).
+pub struct DebouncedInput {
+    pin: InputPin,
+    active_high: bool,
+    debouncer: Debouncer,
+}
+
+impl DebouncedInput {
+    /// Sets up an input on a pin. If `active_high` is false, the input is active when pulled low (e.g. a button
+    /// to ground). The pin is pulled towards its inactive level.
+    pub fn new(pin: u8, active_high: bool, debounce: Duration) -> Result<DebouncedInput> {
+        let pin = instance()?.get(pin)?;
+        let pin = if active_high { pin.into_input_pulldown() } else { pin.into_input_pullup() };
+        // whatever the input is at startup counts as settled
+        let active = pin.is_high() == active_high;
+        Ok(Self { pin, active_high, debouncer: Debouncer::new(active, debounce) })
+    }
+
+    /// Samples the input and returns its debounced state. This needs to be called every few milliseconds.
+    pub fn poll(&mut self) -> bool {
+        let raw = self.pin.is_high() == self.active_high;
+        self.debouncer.update(raw, Instant::now())
+    }
+}
+
+/// Decodes the two outputs of a quadrature rotary encoder into detents.
+///
+/// This only works on levels, so it can be tested without an encoder.
+pub struct QuadratureDecoder {
+    last: u8,
+    // quarter-steps moved since the last full detent
+    steps: i8,
+}
+
+impl QuadratureDecoder {
+    // Movement for each (previous state, current state) pair, where a state is `(a << 1) | b`.
+    // Invalid transitions (both pins changing at once, usually from bouncing) count as no movement.
+    const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+    // Most encoders go through all 4 states between detents.
+    const STEPS_PER_DETENT: i8 = 4;
+
+    /// Creates a decoder, starting from the current levels of the two outputs.
+    pub fn new(a: bool, b: bool) -> QuadratureDecoder {
+        Self { last: Self::state(a, b), steps: 0 }
+    }
+
+    fn state(a: bool, b: bool) -> u8 {
+        ((a as u8) << 1) | b as u8
+    }
+
+    /// Takes a sample of both outputs, and returns how many detents the encoder moved (positive is clockwise).
+    pub fn update(&mut self, a: bool, b: bool) -> i32 {
+        let state = Self::state(a, b);
+        self.steps += Self::TRANSITIONS[((self.last << 2) | state) as usize];
+        self.last = state;
+        if self.steps.abs() >= Self::STEPS_PER_DETENT {
+            let detents = self.steps.signum();
+            self.steps = 0;
+            detents as i32
+        } else {
+            0
+        }
     }
 }
 
+/// A quadrature rotary encoder on two pins, like the common KY-040. The pins are pulled up, so the encoder's
+/// common pin should go to ground. If it turns the wrong way, swap the pins.
+pub struct RotaryEncoder {
+    a: InputPin,
+    b: InputPin,
+    decoder: QuadratureDecoder,
+}
+
+impl RotaryEncoder {
+    /// Sets up an encoder on two pins.
+    pub fn new(pin_a: u8, pin_b: u8) -> Result<RotaryEncoder> {
+        let a = instance()?.get(pin_a)?.into_input_pullup();
+        let b = instance()?.get(pin_b)?.into_input_pullup();
+        let decoder = QuadratureDecoder::new(a.is_high(), b.is_high());
+        Ok(Self { a, b, decoder })
+    }
+
+    /// Samples the encoder and returns how many detents it moved (positive is clockwise). This needs to be called
+    /// often enough to catch every state, about once a millisecond.
+    pub fn poll(&mut self) -> i32 {
+        self.decoder.update(self.a.is_high(), self.b.is_high())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(5);
+
+    fn ms(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn ignores_bounces() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(false, DEBOUNCE);
+        // contact bounce: the input flickers without staying up for the debounce time
+        for (t, raw) in [(0, true), (1, false), (2, true), (3, false), (4, true), (8, false)] {
+            assert!(!debouncer.update(raw, ms(start, t)), "changed at {}ms", t);
+        }
+        // then settles
+        assert!(!debouncer.update(true, ms(start, 10)));
+        assert!(!debouncer.update(true, ms(start, 14)));
+        assert!(debouncer.update(true, ms(start, 15)));
+        // and the same going the other way
+        assert!(debouncer.update(false, ms(start, 16)));
+        assert!(debouncer.update(true, ms(start, 17)));
+        assert!(debouncer.update(false, ms(start, 18)));
+        assert!(!debouncer.update(false, ms(start, 23)));
+    }
+
+    /// Feeds the decoder a sequence of (a, b) levels, returning the total detents.
+    fn turn(decoder: &mut QuadratureDecoder, states: &[(bool, bool)]) -> i32 {
+        states.iter().map(|&(a, b)| decoder.update(a, b)).sum()
+    }
+
+    // one detent clockwise, starting from (and going back to) both pins high
+    const CLOCKWISE: [(bool, bool); 4] = [(false, true), (false, false), (true, false), (true, true)];
+
+    #[test]
+    fn decodes_both_directions() {
+        let mut decoder = QuadratureDecoder::new(true, true);
+        assert_eq!(turn(&mut decoder, &CLOCKWISE), 1);
+        assert_eq!(turn(&mut decoder, &CLOCKWISE), 1);
+        let mut anticlockwise: Vec<(bool, bool)> = CLOCKWISE[..3].iter().rev().copied().collect();
+        anticlockwise.push((true, true));
+        assert_eq!(turn(&mut decoder, &anticlockwise), -1);
+        // part of a detent doesn't count until it's finished
+        assert_eq!(turn(&mut decoder, &CLOCKWISE[..2]), 0);
+        assert_eq!(turn(&mut decoder, &CLOCKWISE[2..]), 1);
+    }
+
+    #[test]
+    fn ignores_invalid_transitions() {
+        let mut decoder = QuadratureDecoder::new(true, true);
+        // both pins changing at once (11 -> 00 -> 11) isn't movement either way
+        assert_eq!(turn(&mut decoder, &[(false, false), (true, true), (false, false), (true, true)]), 0);
+        // neither is sitting still
+        assert_eq!(turn(&mut decoder, &[(true, true); 8]), 0);
+        // a bounce back and forth on one pin cancels out
+        assert_eq!(turn(&mut decoder, &[(true, false), (true, true), (true, false), (true, true)]), 0);
+        assert_eq!(turn(&mut decoder, &CLOCKWISE), 1);
+    }
+}
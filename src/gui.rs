@@ -9,26 +9,63 @@ Date: Jun. 17, 2024
 
 
 use std::{
+    collections::VecDeque,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender},
         Arc, Mutex,
     },
-    thread::{self, JoinHandle},
+    thread::{self, Thread},
+    time::{Duration, Instant},
 };
 
+use crate::{
+    config::{Config, LimitError, Preset},
+    error::Error,
+    i18n::{Language, Msg},
+    surprise::SurprisePicker,
+};
+use admin::{AdminState, Screen};
+use badges::Badges;
 use counter::{Counter, CounterState};
 use eframe::{App, NativeOptions};
-use egui::{Align, Button, CentralPanel, Layout, Vec2, ViewportBuilder};
-use gpio_thread::run_gpio_thread;
+use egui::{Align, Button, CentralPanel, Color32, Layout, TextureHandle, Vec2, ViewportBuilder};
+use gpio_thread::{recover_gpio, run_gpio_thread};
 use music_thread::run_music_thread;
+use panel::PanelAction;
+use history::{load_history, HistoryEntry};
+use persist::{load_toml, save_toml, Calibration, Inventory, OrderProgress, SavedCredit, UsedOrderIds};
+use presets::load_saved_presets;
+use service::ServiceReporter;
+use supervisor::Worker;
+use theme::Theme;
 
+mod admin;
+mod attract;
+mod badges;
+mod coin_thread;
 mod counter;
+mod display_thread;
+mod estop;
 mod gpio_thread;
+mod history;
+mod indicator_thread;
 mod music_thread;
+mod panel;
+mod persist;
+mod presets;
+mod scanner;
+mod service;
+mod shutdown;
+mod supervisor;
+mod surprise;
+mod theme;
 
 /// Shared state between the various threads in the application.
 #[derive(Default)]
 struct SharedState {
+    // Settings loaded at startup.
+    config: Config,
     // Flags that signal exit, pause and cancel.
     exit_flag: AtomicBool,
     pause_flag: AtomicBool,
@@ -37,6 +74,151 @@ struct SharedState {
     is_processing: AtomicBool,
     // The current order if one is being processed, or the next one if no order is being processed.
     next_order: Mutex<Option<(u64, u64)>>,
+    // Set along with `next_order` when the admin screen pushes out a test item. Tests aren't paid for, so they
+    // aren't recorded in the history, refunded, or saved to be resumed.
+    test_order: AtomicBool,
+    // Progress of the order being processed, updated by the GPIO thread.
+    progress: Mutex<Option<OrderProgress>>,
+    // Counts currently entered on the counters, updated by the GUI.
+    draft_order: Mutex<(u64, u64)>,
+    // Slot the GPIO thread is currently dispensing from (0 is red, 1 is green).
+    cur_slot: AtomicUsize,
+    // Credit paid into the coin acceptor that hasn't been spent yet, in cents.
+    credit: AtomicU64,
+    // Held while the credit is saved, so an older amount can't be saved over a newer one.
+    credit_save: Mutex<()>,
+    // Person whose badge the current order was charged to, if badges are turned on.
+    order_badge: Mutex<Option<String>>,
+    // Items charged to someone's badge that were never dispensed, until the GUI takes them off their usage.
+    returned_items: Mutex<Vec<(String, [u64; 2])>>,
+    // Measure of the song the music thread is currently playing.
+    cur_measure: AtomicU32,
+    // Latest hardware error reported by a worker thread. While this is set, no orders can be started.
+    fault: Mutex<Option<String>>,
+    // Flags set by the GPIO and music threads once they've set up their hardware.
+    gpio_ready: AtomicBool,
+    music_ready: AtomicBool,
+    // Set by the badge thread while the badge reader is working.
+    badge_reader_ready: AtomicBool,
+    // Handles to the running worker threads, so they can be interrupted from any thread (e.g. by the E-stop).
+    worker_threads: Mutex<Vec<Thread>>,
+    // Number of worker threads that are currently alive, kept up to date by the supervisor.
+    live_workers: AtomicUsize,
+    // Latched when the emergency stop is pressed. Only cleared from the admin screen.
+    estop: AtomicBool,
+    // True while the emergency stop input itself is active.
+    estop_pressed: AtomicBool,
+    // Set if the emergency stop input couldn't be set up. The E-stop stays latched until the app restarts.
+    estop_unavailable: AtomicBool,
+    // Servo positions and timing, adjusted from the admin screen.
+    calibration: Mutex<Calibration>,
+    // Items left in each slot, counted down by the GPIO thread.
+    inventory: Mutex<Inventory>,
+    // Most recent orders, oldest first.
+    history: Mutex<VecDeque<HistoryEntry>>,
+    // Set from the admin screen to silence the music.
+    muted: AtomicBool,
+    // Set by the GUI while attract mode is showing.
+    attract: AtomicBool,
+}
+
+impl SharedState {
+    /// Records an error from a worker thread, so the GUI can show it.
+    fn report_fault(&self, source: &str, err: &Error) {
+        // the E-stop sets its own fault message, which shouldn't be overwritten by the threads it stopped
+        if let Error::EmergencyStop = err {
+            return;
+        }
+        eprintln!("{} fault: {}", source, err);
+        *self.fault.lock().unwrap() = Some(format!("{} fault: {}", source, err));
+    }
+
+    /// Parks the current thread until the fault (and E-stop, if it was pressed) is cleared from the GUI.
+    /// Returns true if the app is exiting instead.
+    fn wait_fault_cleared(&self) -> bool {
+        loop {
+            if self.exit_flag.load(Ordering::SeqCst) {
+                return true;
+            }
+            if self.fault.lock().unwrap().is_none() && !self.estop.load(Ordering::SeqCst) {
+                return false;
+            }
+            thread::park();
+        }
+    }
+
+    /// Describes what the dispenser is currently doing, in a single line.
+    fn status_text(&self, lang: Language) -> String {
+        if self.estop_unavailable.load(Ordering::SeqCst) {
+            return lang.text(Msg::StatusEstopUnavailable).to_owned();
+        }
+        if self.estop.load(Ordering::SeqCst) {
+            return lang.text(Msg::StatusEstop).to_owned();
+        }
+        if let Some(fault) = &*self.fault.lock().unwrap() {
+            return lang.format(Msg::StatusFault, &[fault]);
+        }
+        if !self.is_processing.load(Ordering::SeqCst) {
+            return lang.text(Msg::StatusIdle).to_owned();
+        }
+        let paused = self.pause_flag.load(Ordering::SeqCst);
+        let msg = if paused { Msg::StatusDispensingPaused } else { Msg::StatusDispensing };
+        match *self.next_order.lock().unwrap() {
+            Some((red, green)) => lang.format(msg, &[&lang.format_number(red), &lang.format_number(green)]),
+            None => lang.text(Msg::StatusFinishing).to_owned(),
+        }
+    }
+
+    /// Adds to the credit, e.g. when coins are paid in or an order is refunded.
+    fn add_credit(&self, amount: u64) {
+        // the closure always returns Some, so this can't fail
+        let _ = self.credit.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |credit| {
+            Some(credit.saturating_add(amount))
+        });
+        self.save_credit();
+    }
+
+    /// Takes the price of an order out of the credit.
+    fn spend_credit(&self, amount: u64) {
+        let _ = self.credit.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |credit| {
+            Some(credit.saturating_sub(amount))
+        });
+        self.save_credit();
+    }
+
+    /// Saves the credit, so it's still there after a restart. A failure is only logged, since the credit is
+    /// still right in memory.
+    fn save_credit(&self) {
+        let _guard = self.credit_save.lock().unwrap();
+        let credit = SavedCredit { credit: self.credit.load(Ordering::SeqCst) };
+        if let Err(err) = save_toml(&self.config.persist.credit_file, &credit) {
+            eprintln!("could not save credit: {}", err);
+        }
+    }
+
+    /// Gives back the credit (and badge quota) for the items of an order that weren't dispensed, and drops the
+    /// saved order so it can't be resumed as well.
+    fn refund_order(&self, progress: &OrderProgress) {
+        let (red, green) = progress.remaining();
+        let refund = self.config.payment.order_cost(red, green);
+        if refund > 0 {
+            self.add_credit(refund);
+        }
+        let order_badge = self.order_badge.lock().unwrap().take();
+        if let Some(user) = order_badge.filter(|_| (red, green) != (0, 0)) {
+            self.returned_items.lock().unwrap().push((user, [red, green]));
+        }
+        if let Err(err) = OrderProgress::clear(&self.config.persist.order_file) {
+            eprintln!("could not delete saved order: {}", err);
+        }
+    }
+
+    /// Interrupts all the worker threads.
+    fn unpark_workers(&self) {
+        for thread in &*self.worker_threads.lock().unwrap() {
+            thread.unpark();
+        }
+    }
 }
 
 /// Primary state for the GUI.
@@ -47,80 +229,449 @@ pub struct Application {
     // Shared state between UI, GPIO and music threads.
     // Since the data isn't owned solely by the GUI, it needs to be reference-counted.
     shared_state: Arc<SharedState>,
-    // Handles to the other threads so they can be cleaned up properly.
-    gpio_join_handle: Option<JoinHandle<()>>,
-    music_join_handle: Option<JoinHandle<()>>,
+    // The other threads, which are restarted if they crash and cleaned up properly on exit.
+    gpio_worker: Worker,
+    music_worker: Worker,
+    // Which screen is shown, and the PIN keypad for admin mode.
+    admin: AdminState,
+    // Reports status to systemd when running as a service.
+    service: ServiceReporter,
+    // Order that was still running when the app last stopped, until the user resumes or discards it.
+    interrupted_order: Option<OrderProgress>,
+    // Actions from the physical buttons, if there are any.
+    panel: Option<Receiver<PanelAction>>,
+    // Slot that the rotary encoder changes (0 is red, 1 is green).
+    selected_slot: usize,
+    // Sends simulated coin pulses, if they're turned on.
+    coin_sim: Option<Sender<u32>>,
+    // Codes read by the scanner thread, if the scanner's input device is read directly.
+    scanner: Option<Receiver<String>>,
+    // Keystrokes typed by a keyboard-wedge scanner, until it presses Enter.
+    scan_buffer: String,
+    // IDs of scanned orders that have been started, so a code can't be used twice. Saved to disk, so this
+    // holds across restarts too.
+    used_order_ids: UsedOrderIds,
+    // Result of the last scan, shown to the user.
+    scan_message: Option<String>,
+    // Badge reader and daily quotas, if badges are turned on.
+    badges: Option<Badges>,
+    // Presets saved from the admin screen, and the name typed in for the next one.
+    saved_presets: Vec<Preset>,
+    preset_name: String,
+    // Picks random orders, and the number of items to pick for the next one.
+    surprise: SurprisePicker,
+    cnt_surprise: CounterState,
+    // Shown when a surprise order can't be picked.
+    surprise_message: Option<String>,
+    // Colours, names and sizes for the GUI, and the logo from it if there is one.
+    theme: Theme,
+    logo: Option<TextureHandle>,
+    // Language the GUI is shown in, which customers can change.
+    language: Language,
+    // Last time anyone used the dispenser, for the admin timeout and attract mode.
+    last_activity: Instant,
+    // When attract mode last started, and the artwork it shows for each slot.
+    attract_since: Instant,
+    slot_art: [Option<TextureHandle>; 2],
 }
 
 impl Application {
     /// Initializes the app. Requires an `egui` context to update the GUI from outside the GUI thread.
-    pub fn new(egui_ctx: &egui::Context) -> Self {
+    fn new(egui_ctx: &egui::Context, config: Config, theme: Theme) -> Self {
+        theme.apply(egui_ctx);
+        let logo = theme.load_logo(egui_ctx);
+        let slot_art = theme.load_slot_art(egui_ctx);
+
+        // Check for an order that didn't finish last time. This has to happen before the GPIO thread starts.
+        // An order that was fully dispensed just before the app stopped has nothing left to resume.
+        let interrupted_order = OrderProgress::load(&config.persist.order_file)
+            .unwrap_or_else(|err| {
+                eprintln!("could not load saved order: {}", err);
+                None
+            })
+            .filter(|progress| progress.remaining() != (0, 0));
+
+        // Load what was set up on the admin screen last time.
+        let calibration = load_toml(&config.persist.calibration_file).unwrap_or_else(|err| {
+            eprintln!("could not load calibration: {}", err);
+            None
+        });
+        let inventory = load_toml(&config.persist.inventory_file).unwrap_or_else(|err| {
+            eprintln!("could not load inventory: {}", err);
+            None
+        });
+        let credit: Option<SavedCredit> = load_toml(&config.persist.credit_file).unwrap_or_else(|err| {
+            eprintln!("could not load credit: {}", err);
+            None
+        });
+        let used_order_ids = load_toml(&config.persist.used_codes_file).unwrap_or_else(|err| {
+            eprintln!("could not load used order codes: {}", err);
+            None
+        });
+        let history = load_history(&config.persist.history_file);
+        let saved_presets = load_saved_presets(&config.persist.presets_file);
+
         // Allocate the shared state on the heap; reference-counted to share between threads.
-        let shared_state = Arc::<SharedState>::default();
+        let shared_state = Arc::new(SharedState {
+            config,
+            calibration: Mutex::new(calibration.unwrap_or_default()),
+            inventory: Mutex::new(inventory.unwrap_or_default()),
+            credit: AtomicU64::new(credit.unwrap_or_default().credit),
+            history: Mutex::new(history),
+            ..Default::default()
+        });
+
+        // Shut down cleanly when the service is stopped.
+        if let Err(err) = shutdown::spawn_signal_thread(Arc::clone(&shared_state), egui_ctx.clone()) {
+            eprintln!("could not handle termination signals: {}", err);
+        }
+
+        // Watch the E-stop before anything starts moving.
+        estop::spawn_estop_thread(Arc::clone(&shared_state), egui_ctx.clone());
+
+        // Show what's going on with the status lights, if there are any.
+        indicator_thread::spawn_indicator_thread(Arc::clone(&shared_state));
+
+        // Show the order on the front panel display, if there is one.
+        display_thread::spawn_display_thread(Arc::clone(&shared_state));
+
+        // Count coins, if there's a coin acceptor.
+        let coin_sim = coin_thread::spawn_coin_thread(Arc::clone(&shared_state), egui_ctx.clone());
+
+        // Read codes from the scanner, if it's read directly.
+        let scanner = scanner::spawn_scanner_thread(&shared_state.config.scanner, egui_ctx.clone());
+
+        // Read badges, if they're needed to order.
+        let badges = Badges::new(&shared_state, egui_ctx);
+
+        // Listen to the physical buttons, if there are any.
+        let panel = panel::spawn_panel_thread(Arc::clone(&shared_state), egui_ctx.clone());
 
         // Share the shared-state object and GUI handle to the two threads and start them.
-        let gpio_thread = {
-            let shared_state = Arc::clone(&shared_state);
-            let egui_ctx = egui_ctx.clone();
-            thread::spawn(move || run_gpio_thread(shared_state, egui_ctx))
-        };
-        let music_thread = {
-            let shared_state = Arc::clone(&shared_state);
-            let egui_ctx = egui_ctx.clone();
-            thread::spawn(move || run_music_thread(shared_state, egui_ctx))
-        };
+        let gpio_worker =
+            Worker::spawn("GPIO", run_gpio_thread, recover_gpio, |state| &state.gpio_ready, &shared_state, egui_ctx);
+        // the buzzers turn themselves off when the music thread dies, so there's nothing to recover
+        let music_worker =
+            Worker::spawn("Music", run_music_thread, |_| {}, |state| &state.music_ready, &shared_state, egui_ctx);
+
+        // Report to systemd, if running as a service. The watchdog is only pinged while both workers are alive.
+        let service = ServiceReporter::new(&shared_state, 2);
+
+        let language = shared_state.config.language;
+        let surprise_config = &shared_state.config.surprise;
+        let surprise = SurprisePicker::new(surprise_config.weights(), surprise_config.seed);
 
         // Store all state in the Application struct
         Self {
             cnt_red: Default::default(),
             cnt_green: Default::default(),
-            shared_state: shared_state,
-            gpio_join_handle: Some(gpio_thread),
-            music_join_handle: Some(music_thread),
+            shared_state,
+            gpio_worker,
+            music_worker,
+            admin: AdminState::new(),
+            service,
+            interrupted_order,
+            panel,
+            selected_slot: 0,
+            coin_sim,
+            scanner,
+            scan_buffer: String::new(),
+            used_order_ids: used_order_ids.unwrap_or_default(),
+            scan_message: None,
+            badges,
+            saved_presets,
+            preset_name: String::new(),
+            surprise,
+            cnt_surprise: Default::default(),
+            surprise_message: None,
+            theme,
+            logo,
+            language,
+            last_activity: Instant::now(),
+            attract_since: Instant::now(),
+            slot_art,
         }
     }
 
-    /// Starts an order based on the current GUI state.
-    fn start_order(&self) {
+    /// Returns the price of the order on the counters.
+    fn order_cost(&self) -> u64 {
+        self.shared_state.config.payment.order_cost(self.cnt_red.count(), self.cnt_green.count())
+    }
+
+    /// Starts an order based on the current GUI state, paying for it from the credit and counting it
+    /// against the daily quota of whoever tapped their badge.
+    fn start_order(&mut self) {
+        let (red, green) = (self.cnt_red.count(), self.cnt_green.count());
+        // whatever's left over stays as credit for the next order
+        self.shared_state.spend_credit(self.order_cost());
+        self.record_badge_order(red, green);
         let mut order = self.shared_state.next_order.lock().unwrap();
-        *order = Some((self.cnt_red.count(), self.cnt_green.count()));
+        *order = Some((red, green));
         // Notify the GPIO thread that we can start.
-        self.gpio_join_handle.as_ref().unwrap().thread().unpark();
+        self.gpio_worker.unpark();
+    }
+
+    /// Returns true if a new order can be started right now.
+    fn can_start(&self) -> bool {
+        // starting a new order would overwrite the unfinished one
+        !self.shared_state.is_processing.load(Ordering::SeqCst)
+            && self.shared_state.fault.lock().unwrap().is_none()
+            && self.interrupted_order.is_none()
+            && self.shared_state.credit.load(Ordering::SeqCst) >= self.order_cost()
+            && self.badge_allows_order()
+            && self.has_stock_for(self.cnt_red.count(), self.cnt_green.count())
+            && self.shared_state.config.limits.allows(self.cnt_red.count(), self.cnt_green.count())
+    }
+
+    /// Pauses or resumes the order, if one is currently being processed.
+    fn toggle_pause(&self) {
+        if self.shared_state.is_processing.load(Ordering::SeqCst) {
+            self.shared_state.pause_flag.fetch_xor(true, Ordering::SeqCst);
+            self.gpio_worker.unpark();
+        }
+    }
+
+    /// Cancels the order, if one is currently being processed.
+    fn cancel_order(&self) {
+        if self.shared_state.is_processing.load(Ordering::SeqCst) {
+            self.shared_state.cancel_flag.store(true, Ordering::SeqCst);
+            self.gpio_worker.unpark();
+        }
+    }
+
+    /// Returns the counter for a slot (0 is red, 1 is green).
+    fn counter_mut(&mut self, slot: usize) -> &mut CounterState {
+        match slot {
+            0 => &mut self.cnt_red,
+            _ => &mut self.cnt_green,
+        }
+    }
+
+    /// Handles everything that's been pressed on the physical buttons since the last frame. Presses outside the
+    /// order screen are thrown away, so the buttons can't start or cancel orders from behind the PIN screen.
+    fn handle_panel_actions(&mut self) {
+        let Some(panel) = &self.panel else {
+            return;
+        };
+        let actions: Vec<PanelAction> = panel.try_iter().collect();
+        if !actions.is_empty() {
+            self.last_activity = Instant::now();
+        }
+        // the buttons are for customers; they'd go behind staff's backs in admin mode
+        if self.admin.screen != Screen::Customer {
+            return;
+        }
+        let is_processing = self.shared_state.is_processing.load(Ordering::SeqCst);
+        for action in actions {
+            match action {
+                // the counters can't be changed during an order, same as on the screen
+                PanelAction::Adjust(slot, delta) if !is_processing => {
+                    let counter = self.counter_mut(slot);
+                    counter.set_count(counter.count().saturating_add_signed(delta));
+                }
+                PanelAction::AdjustSelected(delta) if !is_processing => {
+                    let counter = self.counter_mut(self.selected_slot);
+                    counter.set_count(counter.count().saturating_add_signed(delta));
+                }
+                PanelAction::Preset(index) if !is_processing => self.apply_preset(index),
+                PanelAction::Adjust(..) | PanelAction::AdjustSelected(_) | PanelAction::Preset(_) => {}
+                PanelAction::NextSlot => self.selected_slot = (self.selected_slot + 1) % 2,
+                PanelAction::Start => {
+                    if self.can_start() {
+                        self.start_order();
+                    }
+                }
+                PanelAction::Pause => self.toggle_pause(),
+                PanelAction::Cancel => self.cancel_order(),
+            }
+        }
+    }
+
+    /// Resumes the order that was interrupted last time, dispensing only what's left of it.
+    fn resume_order(&mut self) {
+        if let Some(progress) = self.interrupted_order.take() {
+            *self.shared_state.next_order.lock().unwrap() = Some(progress.remaining());
+            self.gpio_worker.unpark();
+        }
+    }
+
+    /// Throws away the order that was interrupted last time. It was paid for in full, so the items that weren't
+    /// dispensed go back into the credit.
+    fn discard_order(&mut self) {
+        if let Some(progress) = self.interrupted_order.take() {
+            self.shared_state.refund_order(&progress);
+        }
+    }
+
+    /// Resets the E-stop after it's been released, letting the worker threads start again.
+    /// An E-stop that isn't working can't be reset.
+    fn reset_estop(&self) {
+        if self.shared_state.estop_unavailable.load(Ordering::SeqCst) {
+            return;
+        }
+        self.shared_state.estop.store(false, Ordering::SeqCst);
+        self.clear_fault();
+    }
+
+    /// Clears the current fault, letting the worker threads try again.
+    fn clear_fault(&self) {
+        *self.shared_state.fault.lock().unwrap() = None;
+        self.gpio_worker.unpark();
+        self.music_worker.unpark();
+        if let Some(badges) = &self.badges {
+            badges.unpark();
+        }
+    }
+}
+
+/// Describes a broken order limit, for showing next to the counters.
+fn limit_text(lang: Language, err: LimitError) -> String {
+    match err {
+        LimitError::SlotMax(max) => lang.format(Msg::SlotMax, &[&lang.format_number(max)]),
+        LimitError::OrderMax(max) => lang.format(Msg::OrderMax, &[&lang.format_number(max)]),
+        LimitError::OrderMin(min) => lang.format(Msg::OrderMin, &[&lang.format_number(min)]),
     }
 }
 
 impl Drop for Application {
     // This function is run when Rust cleans up the application.
     fn drop(&mut self) {
-        let gpio_join_handle = self.gpio_join_handle.take().unwrap();
-        let gpio_thread = gpio_join_handle.thread();
-
-        let music_join_handle = self.music_join_handle.take().unwrap();
-        let music_thread = music_join_handle.thread();
-
         // set the exit flag
         self.shared_state.exit_flag.store(true, Ordering::SeqCst);
+        self.service.stopping();
 
         // interrupt both background threads to let them know to exit
-        gpio_thread.unpark();
-        music_thread.unpark();
+        self.gpio_worker.unpark();
+        self.music_worker.unpark();
+        if let Some(badges) = &self.badges {
+            badges.unpark();
+        }
 
         // join both threads
-        gpio_join_handle.join().expect("GPIO join failed!");
-        music_join_handle.join().expect("Music join failed!")
+        self.gpio_worker.join();
+        self.music_worker.join();
     }
 }
 
 impl App for Application {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // check on the worker threads, restarting them if they've crashed.
+        // The GUI needs to keep updating for this to happen, even if nothing else changes.
+        self.gpio_worker.poll(&self.shared_state, ctx);
+        self.music_worker.poll(&self.shared_state, ctx);
+        self.check_activity(ctx);
+        self.handle_panel_actions();
+        self.handle_badges();
+        self.handle_scanner(ctx);
+        self.check_admin_timeout();
+        self.update_attract(ctx);
+        *self.shared_state.draft_order.lock().unwrap() = (self.cnt_red.count(), self.cnt_green.count());
+        ctx.request_repaint_after(Duration::from_secs(1));
+
         CentralPanel::default().show(ctx, |ui| {
+            if self.shared_state.attract.load(Ordering::SeqCst) {
+                return self.attract_ui(ui);
+            }
+            match self.admin.screen {
+                Screen::Customer => {}
+                Screen::Pin => return self.pin_ui(ui),
+                Screen::Admin(section) => return self.admin_ui(ui, section),
+            }
+
             let is_processing = self.shared_state.is_processing.load(Ordering::SeqCst);
             let is_paused = self.shared_state.pause_flag.load(Ordering::SeqCst);
+            let fault = self.shared_state.fault.lock().unwrap().clone();
+            let can_start = self.can_start();
+            // with an encoder, mark the slot it changes
+            let has_encoder = self.shared_state.config.panel.encoder_a.is_some();
+            let lang = self.language;
+            let header = |slot: usize| {
+                let name = self.theme.slot(slot).0.unwrap_or(lang.text([Msg::Red, Msg::Green][slot]));
+                if has_encoder && self.selected_slot == slot {
+                    format!("> {} <", name)
+                } else {
+                    name.to_owned()
+                }
+            };
+            let (red_header, green_header) = (header(0), header(1));
+            let (red_accent, green_accent) = (self.theme.slot(0).1, self.theme.slot(1).1);
+            // anything over the limits is pointed out next to the counter it's about
+            let limits = &self.shared_state.config.limits;
+            let (red, green) = (self.cnt_red.count(), self.cnt_green.count());
+            let (red_error, green_error) = (
+                limits.check_slot(0, red).map(|err| limit_text(lang, err)),
+                limits.check_slot(1, green).map(|err| limit_text(lang, err)),
+            );
+            // an empty order isn't worth complaining about, it just can't be started
+            let order_error = limits
+                .check_order(red, green)
+                .filter(|_| red.saturating_add(green) > 0)
+                .map(|err| limit_text(lang, err));
 
             ui.allocate_ui_with_layout(
                 ui.available_size(),
                 Layout::top_down(Align::Center),
                 |ui| {
+                    // the venue's branding goes at the very top
+                    if let Some(logo) = &self.logo {
+                        let size = logo.size_vec2();
+                        let height = self.theme.logo_height;
+                        ui.image((logo.id(), Vec2::new(size.x * height / size.y, height)));
+                    }
+                    if !self.theme.heading.is_empty() {
+                        ui.heading(&self.theme.heading);
+                    }
+                    // if something went wrong with the hardware, show it above everything else.
+                    // Faults and the E-stop can only be reset in admin mode.
+                    if self.shared_state.estop.load(Ordering::SeqCst) {
+                        ui.colored_label(Color32::RED, lang.text(Msg::EmergencyStop));
+                    } else if let Some(fault) = &fault {
+                        ui.colored_label(Color32::RED, lang.format(Msg::OutOfOrder, &[fault]));
+                    }
+                    // offer to finish the order that was cut off last time
+                    if let Some(progress) = self.interrupted_order {
+                        let [red_done, red, green_done, green] = [
+                            progress.dispensed[0],
+                            progress.counts[0],
+                            progress.dispensed[1],
+                            progress.counts[1],
+                        ]
+                        .map(|count| lang.format_number(count));
+                        ui.label(lang.format(Msg::UnfinishedOrder, &[&red_done, &red, &green_done, &green]));
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(
+                                    !is_processing && fault.is_none(),
+                                    Button::new(lang.text(Msg::ResumeOrder)).min_size(self.theme.button_size()),
+                                )
+                                .clicked()
+                            {
+                                self.resume_order();
+                            }
+                            if ui.add(Button::new(lang.text(Msg::Discard)).min_size(self.theme.button_size())).clicked() {
+                                self.discard_order();
+                            }
+                        });
+                    }
+                    // show whose badge was tapped and what they have left
+                    self.badge_ui(ui);
+                    // show how much has been paid, if the dispenser isn't free
+                    let payment = &self.shared_state.config.payment;
+                    if payment.enabled() {
+                        let credit = self.shared_state.credit.load(Ordering::SeqCst);
+                        let cost = self.order_cost();
+                        let money = |cents| lang.format_money(&payment.currency, cents);
+                        ui.label(lang.format(Msg::Credit, &[&money(credit), &money(cost)]));
+                        if credit < cost {
+                            ui.label(lang.format(Msg::InsertMore, &[&money(cost - credit)]));
+                        }
+                    }
+                    // don't let people order what isn't there
+                    if !self.has_stock_for(self.cnt_red.count(), self.cnt_green.count()) {
+                        ui.colored_label(Color32::RED, lang.text(Msg::NotEnoughStock));
+                    }
+                    // one-tap presets, which can't be picked during an order either
+                    self.presets_ui(ui, !is_processing);
                     // arrange the counters in a row
                     ui.allocate_ui_with_layout(
                         Vec2::new(200.0, 150.0),
@@ -128,24 +679,34 @@ impl App for Application {
                         |ui| {
                             ui.add_enabled(
                                 !is_processing,
-                                Counter::new(&mut self.cnt_red).with_header("RED"),
+                                Counter::new(&mut self.cnt_red)
+                                    .with_language(lang)
+                                    .with_header(&red_header)
+                                    .with_accent(red_accent)
+                                    .with_error(red_error.as_deref()),
                             );
                             ui.add_enabled(
                                 !is_processing,
-                                Counter::new(&mut self.cnt_green).with_header("GREEN"),
+                                Counter::new(&mut self.cnt_green)
+                                    .with_language(lang)
+                                    .with_header(&green_header)
+                                    .with_accent(green_accent)
+                                    .with_error(green_error.as_deref()),
                             );
                         },
                     );
+                    if let Some(error) = &order_error {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    // or let the dispenser pick the slots
+                    self.surprise_ui(ui, !is_processing);
                     // start button
                     if ui
-                        .add_enabled(
-                            !is_processing,
-                            Button::new("START").min_size(Vec2::new(150.0, 0.0)),
-                        )
+                        .add_enabled(can_start, Button::new(lang.text(Msg::Start)).min_size(self.theme.button_size()))
                         .clicked()
                     {
                         // start an order if one isn't already being processed
-                        if !self.shared_state.is_processing.load(Ordering::SeqCst) {
+                        if self.can_start() {
                             self.start_order();
                         }
                     }
@@ -153,39 +714,42 @@ impl App for Application {
                     if ui
                         .add_enabled(
                             is_processing,
-                            Button::new(if is_paused {"RESUME"} else {"PAUSE"}).min_size(Vec2::new(150.0, 0.0)),
+                            Button::new(lang.text(if is_paused { Msg::Resume } else { Msg::Pause })).min_size(self.theme.button_size()),
                         )
                         .clicked()
                     {
-                        // pause or resume the order if one is currently being processed.
-                        if self.shared_state.is_processing.load(Ordering::SeqCst) {
-                            self.shared_state.pause_flag.fetch_xor(true, Ordering::SeqCst);
-                            self.gpio_join_handle.as_ref().unwrap().thread().unpark();
-                        }
+                        self.toggle_pause();
                     }
                     // stop immediately button
                     if ui
                         .add_enabled(
                             is_processing,
-                            Button::new("CANCEL").min_size(Vec2::new(150.0, 0.0)),
+                            Button::new(lang.text(Msg::Cancel)).min_size(self.theme.button_size()),
                         )
                         .clicked()
                     {
-                        // cancel the order if one is being processed
-                        if self.shared_state.is_processing.load(Ordering::SeqCst) {
-                            self.shared_state.cancel_flag.store(true, Ordering::SeqCst);
-                            self.gpio_join_handle.as_ref().unwrap().thread().unpark();
-                        }
-
+                        self.cancel_order();
                     }
-                    // quit button
-                    if ui
-                        .add(Button::new("QUIT").min_size(Vec2::new(150.0, 0.0)))
-                        .clicked()
+                    // where the music is up to, while it's playing
+                    if self.shared_state.music_ready.load(Ordering::SeqCst) && !self.shared_state.muted.load(Ordering::SeqCst)
                     {
-                        // This just straight-up closes the window, which triggers the code in `Drop`
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        let bar = self.shared_state.cur_measure.load(Ordering::SeqCst);
+                        ui.label(lang.format(Msg::MusicBar, &[&lang.format_number(bar as u64)]));
                     }
+                    // admin mode button, which asks for the PIN
+                    self.admin_button(ui);
+                    // result of the last scan
+                    if let Some(msg) = &self.scan_message {
+                        ui.label(msg);
+                    }
+                    // anyone can switch the language
+                    ui.horizontal(|ui| {
+                        for language in Language::ALL {
+                            if ui.selectable_label(self.language == language, language.name()).clicked() {
+                                self.language = language;
+                            }
+                        }
+                    });
                 },
             );
         });
@@ -194,19 +758,22 @@ impl App for Application {
 
 impl Application {
     /// Runs the application GUI.
-    pub fn run() {
+    pub fn run(config: Config) {
         // This identifies the app on Wayland. I've used Java package naming to make it more unique.
         // If I install a .desktop file in ~/.local/share/applications named "io.github.jgcodes2020.dispenser.desktop", it would
         // use an icon from there. (Yes, Wayland doesn't simply let you set an icon because it likes to be special).
         const APP_ID: &str = "io.github.jgcodes2020.dispenser";
 
+        // The theme has the window title, so it's loaded before the window is made.
+        let theme = Theme::load(config.theme_file.as_deref());
+
         // These are the options that are set on the display. For now, all this does is make sure it runs fullscreen.
         let opts = NativeOptions {
             viewport: ViewportBuilder::default()
                 .with_resizable(false)
                 .with_inner_size(Vec2::new(800.0, 480.0))
                 .with_fullscreen(true)
-                .with_title("POOTIS PENCER HERE"),
+                .with_title(theme.title.clone()),
 
             ..Default::default()
         };
@@ -218,7 +785,7 @@ impl Application {
             Box::new(|ctx| {
                 ctx.egui_ctx.set_zoom_factor(2.0);
 
-                Box::new(Self::new(&ctx.egui_ctx))
+                Box::new(Self::new(&ctx.egui_ctx, config, theme))
             }),
         )
         .unwrap();
@@ -0,0 +1,577 @@
+/*
+gui/admin.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Implementation of the admin mode, which is locked behind a PIN. It has everything the customers
+//! shouldn't touch: thread health, the E-stop reset, servo calibration, inventory, order history,
+//! hardware tests, settings and quitting the app.
+
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use egui::{Button, Color32, Grid, ScrollArea, Slider, Ui, Vec2};
+
+use crate::i18n::Msg;
+
+use super::{
+    counter::{Counter, CounterState},
+    persist::save_toml,
+    supervisor::WorkerStatus,
+    Application,
+};
+
+/// Number of wrong PINs in a row before the keypad locks up.
+const MAX_PIN_ATTEMPTS: u32 = 3;
+/// How long the keypad stays locked after too many wrong PINs.
+const PIN_LOCKOUT: Duration = Duration::from_secs(30);
+/// Longest PIN that can be typed in.
+const MAX_PIN_LEN: usize = 8;
+
+/// Which screen the GUI is showing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Screen {
+    /// The order screen, which is all customers can see.
+    Customer,
+    /// The keypad for entering the admin PIN.
+    Pin,
+    Admin(Section),
+}
+
+/// Sections of the admin mode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Section {
+    Status,
+    Calibration,
+    Inventory,
+    History,
+    Tests,
+    Settings,
+}
+
+impl Section {
+    const ALL: [Section; 6] = [
+        Section::Status,
+        Section::Calibration,
+        Section::Inventory,
+        Section::History,
+        Section::Tests,
+        Section::Settings,
+    ];
+
+    /// Returns the message for the section's tab.
+    fn msg(self) -> Msg {
+        match self {
+            Section::Status => Msg::Status,
+            Section::Calibration => Msg::Calibration,
+            Section::Inventory => Msg::Inventory,
+            Section::History => Msg::History,
+            Section::Tests => Msg::Tests,
+            Section::Settings => Msg::Settings,
+        }
+    }
+}
+
+/// State of the admin mode and its PIN keypad.
+pub(super) struct AdminState {
+    pub screen: Screen,
+    // Digits typed on the keypad so far.
+    pin_entered: String,
+    // Shown under the keypad, e.g. after a wrong PIN.
+    pin_message: Option<Msg>,
+    // Wrong PINs in a row, and when the keypad unlocks again after too many.
+    failed_attempts: u32,
+    locked_until: Option<Instant>,
+    // Counters for setting the stock on the inventory section.
+    stock_counters: [CounterState; 2],
+}
+
+impl AdminState {
+    pub fn new() -> Self {
+        Self {
+            screen: Screen::Customer,
+            pin_entered: String::new(),
+            pin_message: None,
+            failed_attempts: 0,
+            locked_until: None,
+            stock_counters: Default::default(),
+        }
+    }
+
+    /// Shows the PIN keypad.
+    fn enter(&mut self) {
+        self.pin_entered.clear();
+        self.pin_message = None;
+        self.screen = Screen::Pin;
+    }
+
+    /// Locks admin mode and goes back to the order screen.
+    fn leave(&mut self) {
+        self.pin_entered.clear();
+        self.screen = Screen::Customer;
+    }
+
+    /// Returns when the keypad unlocks, if it's locked at `now`.
+    fn locked_until(&self, now: Instant) -> Option<Instant> {
+        self.locked_until.filter(|&until| now < until)
+    }
+
+    /// Checks the PIN typed on the keypad against `pin`, unlocking admin mode if it's right. Nothing is checked
+    /// while the keypad is locked.
+    fn submit_pin(&mut self, pin: &str, now: Instant) {
+        if self.locked_until(now).is_some() {
+            self.pin_entered.clear();
+            return;
+        }
+        if self.pin_entered == pin {
+            self.failed_attempts = 0;
+            self.pin_message = None;
+            self.screen = Screen::Admin(Section::Status);
+        } else {
+            self.failed_attempts += 1;
+            if self.failed_attempts >= MAX_PIN_ATTEMPTS {
+                self.failed_attempts = 0;
+                self.locked_until = Some(now + PIN_LOCKOUT);
+                self.pin_message = Some(Msg::TooManyWrongPins);
+            } else {
+                self.pin_message = Some(Msg::WrongPin);
+            }
+        }
+        self.pin_entered.clear();
+    }
+
+    /// Goes back to the order screen if nobody has touched the screen for `timeout`, given how long it's been
+    /// since anyone did.
+    fn check_timeout(&mut self, idle: Duration, timeout: Duration) {
+        if self.screen != Screen::Customer && idle >= timeout {
+            self.leave();
+        }
+    }
+}
+
+impl Application {
+    /// Goes back to the order screen if nobody has touched the screen for a while.
+    pub(super) fn check_admin_timeout(&mut self) {
+        let timeout = Duration::from_secs(self.shared_state.config.admin.idle_timeout_secs);
+        self.admin.check_timeout(self.last_activity.elapsed(), timeout);
+    }
+
+    /// Draws the button that opens admin mode, for the order screen.
+    pub(super) fn admin_button(&mut self, ui: &mut Ui) {
+        if ui.add(Button::new(self.language.text(Msg::Admin)).min_size(self.theme.button_size())).clicked() {
+            self.admin.enter();
+        }
+    }
+
+    /// Draws the PIN keypad.
+    pub(super) fn pin_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        ui.heading(lang.text(Msg::EnterPin));
+
+        let locked = self.admin.locked_until(Instant::now());
+        // the digits themselves are never shown
+        let masked = "*".repeat(self.admin.pin_entered.len());
+        ui.heading(if masked.is_empty() { "-" } else { &masked });
+        if let Some(until) = locked {
+            let secs = until.saturating_duration_since(Instant::now()).as_secs() + 1;
+            ui.colored_label(Color32::RED, lang.format(Msg::TryAgainIn, &[&secs]));
+        } else if let Some(msg) = self.admin.pin_message {
+            ui.colored_label(Color32::RED, lang.text(msg));
+        }
+
+        let size = Vec2::new(50.0, 40.0);
+        Grid::new("pin_keypad").show(ui, |ui| {
+            let (clear, ok) = (lang.text(Msg::Clear), lang.text(Msg::Ok));
+            for row in [["1", "2", "3"], ["4", "5", "6"], ["7", "8", "9"], [clear, "0", ok]] {
+                for key in row {
+                    if !ui.add_enabled(locked.is_none(), Button::new(key).min_size(size)).clicked() {
+                        continue;
+                    }
+                    match key {
+                        key if key == clear => self.admin.pin_entered.clear(),
+                        key if key == ok => {
+                            self.admin.submit_pin(&self.shared_state.config.admin.pin, Instant::now());
+                        }
+                        digit if self.admin.pin_entered.len() < MAX_PIN_LEN => self.admin.pin_entered.push_str(digit),
+                        _ => {}
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        if ui.add(Button::new(lang.text(Msg::Cancel)).min_size(self.theme.button_size())).clicked() {
+            self.admin.leave();
+        }
+    }
+
+    /// Draws admin mode: a row of tabs for the sections, the current section, and the buttons to leave.
+    pub(super) fn admin_ui(&mut self, ui: &mut Ui, section: Section) {
+        ui.horizontal(|ui| {
+            for tab in Section::ALL {
+                if ui.selectable_label(tab == section, self.language.text(tab.msg())).clicked() {
+                    self.admin.screen = Screen::Admin(tab);
+                }
+            }
+        });
+        ui.separator();
+
+        match section {
+            Section::Status => self.status_ui(ui),
+            Section::Calibration => self.calibration_ui(ui),
+            Section::Inventory => self.inventory_ui(ui),
+            Section::History => self.history_ui(ui),
+            Section::Tests => self.tests_ui(ui),
+            Section::Settings => self.settings_ui(ui),
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.add(Button::new(self.language.text(Msg::CustomerMode)).min_size(self.theme.button_size())).clicked() {
+                self.admin.leave();
+            }
+            if ui.add(Button::new(self.language.text(Msg::Quit)).min_size(self.theme.button_size())).clicked() {
+                // This just straight-up closes the window, which triggers the code in `Drop`
+                ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        });
+    }
+
+    /// Shows the health of the worker threads, and resets faults and the E-stop.
+    fn status_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        ui.label(lang.format(Msg::CurrentStatus, &[&self.shared_state.status_text(lang)]));
+
+        // one row per worker thread
+        Grid::new("workers").striped(true).show(ui, |ui| {
+            ui.strong(lang.text(Msg::Thread));
+            ui.strong(lang.text(Msg::ThreadStatus));
+            ui.strong(lang.text(Msg::Faults));
+            ui.strong(lang.text(Msg::LastCrash));
+            ui.end_row();
+
+            for worker in [&self.gpio_worker, &self.music_worker] {
+                ui.label(worker.name);
+                ui.label(match worker.status() {
+                    WorkerStatus::Running => lang.text(Msg::Running).to_owned(),
+                    WorkerStatus::Restarting(at) => {
+                        let secs = at.saturating_duration_since(Instant::now()).as_secs() + 1;
+                        lang.format(Msg::RestartingIn, &[&secs])
+                    }
+                    WorkerStatus::Stopped => lang.text(Msg::Stopped).to_owned(),
+                });
+                ui.label(worker.faults.to_string());
+                ui.label(worker.last_panic.as_deref().unwrap_or("-"));
+                ui.end_row();
+            }
+        });
+
+        // the E-stop can only be reset once it's been released, and never if it isn't working
+        let estop = self.shared_state.estop.load(Ordering::SeqCst);
+        let pressed = self.shared_state.estop_pressed.load(Ordering::SeqCst);
+        let unavailable = self.shared_state.estop_unavailable.load(Ordering::SeqCst);
+        match (estop, pressed) {
+            _ if unavailable => ui.colored_label(Color32::RED, lang.text(Msg::EstopUnavailable)),
+            (false, _) => ui.label(lang.text(Msg::EstopOk)),
+            (true, false) => ui.colored_label(Color32::RED, lang.text(Msg::EstopLatched)),
+            (true, true) => ui.colored_label(Color32::RED, lang.text(Msg::EstopStillPressed)),
+        };
+        let can_reset = estop && !pressed && !unavailable;
+        if ui
+            .add_enabled(can_reset, Button::new(lang.text(Msg::ResetEstop)).min_size(self.theme.button_size()))
+            .clicked()
+        {
+            self.reset_estop();
+        }
+
+        // other faults can be reset as long as the E-stop isn't latched
+        let fault = self.shared_state.fault.lock().unwrap().clone();
+        if let (Some(fault), false) = (fault, estop) {
+            ui.colored_label(Color32::RED, fault);
+            if ui.add(Button::new(lang.text(Msg::ResetFault)).min_size(self.theme.button_size())).clicked() {
+                self.clear_fault();
+            }
+        }
+
+        // current position in the music
+        let bar = self.shared_state.cur_measure.load(Ordering::SeqCst);
+        ui.label(lang.format(Msg::MusicBar, &[&lang.format_number(bar as u64)]));
+
+        self.badge_admin_ui(ui);
+
+        // pretend a coin was put in, for testing without a coin acceptor
+        if let Some(coin_sim) = &self.coin_sim {
+            if ui.add(Button::new(lang.text(Msg::SimulateCoinPulse)).min_size(self.theme.button_size())).clicked() {
+                let _ = coin_sim.send(1);
+            }
+        }
+    }
+
+    /// Adjusts the servo positions and timing. Changes apply from the next order, and are only kept
+    /// after a restart once they're saved.
+    fn calibration_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        let mut calibration = *self.shared_state.calibration.lock().unwrap();
+
+        Grid::new("calibration").show(ui, |ui| {
+            for (slot, name) in [Msg::Red, Msg::Green].into_iter().enumerate() {
+                ui.label(lang.format(Msg::RestPosition, &[&lang.text(name)]));
+                ui.add(Slider::new(&mut calibration.rest_pos[slot], 0.0..=1.0));
+                ui.end_row();
+                ui.label(lang.format(Msg::PushPosition, &[&lang.text(name)]));
+                ui.add(Slider::new(&mut calibration.push_pos[slot], 0.0..=1.0));
+                ui.end_row();
+            }
+            ui.label(lang.text(Msg::PushTime));
+            ui.add(Slider::new(&mut calibration.push_ms, 100..=2000).suffix(" ms"));
+            ui.end_row();
+            ui.label(lang.text(Msg::GapBetweenItems));
+            ui.add(Slider::new(&mut calibration.gap_ms, 100..=2000).suffix(" ms"));
+            ui.end_row();
+        });
+        *self.shared_state.calibration.lock().unwrap() = calibration;
+
+        ui.horizontal(|ui| {
+            if ui.add(Button::new(lang.text(Msg::Save)).min_size(self.theme.button_size())).clicked() {
+                if let Err(err) = save_toml(&self.shared_state.config.persist.calibration_file, &calibration) {
+                    eprintln!("could not save calibration: {}", err);
+                }
+            }
+            if ui.add(Button::new(lang.text(Msg::Defaults)).min_size(self.theme.button_size())).clicked() {
+                *self.shared_state.calibration.lock().unwrap() = Default::default();
+            }
+        });
+    }
+
+    /// Shows how much is left in each slot, and sets it after a refill.
+    fn inventory_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        let capacities = self.shared_state.config.inventory.capacities();
+        let stock = self.shared_state.inventory.lock().unwrap().stock;
+
+        ui.allocate_ui_with_layout(
+            Vec2::new(400.0, 150.0),
+            egui::Layout::left_to_right(egui::Align::Center),
+            |ui| {
+                for (slot, name) in [Msg::Red, Msg::Green].into_iter().enumerate() {
+                    let name = lang.text(name);
+                    ui.vertical(|ui| {
+                        match capacities[slot] {
+                            Some(capacity) => ui.label(lang.format(
+                                Msg::StockLeft,
+                                &[&name, &lang.format_number(stock[slot]), &lang.format_number(capacity)],
+                            )),
+                            None => ui.label(lang.format(Msg::NotTracked, &[&name])),
+                        };
+                        ui.add(Counter::new(&mut self.admin.stock_counters[slot]).with_language(lang));
+                        ui.horizontal(|ui| {
+                            if ui.button(lang.text(Msg::SetStock)).clicked() {
+                                self.set_stock(slot, self.admin.stock_counters[slot].count());
+                            }
+                            if let Some(capacity) = capacities[slot] {
+                                if ui.button(lang.text(Msg::Refill)).clicked() {
+                                    self.set_stock(slot, capacity);
+                                }
+                            }
+                        });
+                    });
+                }
+            },
+        );
+    }
+
+    /// Changes the number of items left in a slot, and saves it.
+    fn set_stock(&self, slot: usize, count: u64) {
+        let mut inventory = self.shared_state.inventory.lock().unwrap();
+        inventory.stock[slot] = count;
+        if let Err(err) = save_toml(&self.shared_state.config.persist.inventory_file, &*inventory) {
+            eprintln!("could not save inventory: {}", err);
+        }
+    }
+
+    /// Returns true if there's enough left in the tracked slots for an order.
+    pub(super) fn has_stock_for(&self, red: u64, green: u64) -> bool {
+        let stock = self.shared_state.inventory.lock().unwrap().stock;
+        let capacities = self.shared_state.config.inventory.capacities();
+        [red, green]
+            .into_iter()
+            .enumerate()
+            .all(|(slot, count)| capacities[slot].is_none() || stock[slot] >= count)
+    }
+
+    /// Lists the most recent orders, newest first.
+    fn history_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        let history = self.shared_state.history.lock().unwrap();
+        if history.is_empty() {
+            ui.label(lang.text(Msg::NoOrdersYet));
+            return;
+        }
+        ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+            Grid::new("history").striped(true).show(ui, |ui| {
+                ui.strong(lang.text(Msg::Time));
+                ui.strong(lang.text(Msg::Red));
+                ui.strong(lang.text(Msg::Green));
+                ui.strong(lang.text(Msg::Outcome));
+                ui.end_row();
+
+                for entry in history.iter().rev() {
+                    let p = &entry.progress;
+                    ui.label(&entry.time);
+                    for slot in 0..2 {
+                        let dispensed = lang.format_number(p.dispensed[slot]);
+                        ui.label(format!("{}/{}", dispensed, lang.format_number(p.counts[slot])));
+                    }
+                    // outcomes are saved in English, so older files (and any new outcome) still show up
+                    ui.label(outcome_msg(&entry.outcome).map_or(entry.outcome.as_str(), |msg| lang.text(msg)));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    /// Pushes out single items to check the servos, without charging for them.
+    fn tests_ui(&mut self, ui: &mut Ui) {
+        // same rules as a normal order, except for payment and badges
+        let can_test = !self.shared_state.is_processing.load(Ordering::SeqCst)
+            && self.shared_state.fault.lock().unwrap().is_none()
+            && self.interrupted_order.is_none();
+        let lang = self.language;
+        ui.label(lang.text(Msg::TestsHelp));
+        ui.horizontal(|ui| {
+            for (slot, name) in [Msg::PushRed, Msg::PushGreen].into_iter().enumerate() {
+                if ui.add_enabled(can_test, Button::new(lang.text(name)).min_size(self.theme.button_size())).clicked() {
+                    let order = if slot == 0 { (1, 0) } else { (0, 1) };
+                    let mut next_order = self.shared_state.next_order.lock().unwrap();
+                    // a test isn't paid for, so it mustn't be refunded, recorded or resumed like an order
+                    self.shared_state.test_order.store(true, Ordering::SeqCst);
+                    *next_order = Some(order);
+                    drop(next_order);
+                    self.gpio_worker.unpark();
+                }
+            }
+        });
+    }
+
+    /// Settings that can be changed while the app is running.
+    fn settings_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        let mut muted = self.shared_state.muted.load(Ordering::SeqCst);
+        if ui.checkbox(&mut muted, lang.text(Msg::MuteMusic)).changed() {
+            self.shared_state.muted.store(muted, Ordering::SeqCst);
+            // the music thread parks while it's muted
+            self.music_worker.unpark();
+        }
+        ui.label(lang.format(Msg::AdminTimeout, &[&self.shared_state.config.admin.idle_timeout_secs]));
+        self.presets_admin_ui(ui);
+        if self.shared_state.config.admin.pin == "0000" {
+            ui.colored_label(Color32::RED, lang.text(Msg::DefaultPin));
+        }
+    }
+}
+
+/// Returns the message for an outcome recorded in the order history, if it's one the app records.
+fn outcome_msg(outcome: &str) -> Option<Msg> {
+    match outcome {
+        "done" => Some(Msg::OutcomeDone),
+        "cancelled" => Some(Msg::OutcomeCancelled),
+        "interrupted" => Some(Msg::OutcomeInterrupted),
+        "fault" => Some(Msg::OutcomeFault),
+        "E-stop" => Some(Msg::OutcomeEstop),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIN: &str = "2468";
+
+    /// Types a PIN on the keypad and submits it.
+    fn enter_pin(admin: &mut AdminState, pin: &str, now: Instant) {
+        admin.pin_entered = pin.to_owned();
+        admin.submit_pin(PIN, now);
+    }
+
+    #[test]
+    fn locks_after_too_many_wrong_pins() {
+        let now = Instant::now();
+        let mut admin = AdminState::new();
+        admin.enter();
+        enter_pin(&mut admin, "1111", now);
+        assert!(admin.screen == Screen::Pin);
+        assert_eq!(admin.pin_message, Some(Msg::WrongPin));
+        assert!(admin.pin_entered.is_empty());
+        enter_pin(&mut admin, "2222", now);
+        assert_eq!(admin.locked_until(now), None);
+
+        enter_pin(&mut admin, "3333", now);
+        assert_eq!(admin.pin_message, Some(Msg::TooManyWrongPins));
+        assert_eq!(admin.locked_until(now), Some(now + PIN_LOCKOUT));
+        // even the right PIN doesn't work while it's locked
+        enter_pin(&mut admin, PIN, now + PIN_LOCKOUT / 2);
+        assert!(admin.screen == Screen::Pin);
+    }
+
+    #[test]
+    fn unlocks_after_the_lockout() {
+        let now = Instant::now();
+        let mut admin = AdminState::new();
+        admin.enter();
+        for _ in 0..MAX_PIN_ATTEMPTS {
+            enter_pin(&mut admin, "0000", now);
+        }
+        let later = now + PIN_LOCKOUT - Duration::from_millis(1);
+        assert!(admin.locked_until(later).is_some());
+        let later = now + PIN_LOCKOUT;
+        assert_eq!(admin.locked_until(later), None);
+
+        // the count of wrong PINs starts over after the lockout
+        enter_pin(&mut admin, "0000", later);
+        assert_eq!(admin.locked_until(later), None);
+        enter_pin(&mut admin, PIN, later);
+        assert!(admin.screen == Screen::Admin(Section::Status));
+        assert_eq!(admin.pin_message, None);
+    }
+
+    #[test]
+    fn right_pin_resets_the_wrong_pin_count() {
+        let now = Instant::now();
+        let mut admin = AdminState::new();
+        admin.enter();
+        enter_pin(&mut admin, "1111", now);
+        enter_pin(&mut admin, "1111", now);
+        enter_pin(&mut admin, PIN, now);
+        assert_eq!(admin.failed_attempts, 0);
+        admin.leave();
+        admin.enter();
+        enter_pin(&mut admin, "1111", now);
+        assert_eq!(admin.locked_until(now), None);
+    }
+
+    #[test]
+    fn leaves_admin_mode_when_idle() {
+        let timeout = Duration::from_secs(60);
+        let mut admin = AdminState::new();
+        admin.screen = Screen::Admin(Section::Settings);
+        admin.check_timeout(Duration::from_secs(59), timeout);
+        assert!(admin.screen == Screen::Admin(Section::Settings));
+        admin.check_timeout(timeout, timeout);
+        assert!(admin.screen == Screen::Customer);
+
+        // the keypad times out too, taking the half-typed PIN with it
+        admin.enter();
+        admin.pin_entered.push_str("12");
+        admin.check_timeout(Duration::from_secs(61), timeout);
+        assert!(admin.screen == Screen::Customer);
+        assert!(admin.pin_entered.is_empty());
+        // and there's nothing to leave on the order screen
+        admin.check_timeout(Duration::MAX, timeout);
+        assert!(admin.screen == Screen::Customer);
+    }
+}
@@ -0,0 +1,138 @@
+/*
+gui/attract.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Attract mode, which takes over the screen when nobody has used the dispenser for a while.
+//!
+//! It shows the slots' artwork moving around with "tap to start", and the music thread swaps the song for a
+//! short attention tune every few minutes. Any touch (or button, badge or scan) goes back to the order screen.
+
+use std::{
+    f32::consts::TAU,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Ui, Vec2};
+
+use crate::i18n::Msg;
+
+use super::{admin::Screen, Application};
+
+/// How often the animation is redrawn. It doesn't need to be smooth, and the Pi has better things to do.
+const FRAME_TIME: Duration = Duration::from_millis(50);
+/// How long one trip of the artwork around the screen takes, in seconds.
+const LOOP_SECS: f32 = 8.0;
+
+impl Application {
+    /// Notes that someone used the GUI, if there was any input since the last frame.
+    pub(super) fn check_activity(&mut self, ctx: &egui::Context) {
+        if ctx.input(|input| !input.events.is_empty() || input.pointer.any_down()) {
+            self.last_activity = Instant::now();
+        }
+    }
+
+    /// Starts attract mode once the dispenser has been left alone long enough, and stops it when it's used.
+    pub(super) fn update_attract(&mut self, ctx: &egui::Context) {
+        let state = &self.shared_state;
+        let attracting = state.attract.load(Ordering::SeqCst);
+        if attracting {
+            if self.last_activity > self.attract_since {
+                self.stop_attract();
+            } else {
+                ctx.request_repaint_after(FRAME_TIME);
+            }
+            return;
+        }
+
+        let idle_secs = state.config.attract.idle_secs;
+        // only when nothing is going on, nobody is halfway through paying, and there's no unfinished order
+        // waiting for someone to resume or discard it
+        let idle = idle_secs > 0
+            && self.admin.screen == Screen::Customer
+            && self.interrupted_order.is_none()
+            && !state.is_processing.load(Ordering::SeqCst)
+            && !state.estop.load(Ordering::SeqCst)
+            && state.fault.lock().unwrap().is_none()
+            && state.credit.load(Ordering::SeqCst) == 0
+            && self.last_activity.elapsed() >= Duration::from_secs(idle_secs);
+        if idle {
+            self.start_attract();
+            ctx.request_repaint();
+        }
+    }
+
+    /// Starts attract mode. Whoever used the dispenser last is long gone, so their order is cleared.
+    fn start_attract(&mut self) {
+        self.cnt_red.set_count(0);
+        self.cnt_green.set_count(0);
+        self.cnt_surprise.set_count(0);
+        self.scan_message = None;
+        self.surprise_message = None;
+        self.language = self.shared_state.config.language;
+        if let Some(badges) = &mut self.badges {
+            badges.log_out();
+        }
+        self.attract_since = Instant::now();
+        self.shared_state.attract.store(true, Ordering::SeqCst);
+        // the music thread swaps the song for the attention tune
+        self.music_worker.unpark();
+    }
+
+    /// Goes back to the order screen.
+    fn stop_attract(&mut self) {
+        self.shared_state.attract.store(false, Ordering::SeqCst);
+        self.music_worker.unpark();
+    }
+
+    /// Draws attract mode over the whole screen: the slots' artwork going around in a circle, and
+    /// "tap to start" pulsing in the middle.
+    pub(super) fn attract_ui(&mut self, ui: &mut Ui) {
+        // the touch itself is picked up as activity, this just stops it from going anywhere else
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::click());
+        let painter = ui.painter_at(rect);
+        let time = self.attract_since.elapsed().as_secs_f32();
+        let center = rect.center();
+        let radius = rect.width().min(rect.height()) * 0.3;
+
+        if let Some(logo) = &self.logo {
+            let size = logo.size_vec2();
+            let height = self.theme.logo_height;
+            let logo_rect =
+                Rect::from_center_size(Pos2::new(center.x, rect.top() + height), Vec2::new(size.x * height / size.y, height));
+            painter.image(logo.id(), logo_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::WHITE);
+        }
+
+        // the two slots chase each other around the middle of the screen, bobbing as they go
+        for slot in 0..2 {
+            let angle = time / LOOP_SECS * TAU + slot as f32 * TAU / 2.0;
+            let bob = (time * 3.0 + slot as f32).sin() * 0.1 + 1.0;
+            let pos = center + Vec2::angled(angle) * radius;
+            let size = radius * 0.5 * bob;
+            match &self.slot_art[slot] {
+                Some(art) => {
+                    let art_rect = Rect::from_center_size(pos, Vec2::splat(size * 2.0));
+                    painter.image(art.id(), art_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::WHITE);
+                }
+                None => {
+                    let fallback = [Color32::RED, Color32::GREEN][slot];
+                    painter.circle_filled(pos, size, self.theme.slot(slot).1.unwrap_or(fallback));
+                }
+            }
+        }
+
+        // fade "tap to start" in and out
+        let fade = (time * 2.0).sin() * 0.4 + 0.6;
+        let text_colour = ui.visuals().text_color().gamma_multiply(fade);
+        painter.text(
+            center,
+            Align2::CENTER_CENTER,
+            self.language.text(Msg::TapToStart),
+            FontId::proportional(radius * 0.35),
+            text_colour,
+        );
+    }
+}
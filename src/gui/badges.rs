@@ -0,0 +1,478 @@
+/*
+gui/badges.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! RFID badges, which limit how much each person can take per day.
+//!
+//! When badges are turned on, an order can only be started after tapping a badge that belongs to someone, and
+//! only if it fits in what they have left for the day. What everyone has taken is saved to a file, so it
+//! survives restarts; it starts over when the date changes. Items that are charged but never dispensed (because
+//! the order was cancelled or stopped by a fault) are given back.
+//!
+//! If the reader can't be set up or stops working, nobody can order until it's fixed: the fault is shown, and
+//! the reader is tried again once it's cleared.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use egui::{Button, TextEdit, Ui};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock,
+    config::{BadgeReaderKind, BadgesConfig},
+    error::Result,
+    i18n::Msg,
+    rfid::{BadgeReader, Rc522, SimulatedReader},
+};
+
+use super::{
+    persist::{load_toml, save_toml},
+    Application, SharedState,
+};
+
+/// Sets up the badge reader, each time the badge thread (re)starts it.
+type OpenReader = Box<dyn FnMut() -> Result<Box<dyn BadgeReader + Send>> + Send>;
+
+/// How often the reader is checked for badges.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a badge has to be out of range before tapping it again counts as a new tap.
+const ABSENT_TIME: Duration = Duration::from_secs(1);
+
+/// How much everyone has taken on one day.
+#[derive(Default, Serialize, Deserialize)]
+struct DailyUsage {
+    /// The day, as `YYYY-MM-DD` in local time.
+    date: String,
+    /// Number of items each person has taken from each slot, by name.
+    taken: BTreeMap<String, [u64; 2]>,
+}
+
+impl DailyUsage {
+    /// Starts over if the date has changed.
+    fn roll_over(&mut self) {
+        self.roll_over_to(clock::local_date());
+    }
+
+    /// Starts over if `today` isn't the day the usage is for.
+    fn roll_over_to(&mut self, today: String) {
+        if self.date != today {
+            self.date = today;
+            self.taken.clear();
+        }
+    }
+
+    /// Adds items to what someone has taken.
+    fn take(&mut self, user: &str, items: [u64; 2]) {
+        let taken = self.taken.entry(user.to_owned()).or_default();
+        taken[0] = taken[0].saturating_add(items[0]);
+        taken[1] = taken[1].saturating_add(items[1]);
+    }
+
+    /// Takes items that were never dispensed back off what someone has taken.
+    fn give_back(&mut self, user: &str, items: [u64; 2]) {
+        if let Some(taken) = self.taken.get_mut(user) {
+            taken[0] = taken[0].saturating_sub(items[0]);
+            taken[1] = taken[1].saturating_sub(items[1]);
+        }
+    }
+}
+
+/// What someone can still take today. `None` means no limit.
+struct Allowance {
+    total: Option<u64>,
+    slots: [Option<u64>; 2],
+}
+
+impl Allowance {
+    /// Returns true if an order fits in the allowance.
+    fn allows(&self, red: u64, green: u64) -> bool {
+        let fits = |limit: Option<u64>, count: u64| count <= limit.unwrap_or(u64::MAX);
+        fits(self.total, red.saturating_add(green)) && fits(self.slots[0], red) && fits(self.slots[1], green)
+    }
+}
+
+/// Everything to do with badges in the GUI.
+pub(super) struct Badges {
+    // UIDs of tapped badges, from the badge thread.
+    taps: Receiver<String>,
+    // Sends simulated taps, if the reader is simulated.
+    simulated: Option<Sender<String>>,
+    // The badge thread, which waits for the fault to be cleared after the reader fails.
+    thread: Option<Thread>,
+    // UID typed in on the admin screen to simulate a tap.
+    simulated_uid: String,
+    // Name of the person whose badge was tapped, until their order starts.
+    user: Option<String>,
+    usage: DailyUsage,
+    // Problem with the last tap, shown to the user.
+    message: Option<String>,
+}
+
+impl Badges {
+    /// Starts reading badges, if a reader is configured. Returns `None` if badges are turned off.
+    ///
+    /// Badges stay turned on even if the reader doesn't work, so nobody can order without one until it's fixed.
+    pub fn new(state: &Arc<SharedState>, egui_ctx: &egui::Context) -> Option<Badges> {
+        let config = &state.config.badges;
+        let mut simulated = None;
+        let open_reader: OpenReader = match config.reader? {
+            BadgeReaderKind::Rc522 => {
+                let chip_select = config.chip_select;
+                Box::new(move || Ok(Box::new(Rc522::new(chip_select)?)))
+            }
+            BadgeReaderKind::Simulated => {
+                let (sender, receiver) = mpsc::channel();
+                simulated = Some(sender);
+                // the simulated reader never fails, so it's only opened once
+                let mut receiver = Some(receiver);
+                Box::new(move || {
+                    let receiver = receiver.take().unwrap_or_else(|| mpsc::channel().1);
+                    Ok(Box::new(SimulatedReader::new(receiver)))
+                })
+            }
+        };
+        let (taps, thread) = match spawn_badge_thread(Arc::clone(state), egui_ctx.clone(), open_reader) {
+            Some((taps, thread)) => (taps, Some(thread)),
+            // with no thread, the reader never becomes ready, so orders are refused
+            None => (mpsc::channel().1, None),
+        };
+
+        let mut usage: DailyUsage = load_toml(&config.usage_file)
+            .unwrap_or_else(|err| {
+                eprintln!("could not load badge usage: {}", err);
+                None
+            })
+            .unwrap_or_default();
+        usage.roll_over();
+        Some(Self { taps, simulated, thread, simulated_uid: String::new(), user: None, usage, message: None })
+    }
+
+    /// Wakes up the badge thread, e.g. so it tries the reader again after the fault is cleared.
+    pub fn unpark(&self) {
+        if let Some(thread) = &self.thread {
+            thread.unpark();
+        }
+    }
+
+    /// Logs out whoever tapped their badge.
+    pub fn log_out(&mut self) {
+        self.user = None;
+    }
+
+    /// Returns what the current user can still take today.
+    fn allowance(&self, config: &BadgesConfig) -> Option<Allowance> {
+        let taken = self.usage.taken.get(self.user.as_ref()?).copied().unwrap_or_default();
+        let left = |limit: Option<u64>, taken: u64| limit.map(|limit| limit.saturating_sub(taken));
+        Some(Allowance {
+            total: left(config.daily_limit, taken[0].saturating_add(taken[1])),
+            slots: [left(config.red_daily_limit, taken[0]), left(config.green_daily_limit, taken[1])],
+        })
+    }
+}
+
+/// Starts a thread that reads badges, returning the receiving end for the UIDs of tapped badges, and the thread.
+fn spawn_badge_thread(
+    state: Arc<SharedState>,
+    egui_ctx: egui::Context,
+    open_reader: OpenReader,
+) -> Option<(Receiver<String>, Thread)> {
+    let (sender, receiver) = mpsc::channel();
+    let result = thread::Builder::new()
+        .name("Badges".to_owned())
+        .spawn(move || run_badge_thread(&state, &egui_ctx, &sender, open_reader));
+    match result {
+        Ok(handle) => Some((receiver, handle.thread().clone())),
+        Err(err) => {
+            eprintln!("could not start badge thread: {}", err);
+            None
+        }
+    }
+}
+
+/// Reads badges until the app exits. If the reader fails, it's tried again once the fault is cleared.
+fn run_badge_thread(
+    state: &SharedState,
+    egui_ctx: &egui::Context,
+    sender: &Sender<String>,
+    mut open_reader: OpenReader,
+) {
+    loop {
+        match read_badges(state, egui_ctx, sender, &mut open_reader) {
+            Ok(()) => break,
+            Err(err) => {
+                // nobody can order without the reader, so this needs to be fixed
+                state.badge_reader_ready.store(false, Ordering::SeqCst);
+                state.report_fault("Badge reader", &err);
+                egui_ctx.request_repaint();
+                if state.wait_fault_cleared() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Sets up the reader and sends the UID of each tapped badge, until the app exits (or the GUI is gone).
+fn read_badges(
+    state: &SharedState,
+    egui_ctx: &egui::Context,
+    sender: &Sender<String>,
+    open_reader: &mut OpenReader,
+) -> Result<()> {
+    let mut reader = open_reader()?;
+    state.badge_reader_ready.store(true, Ordering::SeqCst);
+    egui_ctx.request_repaint();
+
+    // last badge seen, and when; a badge held on the reader only counts as one tap
+    let mut last_seen: Option<(String, Instant)> = None;
+    while !state.exit_flag.load(Ordering::SeqCst) {
+        if let Some(uid) = reader.poll()? {
+            let is_new = !matches!(&last_seen, Some((last, seen)) if *last == uid && seen.elapsed() <= ABSENT_TIME);
+            last_seen = Some((uid.clone(), Instant::now()));
+            if is_new {
+                if sender.send(uid).is_err() {
+                    return Ok(());
+                }
+                egui_ctx.request_repaint();
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+impl Application {
+    /// Handles badges tapped since the last frame.
+    pub(super) fn handle_badges(&mut self) {
+        let Some(badges) = &mut self.badges else {
+            return;
+        };
+        badges.usage.roll_over();
+        let config = &self.shared_state.config.badges;
+
+        // whoever was charged for an order that didn't finish gets the rest of it back
+        let returned = std::mem::take(&mut *self.shared_state.returned_items.lock().unwrap());
+        if !returned.is_empty() {
+            for (user, items) in returned {
+                badges.usage.give_back(&user, items);
+            }
+            if let Err(err) = save_toml(&config.usage_file, &badges.usage) {
+                eprintln!("could not save badge usage: {}", err);
+            }
+        }
+
+        // nobody has touched the dispenser for a while, so whoever tapped their badge has probably walked off
+        let logout = Duration::from_secs(config.idle_logout_secs);
+        if config.idle_logout_secs > 0 && self.last_activity.elapsed() >= logout {
+            badges.log_out();
+        }
+
+        let users = &config.users;
+        for uid in badges.taps.try_iter() {
+            self.last_activity = Instant::now();
+            match users.get(&uid) {
+                Some(name) => {
+                    badges.user = Some(name.clone());
+                    badges.message = None;
+                }
+                None => {
+                    badges.user = None;
+                    badges.message = Some(self.language.format(Msg::UnknownBadge, &[&uid]));
+                }
+            }
+        }
+    }
+
+    /// Returns true if the order on the counters is allowed for whoever tapped their badge.
+    /// Always true if badges aren't turned on, and always false if they are but the reader isn't working.
+    pub(super) fn badge_allows_order(&self) -> bool {
+        match &self.badges {
+            Some(_) if !self.shared_state.badge_reader_ready.load(Ordering::SeqCst) => false,
+            Some(badges) => badges
+                .allowance(&self.shared_state.config.badges)
+                .is_some_and(|allowance| allowance.allows(self.cnt_red.count(), self.cnt_green.count())),
+            None => true,
+        }
+    }
+
+    /// Adds an order that's being started to the current user's usage, and logs them out.
+    /// Whatever isn't dispensed is given back to them when the order ends.
+    pub(super) fn record_badge_order(&mut self, red: u64, green: u64) {
+        let mut order_badge = self.shared_state.order_badge.lock().unwrap();
+        *order_badge = None;
+        let Some(badges) = &mut self.badges else {
+            return;
+        };
+        let Some(user) = badges.user.take() else {
+            return;
+        };
+        badges.usage.take(&user, [red, green]);
+        if let Err(err) = save_toml(&self.shared_state.config.badges.usage_file, &badges.usage) {
+            eprintln!("could not save badge usage: {}", err);
+        }
+        *order_badge = Some(user);
+    }
+
+    /// Shows who's ordering and what they have left, on the order screen.
+    pub(super) fn badge_ui(&mut self, ui: &mut Ui) {
+        let Some(badges) = &mut self.badges else {
+            return;
+        };
+        let config = &self.shared_state.config.badges;
+        let lang = self.language;
+        if !self.shared_state.badge_reader_ready.load(Ordering::SeqCst) {
+            ui.label(lang.text(Msg::BadgeReaderDown));
+            return;
+        }
+        let limit_text =
+            |limit: Option<u64>| limit.map_or(lang.text(Msg::NoLimit).to_owned(), |limit| lang.format_number(limit));
+        match (&badges.user, badges.allowance(config)) {
+            (Some(user), Some(allowance)) => {
+                ui.label(lang.format(
+                    Msg::BadgeGreeting,
+                    &[
+                        user,
+                        &limit_text(allowance.total),
+                        &limit_text(allowance.slots[0]),
+                        &limit_text(allowance.slots[1]),
+                    ],
+                ));
+                if !allowance.allows(self.cnt_red.count(), self.cnt_green.count()) {
+                    ui.label(lang.text(Msg::OverBadgeLimit));
+                }
+                if ui.add(Button::new(lang.text(Msg::LogOut)).min_size(self.theme.button_size())).clicked() {
+                    badges.log_out();
+                }
+            }
+            _ => {
+                ui.label(lang.text(Msg::TapBadge));
+            }
+        }
+        if let Some(msg) = &badges.message {
+            ui.label(msg);
+        }
+    }
+
+    /// Lets a badge tap be simulated from the admin screen, if the reader is simulated.
+    pub(super) fn badge_admin_ui(&mut self, ui: &mut Ui) {
+        let Some(badges) = &mut self.badges else {
+            return;
+        };
+        let Some(simulated) = &badges.simulated else {
+            return;
+        };
+        let lang = self.language;
+        ui.horizontal(|ui| {
+            ui.label(lang.text(Msg::BadgeUid));
+            ui.add(TextEdit::singleline(&mut badges.simulated_uid).desired_width(100.0));
+            if ui.add(Button::new(lang.text(Msg::SimulateTap))).clicked() {
+                let _ = simulated.send(badges.simulated_uid.trim().to_ascii_uppercase());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn allows_orders_within_limits() {
+        let allowance = Allowance { total: Some(5), slots: [Some(3), None] };
+        assert!(allowance.allows(3, 2));
+        assert!(allowance.allows(0, 5));
+        assert!(!allowance.allows(4, 0));
+        assert!(!allowance.allows(3, 3));
+        // counts that would overflow when added up are still over the limit
+        assert!(!allowance.allows(1, u64::MAX));
+
+        let unlimited = Allowance { total: None, slots: [None, None] };
+        assert!(unlimited.allows(u64::MAX, u64::MAX));
+        let used_up = Allowance { total: Some(0), slots: [None, None] };
+        assert!(used_up.allows(0, 0));
+        assert!(!used_up.allows(1, 0));
+    }
+
+    #[test]
+    fn starts_over_on_a_new_day() {
+        let mut usage = DailyUsage::default();
+        usage.roll_over_to("2024-06-17".to_owned());
+        usage.take("Alice", [2, 1]);
+        usage.roll_over_to("2024-06-17".to_owned());
+        assert_eq!(usage.taken.get("Alice"), Some(&[2, 1]));
+
+        usage.roll_over_to("2024-06-18".to_owned());
+        assert_eq!(usage.date, "2024-06-18");
+        assert!(usage.taken.is_empty());
+    }
+
+    #[test]
+    fn gives_back_undispensed_items() {
+        let mut usage = DailyUsage::default();
+        usage.take("Alice", [3, 2]);
+        usage.give_back("Alice", [1, 5]);
+        assert_eq!(usage.taken.get("Alice"), Some(&[2, 0]));
+        // someone who hasn't taken anything today has nothing to give back
+        usage.give_back("Bob", [1, 1]);
+        assert!(!usage.taken.contains_key("Bob"));
+    }
+
+    /// Waits up to a few seconds for something to become true.
+    fn wait_for(what: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !what() {
+            if start.elapsed() > Duration::from_secs(5) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    #[test]
+    fn tries_the_reader_again_after_a_fault() {
+        let state = Arc::new(SharedState::default());
+        let (tap_sender, tap_receiver) = mpsc::channel();
+        // the reader fails to start the first time, then works
+        let mut tries = 0;
+        let mut tap_receiver = Some(tap_receiver);
+        let open_reader: OpenReader = Box::new(move || {
+            tries += 1;
+            match tap_receiver.take() {
+                Some(receiver) if tries > 1 => Ok(Box::new(SimulatedReader::new(receiver))),
+                receiver => {
+                    tap_receiver = receiver;
+                    Err(Error::NotPwmPin(0))
+                }
+            }
+        });
+        let (taps, thread) =
+            spawn_badge_thread(Arc::clone(&state), egui::Context::default(), open_reader).unwrap();
+
+        assert!(wait_for(|| state.fault.lock().unwrap().is_some()));
+        assert!(!state.badge_reader_ready.load(Ordering::SeqCst));
+
+        // once the fault is cleared, the reader starts working
+        *state.fault.lock().unwrap() = None;
+        thread.unpark();
+        assert!(wait_for(|| state.badge_reader_ready.load(Ordering::SeqCst)));
+        tap_sender.send("04A1B2C3".to_owned()).unwrap();
+        assert_eq!(taps.recv_timeout(Duration::from_secs(5)).unwrap(), "04A1B2C3");
+
+        state.exit_flag.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,122 @@
+/*
+gui/coin_thread.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Implementation of the coin thread, which counts pulses from the coin acceptor and adds them to the credit.
+
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::coin::{CoinAcceptor, PulseSource, SimulatedPulses};
+
+use super::SharedState;
+
+/// How often the pulse sources are checked. Coin acceptor pulses are usually 20-100 ms long.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Starts the coin thread, if there's a coin acceptor or simulated pulses are turned on.
+/// Returns the sending end for simulated pulses, if they're turned on.
+pub(super) fn spawn_coin_thread(state: Arc<SharedState>, egui_ctx: egui::Context) -> Option<Sender<u32>> {
+    let config = &state.config.payment;
+    let mut sources: Vec<Box<dyn PulseSource + Send>> = Vec::new();
+    if let Some(pin) = config.pin {
+        match CoinAcceptor::new(pin, config.active_high, Duration::from_millis(config.debounce_ms)) {
+            Ok(acceptor) => sources.push(Box::new(acceptor)),
+            Err(err) => eprintln!("could not set up coin acceptor: {}", err),
+        }
+    }
+    let mut simulated = None;
+    if config.simulate {
+        let (sender, receiver) = mpsc::channel();
+        sources.push(Box::new(SimulatedPulses::new(receiver)));
+        simulated = Some(sender);
+    }
+    if sources.is_empty() {
+        return None;
+    }
+
+    let result = thread::Builder::new().name("Coins".to_owned()).spawn(move || {
+        let pulse_value = state.config.payment.pulse_value;
+        while !state.exit_flag.load(Ordering::SeqCst) {
+            let paid = poll_credit(&mut sources, pulse_value);
+            if paid > 0 {
+                state.add_credit(paid);
+                egui_ctx.request_repaint();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    match result {
+        Ok(_) => simulated,
+        Err(err) => {
+            eprintln!("could not start coin thread: {}", err);
+            None
+        }
+    }
+}
+
+/// Checks every pulse source, and returns the credit paid in since the last check.
+fn poll_credit(sources: &mut [Box<dyn PulseSource + Send>], pulse_value: u64) -> u64 {
+    let pulses = sources.iter_mut().map(|source| source.poll() as u64).fold(0, u64::saturating_add);
+    pulses.saturating_mul(pulse_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn counts_credit_from_every_source() {
+        let (sender_a, receiver_a) = mpsc::channel();
+        let (sender_b, receiver_b) = mpsc::channel();
+        let mut sources: Vec<Box<dyn PulseSource + Send>> =
+            vec![Box::new(SimulatedPulses::new(receiver_a)), Box::new(SimulatedPulses::new(receiver_b))];
+        assert_eq!(poll_credit(&mut sources, 5), 0);
+
+        // a quarter on one acceptor and a dime on the other
+        sender_a.send(5).unwrap();
+        sender_b.send(1).unwrap();
+        sender_b.send(1).unwrap();
+        assert_eq!(poll_credit(&mut sources, 5), 35);
+        // pulses are only counted once
+        assert_eq!(poll_credit(&mut sources, 5), 0);
+    }
+
+    #[test]
+    fn does_not_overflow_credit() {
+        let (sender, receiver) = mpsc::channel();
+        let mut sources: Vec<Box<dyn PulseSource + Send>> = vec![Box::new(SimulatedPulses::new(receiver))];
+        sender.send(u32::MAX).unwrap();
+        assert_eq!(poll_credit(&mut sources, u64::MAX), u64::MAX);
+        // nor does a pile of pulses between polls
+        sender.send(u32::MAX).unwrap();
+        sender.send(u32::MAX).unwrap();
+        assert_eq!(poll_credit(&mut sources, 1), u32::MAX as u64);
+    }
+
+    #[test]
+    fn does_not_overflow_saved_credit() {
+        let mut state = SharedState::default();
+        let path = env::temp_dir().join(format!("dispenser-credit-{}.toml", process::id()));
+        state.config.persist.credit_file = path.to_string_lossy().into_owned();
+        state.credit.store(u64::MAX - 1, Ordering::SeqCst);
+        state.add_credit(5);
+        assert_eq!(state.credit.load(Ordering::SeqCst), u64::MAX);
+        state.spend_credit(u64::MAX);
+        state.spend_credit(1);
+        assert_eq!(state.credit.load(Ordering::SeqCst), 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -8,21 +8,19 @@ Date: Jun. 17, 2024
 //! Implementation of the counter on the GUI.
 
 
-use egui::{Align, Button, Layout, TextEdit, Ui, Vec2, Widget};
+use egui::{Align, Align2, Button, Color32, Grid, Layout, RichText, Ui, Vec2, Widget, Window};
+
+use crate::i18n::{Language, Msg};
+
+/// Most digits that can be typed on the keypad. This keeps the number well away from overflowing.
+const MAX_DIGITS: usize = 9;
 
 /// State associated with a counter widget.
+#[derive(Default)]
 pub struct CounterState {
-    text: String,
     count: u64,
-}
-
-impl Default for CounterState {
-    fn default() -> Self {
-        Self {
-            text: "0".to_owned(),
-            count: 0,
-        }
-    }
+    // Digits typed so far while the keypad is open, or None if it's closed.
+    keypad: Option<String>,
 }
 
 impl CounterState {
@@ -34,22 +32,36 @@ impl CounterState {
     /// Changes the count on the counter.
     pub fn set_count(&mut self, count: u64) {
         self.count = count;
-        self.text = count.to_string();
     }
 }
 
-/// Widget representing a counter that has two buttons for +1/-1 and a text box to edit the value.
+/// Widget representing a counter that has two buttons for +1/-1, and a field that opens a keypad to type
+/// in the value. There's no keyboard on the touchscreen, so the keypad is drawn by the app itself.
 pub struct Counter<'a, 'b> {
     state: &'a mut CounterState,
-    header: Option<&'b str>
+    header: Option<&'b str>,
+    error: Option<&'b str>,
+    accent: Option<Color32>,
+    language: Language,
 }
 
 impl<'a, 'b> Counter<'a, 'b> {
     /// Creates a counter based on the provided state.
     pub fn new(state: &'a mut CounterState) -> Self {
         Self {
-            state: state,
+            state,
             header: None,
+            error: None,
+            accent: None,
+            language: Language::default(),
+        }
+    }
+
+    /// Shows the count and keypad in a language other than English.
+    pub fn with_language(self, language: Language) -> Self {
+        Self {
+            language,
+            ..self
         }
     }
 
@@ -60,6 +72,75 @@ impl<'a, 'b> Counter<'a, 'b> {
             ..self
         }
     }
+
+    /// Colours the header and +1/-1 buttons of this counter, e.g. to match what's in the slot.
+    pub fn with_accent(self, accent: Option<Color32>) -> Self {
+        Self {
+            accent,
+            ..self
+        }
+    }
+
+    /// Shows an error under this counter, e.g. when the count is over a limit.
+    pub fn with_error(self, error: Option<&'b str>) -> Self {
+        Self {
+            error,
+            ..self
+        }
+    }
+}
+
+/// Draws the keypad for a counter in a window over everything else. Returns the typed number once OK is
+/// pressed, and closes the keypad when it's done.
+fn keypad_ui(ui: &Ui, title: &str, lang: Language, keypad: &mut Option<String>) -> Option<u64> {
+    let entry = keypad.as_mut()?;
+    let mut done = false;
+    let mut cancelled = false;
+    let size = Vec2::new(50.0, 40.0);
+
+    Window::new(title)
+        .id(ui.id().with("keypad"))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ui.ctx(), |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading(if entry.is_empty() { "0" } else { entry.as_str() });
+            });
+            Grid::new("keys").show(ui, |ui| {
+                let (clear, delete) = (lang.text(Msg::Clear), lang.text(Msg::Delete));
+                for row in [["1", "2", "3"], ["4", "5", "6"], ["7", "8", "9"], [clear, "0", delete]] {
+                    for key in row {
+                        if !ui.add(Button::new(key).min_size(size)).clicked() {
+                            continue;
+                        }
+                        match key {
+                            key if key == clear => entry.clear(),
+                            key if key == delete => {
+                                entry.pop();
+                            }
+                            // no leading zeros, they'd just look odd
+                            "0" if entry.is_empty() => {}
+                            digit if entry.len() < MAX_DIGITS => entry.push_str(digit),
+                            _ => {}
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                cancelled = ui.add(Button::new(lang.text(Msg::Cancel)).min_size(Vec2::new(75.0, 40.0))).clicked();
+                done = ui.add(Button::new(lang.text(Msg::Ok)).min_size(Vec2::new(75.0, 40.0))).clicked();
+            });
+        });
+
+    // an empty keypad means 0, same as what it shows
+    let typed = done.then(|| entry.parse().unwrap_or(0));
+    // the keypad is closed either way, but only OK changes the count
+    if done || cancelled {
+        *keypad = None;
+    }
+    typed
 }
 
 impl<'a, 'b> Widget for Counter<'a, 'b> {
@@ -70,43 +151,51 @@ impl<'a, 'b> Widget for Counter<'a, 'b> {
             Vec2::new(100.0, 150.0),
             Layout::top_down(Align::Center),
             |ui| {
-                let mut count_update = false;
-
                 // if a heading was specified, draw it
                 if let Some(header) = self.header {
-                    ui.heading(header);
+                    match self.accent {
+                        Some(accent) => ui.heading(RichText::new(header).color(accent)),
+                        None => ui.heading(header),
+                    };
                 }
+                // +1/-1 take on the accent colour, if there is one
+                let step_button = |text: &str| match self.accent {
+                    Some(accent) => Button::new(RichText::new(text).color(Color32::WHITE)).fill(accent),
+                    None => Button::new(text),
+                };
 
                 // +1 button
-                if ui.add(Button::new("+1")).clicked() {
+                if ui.add(step_button("+1")).clicked() {
                     // increments the counter, checking against integer limit
                     // in practice we should never hit the integer limit, but Rust wants us to check anyways
-                    if self.state.count != u64::MAX {
-                        self.state.count += 1;
-                    }
-                    count_update = true;
+                    self.state.count = self.state.count.saturating_add(1);
                 }
-                // text box containing the number
-                if ui.add(TextEdit::singleline(&mut self.state.text)).lost_focus() {
-                    // if the user types in a number, then mouses off, they can set it only if it's a valid number
-                    if let Ok(value) = self.state.text.parse::<u64>() {
-                        self.state.count = value;
-                    }
-                    count_update = true;
+                // the number itself, which opens the keypad when tapped
+                let text = self.language.format_number(self.state.count);
+                if ui.add(Button::new(text).min_size(Vec2::new(80.0, 0.0))).clicked() {
+                    self.state.keypad = Some(String::new());
                 }
                 // -1 button
-                if ui.add(Button::new("-1")).clicked() {
+                if ui.add(step_button("-1")).clicked() {
                     // decrements the counter, checking against 0
                     // 0 check is needed to prevent logic errors
                     if self.state.count != 0 {
                         self.state.count -= 1;
                     }
-                    count_update = true;
                 }
 
-                // if the count was changed at all, update the text box
-                if count_update {
-                    self.state.text = self.state.count.to_string();
+                // the counter can be disabled while the keypad is open (e.g. an order started from the panel)
+                if !ui.is_enabled() {
+                    self.state.keypad = None;
+                }
+                let title = self.header.unwrap_or(self.language.text(Msg::Count));
+                if let Some(typed) = keypad_ui(ui, title, self.language, &mut self.state.keypad) {
+                    self.state.count = typed;
+                }
+
+                // errors go right under the counter they're about
+                if let Some(error) = self.error {
+                    ui.colored_label(Color32::RED, error);
                 }
             },
         )
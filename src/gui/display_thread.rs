@@ -0,0 +1,186 @@
+/*
+gui/display_thread.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Implementation of the display thread, which shows the order and any errors on the front panel display.
+
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    config::DisplayConfig,
+    display::{DisplayKind, FrontPanel, Hd44780, MemoryPanel, Ssd1306},
+    error::Result,
+};
+
+use super::SharedState;
+
+/// Time between updates of the display.
+const UPDATE_TIME: Duration = Duration::from_millis(100);
+/// Number of updates between each step of scrolling a line that's too long.
+const SCROLL_UPDATES: usize = 4;
+
+/// Starts the display thread, if a display is configured. If it can't be set up, the dispenser works fine
+/// without it, so the error is only logged.
+pub(super) fn spawn_display_thread(state: Arc<SharedState>) {
+    let panel = match open_panel(&state.config.display) {
+        Ok(Some(panel)) => panel,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("could not set up front panel display: {}", err);
+            return;
+        }
+    };
+
+    let result = thread::Builder::new()
+        .name("Display".to_owned())
+        .spawn(move || run_display(&state, panel));
+    if let Err(err) = result {
+        eprintln!("could not start display thread: {}", err);
+    }
+}
+
+/// Opens the configured display.
+fn open_panel(config: &DisplayConfig) -> Result<Option<Box<dyn FrontPanel + Send>>> {
+    Ok(match config.kind {
+        None => None,
+        Some(DisplayKind::Hd44780) => Some(Box::new(Hd44780::new(
+            config.bus,
+            config.address.unwrap_or(Hd44780::DEFAULT_ADDRESS),
+        )?)),
+        Some(DisplayKind::Ssd1306) => Some(Box::new(Ssd1306::new(
+            config.bus,
+            config.address.unwrap_or(Ssd1306::DEFAULT_ADDRESS),
+        )?)),
+    })
+}
+
+/// Works out what the display should say.
+fn panel_lines(state: &SharedState) -> Vec<String> {
+    if state.estop.load(Ordering::SeqCst) {
+        return vec!["EMERGENCY STOP".to_owned(), "Reset from admin".to_owned()];
+    }
+    if let Some(fault) = &*state.fault.lock().unwrap() {
+        return vec!["FAULT".to_owned(), fault.clone()];
+    }
+    if state.is_processing.load(Ordering::SeqCst) {
+        let progress = state.progress.lock().unwrap().unwrap_or_default();
+        let status = if state.pause_flag.load(Ordering::SeqCst) { "Paused" } else { "Dispensing..." };
+        return vec![
+            format!(
+                "R {}/{} G {}/{}",
+                progress.dispensed[0], progress.counts[0], progress.dispensed[1], progress.counts[1],
+            ),
+            status.to_owned(),
+        ];
+    }
+    let (red, green) = *state.draft_order.lock().unwrap();
+    vec![format!("R {}  G {}", red, green), "Press START".to_owned()]
+}
+
+/// Scrolls a line sideways if it's too long to fit, so the whole thing can be read.
+fn scroll(line: &str, cols: usize, step: usize) -> String {
+    if line.chars().count() <= cols {
+        return line.to_owned();
+    }
+    // leave a gap between the end of the line and the start coming around again
+    let looped: Vec<char> = line.chars().chain("   ".chars()).collect();
+    let start = step % looped.len();
+    looped.iter().cycle().skip(start).take(cols).collect()
+}
+
+/// Updates the display until the app exits, then clears it.
+fn run_display(state: &SharedState, mut panel: Box<dyn FrontPanel + Send>) {
+    let (cols, rows) = panel.size();
+    // what's on the real display, so it's only redrawn when something changes
+    let mut shown = MemoryPanel::new(cols, rows);
+    let mut next = MemoryPanel::new(cols, rows);
+    let mut updates = 0;
+    let mut first = true;
+
+    while !state.exit_flag.load(Ordering::SeqCst) {
+        let lines: Vec<String> = panel_lines(state)
+            .iter()
+            .map(|line| scroll(line, cols, updates / SCROLL_UPDATES))
+            .collect();
+        // drawing to memory can't fail
+        let _ = next.show(&lines);
+        if first || next.lines() != shown.lines() {
+            if let Err(err) = panel.show(next.lines()) {
+                eprintln!("front panel display stopped working: {}", err);
+                return;
+            }
+            std::mem::swap(&mut shown, &mut next);
+            first = false;
+        }
+        updates += 1;
+        thread::sleep(UPDATE_TIME);
+    }
+
+    if let Err(err) = panel.show(&[]) {
+        eprintln!("could not clear front panel display: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::persist::OrderProgress;
+
+    /// Renders what the display thread would show on a 16x2 display.
+    fn render(state: &SharedState) -> Vec<String> {
+        let mut panel = MemoryPanel::new(16, 2);
+        panel.show(&panel_lines(state)).unwrap();
+        panel.lines().to_vec()
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn shows_draft_order_when_idle() {
+        let state = SharedState::default();
+        *state.draft_order.lock().unwrap() = (3, 12);
+        assert_eq!(render(&state), lines(&["R 3  G 12       ", "Press START     "]));
+    }
+
+    #[test]
+    fn shows_progress() {
+        let state = SharedState::default();
+        state.is_processing.store(true, Ordering::SeqCst);
+        let mut progress = OrderProgress::new(5, 2);
+        progress.dispensed = [5, 1];
+        *state.progress.lock().unwrap() = Some(progress);
+        assert_eq!(render(&state), lines(&["R 5/5 G 1/2     ", "Dispensing...   "]));
+
+        state.pause_flag.store(true, Ordering::SeqCst);
+        assert_eq!(render(&state), lines(&["R 5/5 G 1/2     ", "Paused          "]));
+    }
+
+    #[test]
+    fn shows_faults_over_progress() {
+        let state = SharedState::default();
+        state.is_processing.store(true, Ordering::SeqCst);
+        *state.fault.lock().unwrap() = Some("GPIO fault: servo".to_owned());
+        assert_eq!(render(&state), lines(&["FAULT           ", "GPIO fault: serv"]));
+
+        state.estop.store(true, Ordering::SeqCst);
+        assert_eq!(render(&state), lines(&["EMERGENCY STOP  ", "Reset from admin"]));
+    }
+
+    #[test]
+    fn scrolls_long_lines() {
+        assert_eq!(scroll("short", 8, 3), "short");
+        assert_eq!(scroll("abcdefghij", 8, 0), "abcdefgh");
+        assert_eq!(scroll("abcdefghij", 8, 4), "efghij  ");
+        // wraps back around to the start after the gap
+        assert_eq!(scroll("abcdefghij", 8, 13), "abcdefgh");
+    }
+}
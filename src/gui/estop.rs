@@ -0,0 +1,80 @@
+/*
+gui/estop.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Monitoring of the emergency stop input.
+//!
+//! Pressing the E-stop latches a fault: the current order is abandoned, the servos are let go wherever they are,
+//! and the buzzers are turned off. Nothing runs again until the E-stop is released and reset from the admin screen.
+//!
+//! If the E-stop input can't be watched at all, the E-stop is latched for good: it can't be reset from the
+//! admin screen, only by fixing the input and restarting the app.
+
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::gpio::DebouncedInput;
+
+use super::{music_thread::force_disable_buzzers, SharedState};
+
+/// How often the E-stop input is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Starts a thread that watches the E-stop input, if one is configured. If the input can't be set up, the
+/// E-stop is latched until the app restarts, since the dispenser shouldn't run without a working E-stop.
+pub(super) fn spawn_estop_thread(state: Arc<SharedState>, egui_ctx: egui::Context) {
+    let Some(pin) = state.config.estop.pin else {
+        return;
+    };
+    let debounce = Duration::from_millis(state.config.estop.debounce_ms);
+    let mut input = match DebouncedInput::new(pin, state.config.estop.active_high, debounce) {
+        Ok(input) => input,
+        Err(err) => {
+            mark_unavailable(&state, &err.to_string());
+            return;
+        }
+    };
+
+    let thread_state = Arc::clone(&state);
+    let result = thread::Builder::new().name("E-stop".to_owned()).spawn(move || {
+        let state = thread_state;
+        while !state.exit_flag.load(Ordering::SeqCst) {
+            let pressed = input.poll();
+            if state.estop_pressed.swap(pressed, Ordering::SeqCst) != pressed {
+                egui_ctx.request_repaint();
+            }
+            if pressed && !state.estop.swap(true, Ordering::SeqCst) {
+                trigger_estop(&state);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    if let Err(err) = result {
+        mark_unavailable(&state, &format!("could not start E-stop thread: {}", err));
+    }
+}
+
+/// Latches the E-stop for good, because nothing is watching the E-stop input.
+fn mark_unavailable(state: &SharedState, reason: &str) {
+    eprintln!("E-stop unavailable: {}", reason);
+    state.estop_unavailable.store(true, Ordering::SeqCst);
+    state.estop.store(true, Ordering::SeqCst);
+    *state.fault.lock().unwrap() = Some(format!("E-stop unavailable: {}", reason));
+}
+
+/// Stops everything as fast as possible. The worker threads notice the latched E-stop and let go of
+/// their hardware; the buzzers are also turned off directly, in case the music thread is stuck.
+fn trigger_estop(state: &SharedState) {
+    eprintln!("EMERGENCY STOP");
+    *state.fault.lock().unwrap() = Some("Emergency stop pressed".to_owned());
+    state.cancel_flag.store(true, Ordering::SeqCst);
+    state.unpark_workers();
+    force_disable_buzzers(&state.config.music.buzzer_pins);
+}
+
@@ -7,37 +7,124 @@ Date: Jun. 17, 2024
 
 //! Implementation of the GPIO thread, which controls the servos and dispatches orders.
 
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::{Duration, Instant}};
+use std::{sync::{atomic::Ordering, Arc}, thread, time::Duration};
 
-use crate::{gpio::ServoSg90, wait_interruptible, wait_pausable};
+use crate::{
+    error::{Error, Result},
+    gpio::ServoSg90,
+    wait_interruptible, wait_pausable,
+};
 
-use super::SharedState;
+use super::{
+    history::record_order,
+    persist::{save_toml, Calibration, OrderProgress},
+    SharedState,
+};
+
+/// Pins the servos for each slot are attached to.
+const SERVO_A_PIN: u8 = 17;
+const SERVO_B_PIN: u8 = 27;
 
 /// Function for the GPIO thread, which controls the servos and dispatches orders.
 /// ## Parameters
 /// - `state`: Shared state from the GUI.
 /// - `egui_ctx`: GUI context, also obtained from the GUI.
 pub(crate) fn run_gpio_thread(state: Arc<SharedState>, egui_ctx: egui::Context) {
+    loop {
+        let result = run_orders(&state, &egui_ctx);
+        // whether it finished or failed, no order is being processed anymore
+        finish_order(&state, &egui_ctx);
+        match result {
+            Ok(()) => break,
+            Err(err) => {
+                // the servos have been reset as far as possible, so wait for someone to clear the fault and try again
+                state.gpio_ready.store(false, Ordering::SeqCst);
+                state.report_fault("GPIO", &err);
+                egui_ctx.request_repaint();
+                if state.wait_fault_cleared() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Signals that the current order is over.
+fn finish_order(state: &SharedState, egui_ctx: &egui::Context) {
+    reset_order_state(state);
+    egui_ctx.request_repaint();
+}
+
+/// Clears the current order and all flags that go with it.
+fn reset_order_state(state: &SharedState) {
+    *state.next_order.lock().unwrap() = None;
+    *state.progress.lock().unwrap() = None;
+    state.is_processing.store(false, Ordering::SeqCst);
+    state.test_order.store(false, Ordering::SeqCst);
+    state.cancel_flag.store(false, Ordering::SeqCst);
+    state.pause_flag.store(false, Ordering::SeqCst);
+}
+
+/// Cleans up after the GPIO thread has crashed: the order it was running is dropped (and refunded), and the
+/// servos are moved back to their rest positions. The servos are parked on a separate thread so the GUI doesn't freeze,
+/// and are released before the GPIO thread is restarted. While the E-stop is latched, the servos are left
+/// where they are.
+pub(super) fn recover_gpio(state: &SharedState) {
+    let progress = *state.progress.lock().unwrap();
+    if let Some(progress) = progress.filter(|_| !state.test_order.load(Ordering::SeqCst)) {
+        state.refund_order(&progress);
+    }
+    reset_order_state(state);
+    if state.estop.load(Ordering::SeqCst) {
+        return;
+    }
+    let rest_pos = state.calibration.lock().unwrap().rest_pos;
+    thread::spawn(move || {
+        let result = ServoSg90::new(SERVO_A_PIN, rest_pos[0]).and_then(|servo_a| {
+            let servo_b = ServoSg90::new(SERVO_B_PIN, rest_pos[1])?;
+            // give the servos time to get there before letting go of them
+            thread::sleep(Duration::from_millis(500));
+            drop((servo_a, servo_b));
+            Ok(())
+        });
+        if let Err(err) = result {
+            eprintln!("could not park servos: {}", err);
+        }
+    });
+}
+
+/// Waits for orders and runs them until the app exits. If a servo returns an error, the servos are
+/// reset to their rest positions and the error is returned.
+fn run_orders(state: &SharedState, egui_ctx: &egui::Context) -> Result<()> {
     let mut next_order: Option<(u64, u64)>;
     let mut cur_exit: bool;
 
-    let mut servo_a = ServoSg90::new(17, 0.0).expect("Could not bind servo at pin 17");
-    let mut servo_b = ServoSg90::new(27, 0.0).expect("Could not bind servo at pin 27");
+    // setting up the servos moves them to rest, which mustn't happen while the E-stop is latched
+    if state.estop.load(Ordering::SeqCst) {
+        return Err(Error::EmergencyStop);
+    }
+    let rest_pos = state.calibration.lock().unwrap().rest_pos;
+    let mut servo_a = ServoSg90::new(SERVO_A_PIN, rest_pos[0])?;
+    let mut servo_b = ServoSg90::new(SERVO_B_PIN, rest_pos[1])?;
+    state.gpio_ready.store(true, Ordering::SeqCst);
 
     // main loop
     loop {
         // two things we're checking: whether we should exit or whether we have an order to run
         next_order = *state.next_order.lock().unwrap();
         cur_exit = state.exit_flag.load(Ordering::SeqCst);
-        // wait for either of these things to change
+        // wait for either of these things to change (or the E-stop)
         while let (None, false) = (next_order, cur_exit) {
+            if state.estop.load(Ordering::SeqCst) {
+                return Err(Error::EmergencyStop);
+            }
             thread::park();
             next_order = *state.next_order.lock().unwrap();
             cur_exit = state.exit_flag.load(Ordering::SeqCst);
         }
         // if we're requested to exit the app, break
         if cur_exit {
-            break;
+            return Ok(());
         }
 
         // otherwise we must have an order, start processing it
@@ -47,54 +134,151 @@ pub(crate) fn run_gpio_thread(state: Arc<SharedState>, egui_ctx: egui::Context)
         let (red_count, green_count) = next_order.expect("We should have an order!");
         println!("ORDER: {}, {}", red_count, green_count);
 
-        // execute the order. Any sleep must be replaced with a park (so that it can be interrupted)
-        let wait_fn = || state.exit_flag.load(Ordering::SeqCst) || state.cancel_flag.load(Ordering::SeqCst);
-        let pause_fn = || state.pause_flag.load(Ordering::SeqCst);
-        
-
-        'exec: {
-            // This works a lot like thread::sleep, but it can be interrupted from the outside.
-            // This allows us to easily close the appplication.
-            macro_rules! delay {
-                ($dur:expr) => {
-                    if wait_interruptible(Duration::from_millis($dur), &wait_fn) {
-                        break 'exec;
-                    }
-                };
-            }
-            // This is basically the delay macro, but it can be paused.
-            macro_rules! delay_pause {
-                ($dur:expr) => {
-                    if wait_pausable(Duration::from_millis($dur), &wait_fn, &pause_fn) {
-                        break 'exec;
-                    }
-                };
-            }
+        // calibration changes from the admin screen apply from the next order
+        let calibration = *state.calibration.lock().unwrap();
+        let mut progress = OrderProgress::new(red_count, green_count);
+        save_progress(state, &progress);
+        let result = execute_order(state, [&mut servo_a, &mut servo_b], &calibration, &mut progress);
 
-            for _ in 0..red_count {
-                servo_a.set_pos(1.0);
-                delay!(500);
-                servo_a.set_pos(0.0);
-                delay_pause!(500);
-            }
-            for _ in 0..green_count {
-                servo_b.set_pos(1.0);
-                delay!(500);
-                servo_b.set_pos(0.0);
-                delay_pause!(500);
+        let outcome = if state.estop.load(Ordering::SeqCst) {
+            "E-stop"
+        } else if result.is_err() {
+            "fault"
+        } else if state.cancel_flag.load(Ordering::SeqCst) {
+            "cancelled"
+        } else if state.exit_flag.load(Ordering::SeqCst) {
+            "interrupted"
+        } else {
+            "done"
+        };
+        // tests from the admin screen weren't paid for or saved, so there's nothing to record or refund
+        if !state.test_order.load(Ordering::SeqCst) {
+            record_order(state, progress, outcome);
+
+            // The order was paid for in full, so whatever wasn't dispensed goes back into the credit, whether it
+            // was cancelled, stopped by the E-stop or a fault. The only exception is when the app is exiting
+            // halfway through an order: the saved progress is kept (and stays paid for) so it can be resumed
+            // next time.
+            let resumable = outcome == "interrupted" && progress.remaining() != (0, 0);
+            if !resumable {
+                state.refund_order(&progress);
             }
         }
-        // reset the motors
-        servo_a.set_pos(0.0);
-        servo_b.set_pos(0.0);
+
+        // on an E-stop, the servos must not move again: returning drops them, which stops their control pulses
+        if state.estop.load(Ordering::SeqCst) {
+            return Err(Error::EmergencyStop);
+        }
+
+        // reset the motors, even if the order failed
+        let reset = servo_a.set_pos(calibration.rest_pos[0]).and(servo_b.set_pos(calibration.rest_pos[1]));
         thread::sleep(Duration::from_millis(300));
+        result.and(reset)?;
 
         // signal that the order is over
-        *state.next_order.lock().unwrap() = None;
-        state.is_processing.store(false, Ordering::SeqCst);
-        state.cancel_flag.store(false, Ordering::SeqCst);
-        state.pause_flag.store(false, Ordering::SeqCst);
-        egui_ctx.request_repaint();
+        finish_order(state, egui_ctx);
+    }
+}
+
+/// Saves the progress of the current order, and shares it with the other threads.
+/// A failure to save shouldn't stop the order, so it's only logged. Tests are only shared, since they can't be
+/// resumed.
+fn save_progress(state: &SharedState, progress: &OrderProgress) {
+    *state.progress.lock().unwrap() = Some(*progress);
+    if state.test_order.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Err(err) = progress.save(&state.config.persist.order_file) {
+        eprintln!("could not save order progress: {}", err);
+    }
+}
+
+/// Takes a dispensed item out of the inventory.
+fn take_from_inventory(state: &SharedState, slot: usize) {
+    let mut inventory = state.inventory.lock().unwrap();
+    inventory.stock[slot] = inventory.stock[slot].saturating_sub(1);
+    if let Err(err) = save_toml(&state.config.persist.inventory_file, &*inventory) {
+        eprintln!("could not save inventory: {}", err);
+    }
+}
+
+/// Dispenses an order, saving the progress with every item. Returns early (without an error) if the order
+/// is cancelled or the app exits.
+///
+/// Each item is counted (and saved) before it's pushed out, so if the power goes out in the middle of a push,
+/// the resumed order never dispenses that item a second time. This errs on the side of dispensing one item
+/// too few, which is easier to make up for than one too many.
+fn execute_order(
+    state: &SharedState,
+    servos: [&mut ServoSg90; 2],
+    calibration: &Calibration,
+    progress: &mut OrderProgress,
+) -> Result<()> {
+    // execute the order. Any sleep must be replaced with a park (so that it can be interrupted)
+    let wait_fn = || state.exit_flag.load(Ordering::SeqCst) || state.cancel_flag.load(Ordering::SeqCst);
+    let pause_fn = || state.pause_flag.load(Ordering::SeqCst);
+
+    // This works a lot like thread::sleep, but it can be interrupted from the outside.
+    // This allows us to easily close the appplication.
+    macro_rules! delay {
+        ($dur:expr) => {
+            if wait_interruptible(Duration::from_millis($dur), &wait_fn) {
+                return Ok(());
+            }
+        };
+    }
+    // This is basically the delay macro, but it can be paused.
+    macro_rules! delay_pause {
+        ($dur:expr) => {
+            if wait_pausable(Duration::from_millis($dur), &wait_fn, &pause_fn) {
+                return Ok(());
+            }
+        };
+    }
+
+    for (slot, servo) in servos.into_iter().enumerate() {
+        state.cur_slot.store(slot, Ordering::SeqCst);
+        while progress.dispensed[slot] < progress.counts[slot] {
+            progress.dispensed[slot] += 1;
+            // Both of these sync to disk (the file, then its directory), so that's two rounds of fsyncs per item.
+            // On an SD card each round takes a few to a few tens of milliseconds, which is small next to the
+            // push itself, but it does add up if the gap between items is set very short.
+            save_progress(state, progress);
+            take_from_inventory(state, slot);
+            servo.set_pos(calibration.push_pos[slot])?;
+            delay!(calibration.push_ms);
+            servo.set_pos(calibration.rest_pos[slot])?;
+            delay_pause!(calibration.gap_ms);
+        }
     }
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path, process};
+
+    use super::*;
+
+    #[test]
+    fn does_not_save_or_refund_tests() {
+        let mut state = SharedState::default();
+        let path = env::temp_dir().join(format!("dispenser-test-order-{}.toml", process::id()));
+        state.config.persist.order_file = path.to_string_lossy().into_owned();
+        state.test_order.store(true, Ordering::SeqCst);
+        // with the E-stop latched, recovering doesn't try to move the servos
+        state.estop.store(true, Ordering::SeqCst);
+        *state.order_badge.lock().unwrap() = Some("Alice".to_owned());
+
+        let progress = OrderProgress::new(1, 0);
+        save_progress(&state, &progress);
+        assert!(!Path::new(&path).exists());
+        assert!(state.progress.lock().unwrap().is_some_and(|shared| shared.counts == progress.counts));
+
+        // a test that crashes halfway isn't given back to anyone
+        recover_gpio(&state);
+        assert!(state.returned_items.lock().unwrap().is_empty());
+        assert!(!state.test_order.load(Ordering::SeqCst));
+        assert!(state.progress.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,91 @@
+/*
+gui/history.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! History of finished orders, shown on the admin screen.
+//!
+//! Every order is added to a CSV file as it finishes, so the history survives restarts. Only the most
+//! recent orders are kept in memory.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+};
+
+use crate::clock;
+
+use super::{persist::OrderProgress, SharedState};
+
+/// Number of orders kept in memory.
+const MAX_ENTRIES: usize = 50;
+
+/// An order that has finished, one way or another.
+#[derive(Clone, Debug)]
+pub(super) struct HistoryEntry {
+    /// When the order finished, as `YYYY-MM-DD HH:MM:SS`.
+    pub time: String,
+    pub progress: OrderProgress,
+    /// How the order ended: done, cancelled, fault, E-stop or interrupted.
+    pub outcome: String,
+}
+
+impl HistoryEntry {
+    /// Converts the entry to a line of the history file: time, red and green ordered, red and green dispensed,
+    /// and the outcome.
+    fn to_line(&self) -> String {
+        let p = &self.progress;
+        format!(
+            "{},{},{},{},{},{}",
+            self.time, p.counts[0], p.counts[1], p.dispensed[0], p.dispensed[1], self.outcome
+        )
+    }
+
+    /// Reads an entry from a line of the history file.
+    fn parse(line: &str) -> Option<HistoryEntry> {
+        let mut fields = line.split(',');
+        let time = fields.next()?.to_owned();
+        let mut number = || fields.next()?.parse::<u64>().ok();
+        let progress = OrderProgress { counts: [number()?, number()?], dispensed: [number()?, number()?] };
+        let outcome = fields.next()?.to_owned();
+        Some(Self { time, progress, outcome })
+    }
+}
+
+/// Loads the most recent orders from the history file.
+pub(super) fn load_history(path: &str) -> VecDeque<HistoryEntry> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!("could not load order history: {}", err);
+            }
+            return VecDeque::new();
+        }
+    };
+    let entries: Vec<HistoryEntry> = text.lines().filter_map(HistoryEntry::parse).collect();
+    entries[entries.len().saturating_sub(MAX_ENTRIES)..].iter().cloned().collect()
+}
+
+/// Adds a finished order to the history.
+pub(super) fn record_order(state: &SharedState, progress: OrderProgress, outcome: &str) {
+    let entry = HistoryEntry { time: clock::local_date_time(), progress, outcome: outcome.to_owned() };
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&state.config.persist.history_file)
+        .and_then(|mut file| writeln!(file, "{}", entry.to_line()));
+    if let Err(err) = result {
+        eprintln!("could not save order history: {}", err);
+    }
+
+    let mut history = state.history.lock().unwrap();
+    history.push_back(entry);
+    if history.len() > MAX_ENTRIES {
+        history.pop_front();
+    }
+}
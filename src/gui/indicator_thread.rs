@@ -0,0 +1,127 @@
+/*
+gui/indicator_thread.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Implementation of the indicator thread, which shows what the dispenser is doing on the status lights.
+
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::leds::{GpioLeds, Lights, Rgb, Ws2812Strip};
+
+use super::SharedState;
+
+/// Time between updates of the lights (50 updates per second).
+const FRAME_TIME: Duration = Duration::from_millis(20);
+
+/// What the lights are showing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LightState {
+    Idle,
+    /// Dispensing from a slot (0 is red, 1 is green).
+    Dispensing(usize),
+    Paused,
+    Fault,
+}
+
+impl LightState {
+    /// Works out what the lights should show from the shared state.
+    fn current(state: &SharedState) -> LightState {
+        if state.fault.lock().unwrap().is_some() || state.estop.load(Ordering::SeqCst) {
+            LightState::Fault
+        } else if !state.is_processing.load(Ordering::SeqCst) {
+            LightState::Idle
+        } else if state.pause_flag.load(Ordering::SeqCst) {
+            LightState::Paused
+        } else {
+            LightState::Dispensing(state.cur_slot.load(Ordering::SeqCst))
+        }
+    }
+}
+
+/// Starts the indicator thread, if any lights are configured. Lights that can't be set up are skipped,
+/// since the dispenser works fine without them.
+pub(super) fn spawn_indicator_thread(state: Arc<SharedState>) {
+    let config = &state.config.leds;
+    let mut lights: Vec<Box<dyn Lights + Send>> = Vec::new();
+    if !config.pins.is_empty() {
+        match GpioLeds::new(&config.pins) {
+            Ok(leds) => lights.push(Box::new(leds)),
+            Err(err) => eprintln!("could not set up status LEDs: {}", err),
+        }
+    }
+    if config.strip_length > 0 {
+        match Ws2812Strip::new(config.strip_length) {
+            Ok(strip) => lights.push(Box::new(strip)),
+            Err(err) => eprintln!("could not set up LED strip: {}", err),
+        }
+    }
+    if lights.is_empty() {
+        return;
+    }
+
+    let result = thread::Builder::new()
+        .name("Lights".to_owned())
+        .spawn(move || run_lights(&state, lights));
+    if let Err(err) = result {
+        eprintln!("could not start indicator thread: {}", err);
+    }
+}
+
+/// Animates the lights until the app exits, then turns them off.
+fn run_lights(state: &SharedState, mut lights: Vec<Box<dyn Lights + Send>>) {
+    let config = &state.config.leds;
+    let mut cur_state = LightState::Idle;
+    let mut state_start = Instant::now();
+    let mut pixels = Vec::new();
+
+    while !state.exit_flag.load(Ordering::SeqCst) {
+        // restart the pattern whenever the state changes, so e.g. a blink starts off lit
+        let new_state = LightState::current(state);
+        if new_state != cur_state {
+            cur_state = new_state;
+            state_start = Instant::now();
+        }
+
+        let (pattern, colour) = match cur_state {
+            LightState::Idle => (config.idle, config.idle_colour),
+            LightState::Dispensing(0) => (config.dispensing, config.red_colour),
+            LightState::Dispensing(_) => (config.dispensing, config.green_colour),
+            LightState::Paused => (config.paused, config.paused_colour),
+            LightState::Fault => (config.fault, config.fault_colour),
+        };
+        let colour = colour.scale(config.brightness);
+        let time = state_start.elapsed().as_secs_f64();
+
+        // lights that stop working are dropped, rather than stopping the others
+        lights.retain_mut(|light| {
+            pixels.resize(light.pixel_count(), Rgb::OFF);
+            pattern.render(colour, time, &mut pixels);
+            match light.show(&pixels) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("status lights stopped working: {}", err);
+                    false
+                }
+            }
+        });
+        if lights.is_empty() {
+            return;
+        }
+        thread::sleep(FRAME_TIME);
+    }
+
+    for light in &mut lights {
+        pixels.clear();
+        pixels.resize(light.pixel_count(), Rgb::OFF);
+        if let Err(err) = light.show(&pixels) {
+            eprintln!("could not turn off status lights: {}", err);
+        }
+    }
+}
@@ -6,27 +6,169 @@ Date: Jun. 17, 2024
 */
 
 //! Implementation of the music thread, which plays music on the buzzer.
+//!
+//! The song stops while the music is muted, and in attract mode, where it's replaced by an attention tune
+//! every few minutes (if there is one).
 
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::{music::buzzer_play_array, pwm::PwmToneBuzzer};
-
-use crate::music::badapple as song;
+use crate::{
+    error::{Error, Result},
+    music::{badapple, buzzer_play_song, load_song, Song},
+    pwm::{self, PwmToneBuzzer},
+};
 
 use super::SharedState;
 
 /// Function for the music thread, which plays music on the buzzer.
 /// ## Parameters
 /// - `state`: Shared state from the GUI.
-/// - `_egui_ctx`: GUI context, also obtained from the GUI. Currently unused.
-pub(super) fn run_music_thread(state: Arc<SharedState>, _egui_ctx: egui::Context) {
-    let mut check_cur_exit = || state.exit_flag.load(Ordering::SeqCst);
-    let mut buzzer = PwmToneBuzzer::new(18).unwrap();
+/// - `egui_ctx`: GUI context, also obtained from the GUI. Used to show the current measure.
+pub(super) fn run_music_thread(state: Arc<SharedState>, egui_ctx: egui::Context) {
+    // load the song from the config, falling back to Bad Apple if it can't be loaded
+    let transform = state.config.music.transform();
+    let song = load_song(&state.config.music.song, &transform).unwrap_or_else(|err| {
+        eprintln!("{}\nplaying the default song instead", err);
+        badapple::SONG.transformed(&transform)
+    });
+    // attract mode is just quiet if the tune can't be loaded
+    let attract = &state.config.attract;
+    let tune = attract.tune.as_ref().and_then(|tune| {
+        load_song(tune, &transform)
+            .map_err(|err| eprintln!("{}\nattract mode will be quiet", err))
+            .ok()
+    });
+    let tune_interval = Duration::from_secs(attract.tune_interval_secs);
+
     loop {
-        if buzzer_play_array(&mut buzzer, song::BPM, &song::DATA, &mut check_cur_exit) {
-            buzzer.stop();
-            println!("STAHP!");
-            break
+        match play_music(&state, &egui_ctx, &song, tune.as_ref(), tune_interval) {
+            Ok(()) => break,
+            Err(err) => {
+                // the buzzers have been silenced, so wait for someone to clear the fault and try again
+                state.music_ready.store(false, Ordering::SeqCst);
+                state.report_fault("Music", &err);
+                egui_ctx.request_repaint();
+                if state.wait_fault_cleared() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Plays the song on repeat until the app exits, with breaks for muting and attract mode. The buzzers are
+/// always silenced before returning, even if playing the song failed.
+fn play_music(
+    state: &SharedState,
+    egui_ctx: &egui::Context,
+    song: &Song,
+    tune: Option<&Song>,
+    tune_interval: Duration,
+) -> Result<()> {
+    let check_cur_exit = || check_exit_or_estop(state) || is_quiet(state);
+    let mut on_measure = |measure| {
+        state.cur_measure.store(measure, Ordering::SeqCst);
+        egui_ctx.request_repaint();
+    };
+
+    let mut buzzers = state
+        .config
+        .music
+        .buzzer_pins
+        .iter()
+        .map(|&pin| PwmToneBuzzer::new(pin))
+        .collect::<Result<Vec<PwmToneBuzzer>>>()?;
+    state.music_ready.store(true, Ordering::SeqCst);
+    let result = loop {
+        match buzzer_play_song(&mut buzzers, song, 0, &check_cur_exit, &mut on_measure) {
+            Ok(true) if state.estop.load(Ordering::SeqCst) => break Err(Error::EmergencyStop),
+            Ok(true) if !state.exit_flag.load(Ordering::SeqCst) => {
+                // muted or in attract mode; start the song over once that's done
+                let waited =
+                    stop_buzzers(&mut buzzers).and_then(|()| wait_quietly(state, &mut buzzers, tune, tune_interval));
+                if let Err(err) = waited {
+                    break Err(err);
+                }
+                continue;
+            }
+            Ok(true) => {
+                println!("STAHP!");
+                break Ok(());
+            }
+            Ok(false) => continue,
+            Err(err) => break Err(err),
+        }
+    };
+
+    let stopped = stop_buzzers(&mut buzzers);
+    result.and(stopped)
+}
+
+/// Returns true if the music should stop for good: the app is exiting, or the E-stop was pressed.
+fn check_exit_or_estop(state: &SharedState) -> bool {
+    state.exit_flag.load(Ordering::SeqCst) || state.estop.load(Ordering::SeqCst)
+}
+
+/// Returns true if the song shouldn't be playing: the music is muted, or attract mode is showing.
+fn is_quiet(state: &SharedState) -> bool {
+    state.muted.load(Ordering::SeqCst) || state.attract.load(Ordering::SeqCst)
+}
+
+/// Waits until the song can play again, or the music should stop for good. In attract mode, the attention
+/// tune (if there is one) plays right away and then once every `tune_interval`, unless the music is muted.
+fn wait_quietly(
+    state: &SharedState,
+    buzzers: &mut [PwmToneBuzzer],
+    tune: Option<&Song>,
+    tune_interval: Duration,
+) -> Result<()> {
+    let mut next_tune = Instant::now();
+    while is_quiet(state) && !check_exit_or_estop(state) {
+        let muted = state.muted.load(Ordering::SeqCst);
+        let attract = state.attract.load(Ordering::SeqCst);
+        match tune {
+            Some(tune) if attract && !muted => {
+                let now = Instant::now();
+                if now >= next_tune {
+                    // cut the tune short as soon as someone touches the screen
+                    let cancel = || {
+                        check_exit_or_estop(state)
+                            || !state.attract.load(Ordering::SeqCst)
+                            || state.muted.load(Ordering::SeqCst)
+                    };
+                    buzzer_play_song(buzzers, tune, 0, &cancel, &mut |_| {})?;
+                    stop_buzzers(buzzers)?;
+                    next_tune = Instant::now() + tune_interval;
+                } else {
+                    thread::park_timeout(next_tune - now);
+                }
+            }
+            // the GUI unparks the thread when this changes
+            _ => thread::park(),
+        }
+    }
+    Ok(())
+}
+
+/// Turns off the buzzers on the given pins directly, without needing the music thread's handles to them.
+/// Used when the music thread might be stuck.
+pub(super) fn force_disable_buzzers(pins: &[u8]) {
+    for &pin in pins {
+        if let Err(err) = pwm::force_disable(pin) {
+            eprintln!("could not turn off buzzer on pin {}: {}", pin, err);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Stops every buzzer, even if one of them fails.
+fn stop_buzzers(buzzers: &mut [PwmToneBuzzer]) -> Result<()> {
+    let mut stopped = Ok(());
+    for buzzer in buzzers {
+        stopped = stopped.and(buzzer.stop());
+    }
+    stopped
+}
@@ -0,0 +1,295 @@
+/*
+gui/panel.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Physical buttons and rotary encoder, for enclosures without a touchscreen.
+//!
+//! The inputs are polled on their own thread, which sends [`PanelAction`]s to the GUI thread. The GUI handles
+//! them exactly like its own buttons, so everything else works the same no matter where an order comes from.
+
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::PanelConfig,
+    error::Result,
+    gpio::{DebouncedInput, RotaryEncoder},
+};
+
+use super::SharedState;
+
+/// How often the inputs are checked. The encoder needs to be checked quite often to not miss any steps.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How often a held +/- button repeats, after the long press time.
+const REPEAT_INTERVAL: Duration = Duration::from_millis(150);
+
+/// Something the user asked for using the physical controls.
+pub(super) enum PanelAction {
+    /// Changes the count of a slot (0 is red, 1 is green) by some amount.
+    Adjust(usize, i64),
+    /// Changes the count of the slot selected with the encoder.
+    AdjustSelected(i64),
+    /// Selects the next slot for the encoder.
+    NextSlot,
+    /// Fills in the counters from a preset, by its position in the list.
+    Preset(usize),
+    Start,
+    Pause,
+    Cancel,
+}
+
+/// Things that can happen to a button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Press {
+    /// The button was just pressed.
+    Down,
+    /// The button was released before it counted as a long press.
+    Short,
+    /// The button has been held for the long press time.
+    Long,
+    /// The button is still held after a long press.
+    Repeat,
+}
+
+/// What each button does.
+#[derive(Clone, Copy)]
+enum ButtonKind {
+    Plus(usize),
+    Minus(usize),
+    Start,
+    Pause,
+    Cancel,
+    EncoderSwitch,
+    Preset(usize),
+}
+
+impl ButtonKind {
+    /// Returns the action for a press of this button, if it does anything.
+    fn action(self, press: Press) -> Option<PanelAction> {
+        // +/- act straight away and repeat while held; START/PAUSE/CANCEL act straight away.
+        // The encoder switch waits to see whether it's a short or long press.
+        match (self, press) {
+            (ButtonKind::Plus(slot), Press::Down | Press::Long | Press::Repeat) => Some(PanelAction::Adjust(slot, 1)),
+            (ButtonKind::Minus(slot), Press::Down | Press::Long | Press::Repeat) => Some(PanelAction::Adjust(slot, -1)),
+            (ButtonKind::Start, Press::Down) => Some(PanelAction::Start),
+            (ButtonKind::Pause, Press::Down) => Some(PanelAction::Pause),
+            (ButtonKind::Cancel, Press::Down) => Some(PanelAction::Cancel),
+            (ButtonKind::EncoderSwitch, Press::Short) => Some(PanelAction::NextSlot),
+            (ButtonKind::EncoderSwitch, Press::Long) => Some(PanelAction::Start),
+            (ButtonKind::Preset(index), Press::Down) => Some(PanelAction::Preset(index)),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps track of how long a button has been held, turning its debounced level into [`Press`]es.
+///
+/// This only works on levels and times, so it can be tested without a button.
+#[derive(Default)]
+struct LongPress {
+    held_since: Option<Instant>,
+    // last time a long press or repeat was reported for the current press
+    last_long: Option<Instant>,
+}
+
+impl LongPress {
+    /// Takes the debounced level of the button at time `now`, and returns what happened to it, if anything.
+    fn update(&mut self, pressed: bool, now: Instant, long_press: Duration) -> Option<Press> {
+        match (pressed, self.held_since, self.last_long) {
+            (true, None, _) => {
+                self.held_since = Some(now);
+                Some(Press::Down)
+            }
+            (true, Some(since), None) if now.saturating_duration_since(since) >= long_press => {
+                self.last_long = Some(now);
+                Some(Press::Long)
+            }
+            (true, _, Some(last)) if now.saturating_duration_since(last) >= REPEAT_INTERVAL => {
+                self.last_long = Some(now);
+                Some(Press::Repeat)
+            }
+            (false, Some(_), last_long) => {
+                self.held_since = None;
+                self.last_long = None;
+                last_long.is_none().then_some(Press::Short)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A debounced button that keeps track of how long it's been held.
+struct PanelButton {
+    kind: ButtonKind,
+    input: DebouncedInput,
+    press: LongPress,
+}
+
+impl PanelButton {
+    /// Samples the button and returns what happened to it, if anything.
+    fn poll(&mut self, long_press: Duration) -> Option<Press> {
+        let pressed = self.input.poll();
+        self.press.update(pressed, Instant::now(), long_press)
+    }
+}
+
+/// All the configured inputs.
+struct Panel {
+    buttons: Vec<PanelButton>,
+    encoder: Option<RotaryEncoder>,
+}
+
+impl Panel {
+    /// Sets up every input that has a pin in the config.
+    fn new(config: &PanelConfig) -> Result<Panel> {
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let mut pins = vec![
+            (config.red_plus, ButtonKind::Plus(0)),
+            (config.red_minus, ButtonKind::Minus(0)),
+            (config.green_plus, ButtonKind::Plus(1)),
+            (config.green_minus, ButtonKind::Minus(1)),
+            (config.start, ButtonKind::Start),
+            (config.pause, ButtonKind::Pause),
+            (config.cancel, ButtonKind::Cancel),
+            (config.encoder_switch, ButtonKind::EncoderSwitch),
+        ];
+        pins.extend(config.presets.iter().enumerate().map(|(index, &pin)| (Some(pin), ButtonKind::Preset(index))));
+        let buttons = pins
+            .into_iter()
+            .filter_map(|(pin, kind)| pin.map(|pin| (pin, kind)))
+            .map(|(pin, kind)| {
+                Ok(PanelButton {
+                    kind,
+                    input: DebouncedInput::new(pin, config.active_high, debounce)?,
+                    press: LongPress::default(),
+                })
+            })
+            .collect::<Result<Vec<PanelButton>>>()?;
+        let encoder = match (config.encoder_a, config.encoder_b) {
+            (Some(a), Some(b)) => Some(RotaryEncoder::new(a, b)?),
+            _ => None,
+        };
+        Ok(Self { buttons, encoder })
+    }
+
+    /// Checks every input once, returning the actions for anything that happened.
+    fn poll(&mut self, long_press: Duration) -> Vec<PanelAction> {
+        let mut actions = Vec::new();
+        for button in &mut self.buttons {
+            if let Some(action) = button.poll(long_press).and_then(|press| button.kind.action(press)) {
+                actions.push(action);
+            }
+        }
+        if let Some(encoder) = &mut self.encoder {
+            let detents = encoder.poll();
+            if detents != 0 {
+                actions.push(PanelAction::AdjustSelected(detents.into()));
+            }
+        }
+        actions
+    }
+}
+
+/// Starts a thread that polls the physical controls, if any are configured. Returns the receiving end of the
+/// actions, which the GUI should check every frame.
+pub(super) fn spawn_panel_thread(state: Arc<SharedState>, egui_ctx: egui::Context) -> Option<Receiver<PanelAction>> {
+    let config = &state.config.panel;
+    let mut panel = match Panel::new(config) {
+        Ok(panel) if panel.buttons.is_empty() && panel.encoder.is_none() => return None,
+        Ok(panel) => panel,
+        Err(err) => {
+            eprintln!("could not set up buttons: {}", err);
+            return None;
+        }
+    };
+    let long_press = Duration::from_millis(config.long_press_ms);
+
+    let (sender, receiver) = mpsc::channel();
+    let result = thread::Builder::new().name("Panel".to_owned()).spawn(move || {
+        while !state.exit_flag.load(Ordering::SeqCst) {
+            let actions = panel.poll(long_press);
+            // only wake the GUI up if something happened
+            if !actions.is_empty() {
+                for action in actions {
+                    if sender.send(action).is_err() {
+                        return;
+                    }
+                }
+                egui_ctx.request_repaint();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    match result {
+        Ok(_) => Some(receiver),
+        Err(err) => {
+            eprintln!("could not start button thread: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG_PRESS: Duration = Duration::from_millis(800);
+
+    /// Feeds the long press tracker (time in ms, pressed) samples, returning every press it reports with its time.
+    fn presses(samples: &[(u64, bool)]) -> Vec<(u64, Press)> {
+        let start = Instant::now();
+        let mut press = LongPress::default();
+        samples
+            .iter()
+            .filter_map(|&(t, pressed)| {
+                press.update(pressed, start + Duration::from_millis(t), LONG_PRESS).map(|press| (t, press))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_short_presses() {
+        let samples: Vec<(u64, bool)> = (0..=500).step_by(10).map(|t| (t, t < 300)).collect();
+        assert_eq!(presses(&samples), [(0, Press::Down), (300, Press::Short)]);
+    }
+
+    #[test]
+    fn reports_long_presses_and_repeats() {
+        let samples: Vec<(u64, bool)> = (0..=1200).step_by(10).map(|t| (t, t < 1150)).collect();
+        assert_eq!(
+            presses(&samples),
+            [(0, Press::Down), (800, Press::Long), (950, Press::Repeat), (1100, Press::Repeat)]
+        );
+    }
+
+    #[test]
+    fn starts_over_after_release() {
+        let samples = [(0, true), (500, false), (600, true), (1300, true), (1400, true), (1500, false)];
+        assert_eq!(presses(&samples), [(0, Press::Down), (500, Press::Short), (600, Press::Down), (1400, Press::Long)]);
+    }
+
+    #[test]
+    fn maps_presses_to_actions() {
+        let switch = ButtonKind::EncoderSwitch;
+        assert!(switch.action(Press::Down).is_none());
+        assert!(matches!(switch.action(Press::Short), Some(PanelAction::NextSlot)));
+        assert!(matches!(switch.action(Press::Long), Some(PanelAction::Start)));
+        assert!(switch.action(Press::Repeat).is_none());
+        let plus = ButtonKind::Plus(1);
+        assert!(matches!(plus.action(Press::Down), Some(PanelAction::Adjust(1, 1))));
+        assert!(matches!(plus.action(Press::Repeat), Some(PanelAction::Adjust(1, 1))));
+        assert!(plus.action(Press::Short).is_none());
+        assert!(matches!(ButtonKind::Minus(0).action(Press::Long), Some(PanelAction::Adjust(0, -1))));
+        assert!(ButtonKind::Cancel.action(Press::Short).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+/*
+gui/persist.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Saving state to disk, so it survives a crash or the power going out.
+//!
+//! Files are written to a temporary file first and then renamed over the old one, so a crash in the middle
+//! of saving leaves either the old or the new contents, never half of each.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::config::Preset;
+
+/// An order, and how much of it has been dispensed so far.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct OrderProgress {
+    /// Number of items ordered from each slot (red, green).
+    pub counts: [u64; 2],
+    /// Number of items already dispensed from each slot.
+    pub dispensed: [u64; 2],
+}
+
+impl OrderProgress {
+    /// Starts tracking a new order.
+    pub fn new(red: u64, green: u64) -> Self {
+        Self { counts: [red, green], dispensed: [0, 0] }
+    }
+
+    /// Returns the items that still need to be dispensed, as a (red, green) order.
+    pub fn remaining(&self) -> (u64, u64) {
+        (
+            self.counts[0].saturating_sub(self.dispensed[0]),
+            self.counts[1].saturating_sub(self.dispensed[1]),
+        )
+    }
+
+    /// Loads a saved order. Returns `None` if there isn't one.
+    pub fn load(path: &str) -> io::Result<Option<Self>> {
+        load_toml(path)
+    }
+
+    /// Saves the order, replacing any previously saved one. This is done after every item is dispensed.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        save_toml(path, self)
+    }
+
+    /// Deletes the saved order, if there is one.
+    pub fn clear(path: &str) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Servo positions and timing for each slot, adjusted from the admin screen.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct Calibration {
+    /// Position of each slot's servo at rest (0 to 1).
+    pub rest_pos: [f32; 2],
+    /// Position each slot's servo moves to, to push an item out.
+    pub push_pos: [f32; 2],
+    /// How long a servo stays pushed out, in milliseconds.
+    pub push_ms: u64,
+    /// How long to wait after each item, in milliseconds.
+    pub gap_ms: u64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self { rest_pos: [0.0, 0.0], push_pos: [1.0, 1.0], push_ms: 500, gap_ms: 500 }
+    }
+}
+
+/// Number of items left in each slot.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Inventory {
+    pub stock: [u64; 2],
+}
+
+/// Credit that's been paid in but not spent yet.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct SavedCredit {
+    pub credit: u64,
+}
+
+/// IDs of the scanned order codes that have been used.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct UsedOrderIds {
+    pub ids: HashSet<String>,
+}
+
+/// Presets saved from the admin screen. They're kept in a table, since a TOML file can't just be a list.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct SavedPresets {
+    pub presets: Vec<Preset>,
+}
+
+/// Loads a value from a TOML file. Returns `None` if the file doesn't exist.
+pub(super) fn load_toml<T: DeserializeOwned>(path: &str) -> io::Result<Option<T>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Saves a value to a TOML file, replacing the old file safely. This syncs both the file and its directory
+/// to disk, which is slow on an SD card, so it shouldn't be called more often than needed.
+pub(super) fn save_toml<T: Serialize>(path: &str, value: &T) -> io::Result<()> {
+    let text = toml::to_string(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    // write the new contents next to the old ones, make sure they're actually on the disk, then swap them in
+    let tmp_path = format!("{}.tmp", path);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // the rename itself only sticks once the directory is synced
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    /// Returns a path in the temp directory that nothing else is using.
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dispenser-persist-{}-{}.toml", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn saves_and_loads_orders() {
+        let path = temp_path("round-trip");
+        assert!(OrderProgress::load(&path).unwrap().is_none());
+
+        let mut progress = OrderProgress::new(3, 2);
+        progress.dispensed = [3, 1];
+        progress.save(&path).unwrap();
+        let loaded = OrderProgress::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.counts, [3, 2]);
+        assert_eq!(loaded.dispensed, [3, 1]);
+        assert_eq!(loaded.remaining(), (0, 1));
+
+        // saving again replaces the old order, without leaving the temporary file behind
+        OrderProgress::new(1, 0).save(&path).unwrap();
+        assert_eq!(OrderProgress::load(&path).unwrap().unwrap().counts, [1, 0]);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        OrderProgress::clear(&path).unwrap();
+        assert!(OrderProgress::load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn clears_missing_orders() {
+        let path = temp_path("missing");
+        OrderProgress::clear(&path).unwrap();
+        OrderProgress::clear(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let path = temp_path("corrupt");
+        // e.g. a file cut off halfway through by something other than save_toml
+        fs::write(&path, "counts = [3, 2]\ndispensed = [1,").unwrap();
+        let err = OrderProgress::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::write(&path, "counts = \"lots\"\n").unwrap();
+        assert_eq!(load_toml::<Inventory>(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fills_in_missing_calibration() {
+        let path = temp_path("calibration");
+        fs::write(&path, "push_ms = 250\n").unwrap();
+        let calibration: Calibration = load_toml(&path).unwrap().unwrap();
+        assert_eq!(calibration.push_ms, 250);
+        assert_eq!(calibration.gap_ms, Calibration::default().gap_ms);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+/*
+gui/presets.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Presets: named orders that fill in the counters with one tap.
+//!
+//! Presets come from the config file, or are saved from the admin screen. Saved presets go after the ones from
+//! the config file. The physical buttons and the scanner can pick presets too.
+
+use egui::{Button, TextEdit, Ui};
+
+use crate::{config::Preset, i18n::Msg};
+
+use super::{
+    counter::Counter,
+    persist::{load_toml, save_toml, SavedPresets},
+    Application,
+};
+
+/// Longest name a saved preset can have, so its button still fits on the screen.
+const MAX_NAME_LEN: usize = 20;
+/// Most presets that can be saved, so their buttons still fit on the order screen.
+const MAX_SAVED: usize = 8;
+
+/// Loads the presets saved from the admin screen.
+pub(super) fn load_saved_presets(path: &str) -> Vec<Preset> {
+    match load_toml::<SavedPresets>(path) {
+        Ok(saved) => saved.unwrap_or_default().presets,
+        Err(err) => {
+            eprintln!("could not load saved presets: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+impl Application {
+    /// Returns every preset, in the order they're shown: the config file's first, then the saved ones.
+    fn presets(&self) -> impl Iterator<Item = &Preset> {
+        self.shared_state.config.presets.iter().chain(&self.saved_presets)
+    }
+
+    /// Finds a preset by its name, ignoring case.
+    pub(super) fn find_preset(&self, name: &str) -> Option<Preset> {
+        self.presets().find(|preset| preset.name.eq_ignore_ascii_case(name)).cloned()
+    }
+
+    /// Fills in the counters from a preset, by its position in the list.
+    pub(super) fn apply_preset(&mut self, index: usize) {
+        let preset = self.presets().nth(index).cloned();
+        if let Some(preset) = preset {
+            self.cnt_red.set_count(preset.red);
+            self.cnt_green.set_count(preset.green);
+        }
+    }
+
+    /// Saves what's on the counters as a preset, under the name typed in on the admin screen. A preset with the
+    /// same name is replaced.
+    fn save_preset(&mut self) {
+        let name = self.preset_name.trim().to_owned();
+        self.saved_presets.retain(|preset| !preset.name.eq_ignore_ascii_case(&name));
+        let (red, green) = (self.cnt_red.count(), self.cnt_green.count());
+        self.saved_presets.push(Preset { name, red, green });
+        self.preset_name.clear();
+        self.write_saved_presets();
+    }
+
+    /// Writes the saved presets to their file.
+    fn write_saved_presets(&self) {
+        let saved = SavedPresets { presets: self.saved_presets.clone() };
+        if let Err(err) = save_toml(&self.shared_state.config.persist.presets_file, &saved) {
+            eprintln!("could not save presets: {}", err);
+        }
+    }
+
+    /// Draws a button for each preset.
+    pub(super) fn presets_ui(&mut self, ui: &mut Ui, enabled: bool) {
+        let lang = self.language;
+        let mut picked = None;
+        ui.horizontal_wrapped(|ui| {
+            for (index, preset) in self.presets().enumerate() {
+                let (red, green) = (lang.format_number(preset.red), lang.format_number(preset.green));
+                let text = lang.format(Msg::PresetButton, &[&preset.name, &red, &green]);
+                if ui.add_enabled(enabled, Button::new(text)).clicked() {
+                    picked = Some(index);
+                }
+            }
+        });
+        if let Some(index) = picked {
+            self.apply_preset(index);
+        }
+    }
+
+    /// Lists the saved presets on the admin screen, so they can be deleted, and lets new ones be saved. The
+    /// counters here are the same as on the order screen, so an order that's already set up can be saved as is.
+    pub(super) fn presets_admin_ui(&mut self, ui: &mut Ui) {
+        let lang = self.language;
+        ui.label(lang.format(Msg::SavedPresets, &[&self.saved_presets.len(), &MAX_SAVED]));
+        ui.horizontal(|ui| {
+            ui.add(Counter::new(&mut self.cnt_red).with_language(lang).with_header(lang.text(Msg::Red)));
+            ui.add(Counter::new(&mut self.cnt_green).with_language(lang).with_header(lang.text(Msg::Green)));
+            ui.vertical(|ui| {
+                ui.add(
+                    TextEdit::singleline(&mut self.preset_name)
+                        .char_limit(MAX_NAME_LEN)
+                        .hint_text(lang.text(Msg::PresetName))
+                        .desired_width(150.0),
+                );
+                // a preset from the config file can't be replaced from the screen, and replacing a saved one
+                // doesn't add to the count
+                let name = self.preset_name.trim();
+                let replaces = self.saved_presets.iter().any(|preset| preset.name.eq_ignore_ascii_case(name));
+                let can_save = !name.is_empty()
+                    && !self.shared_state.config.presets.iter().any(|preset| preset.name.eq_ignore_ascii_case(name))
+                    && (replaces || self.saved_presets.len() < MAX_SAVED);
+                if ui.add_enabled(can_save, Button::new(lang.text(Msg::SavePreset))).clicked() {
+                    self.save_preset();
+                }
+            });
+        });
+        let mut deleted = None;
+        for (index, preset) in self.saved_presets.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let (red, green) = (lang.format_number(preset.red), lang.format_number(preset.green));
+                ui.label(lang.format(Msg::PresetDetails, &[&preset.name, &red, &green]));
+                if ui.button(lang.text(Msg::DeletePreset)).clicked() {
+                    deleted = Some(index);
+                }
+            });
+        }
+        if let Some(index) = deleted {
+            self.saved_presets.remove(index);
+            self.write_saved_presets();
+        }
+    }
+}
@@ -0,0 +1,180 @@
+/*
+gui/scanner.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Orders from a barcode/QR code scanner.
+//!
+//! Scanned codes either come from a thread reading the scanner's input device, or are picked up from
+//! keystrokes in the window. Either way, they're handled like the counters had been set and START pressed, so
+//! they're only taken on the customer screen.
+//!
+//! Besides order codes, a code can name one of the presets, like `PRESET:Small mix`. Preset codes can't be signed,
+//! so they're refused when order codes have to be.
+
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Instant,
+};
+
+use egui::{Event, Key};
+
+use crate::{
+    config::{ScannerConfig, ScannerSource},
+    i18n::Msg,
+    order_code::{parse_order_code, OrderCodeError},
+    scanner::EvdevScanner,
+};
+
+use super::{admin::Screen, persist::save_toml, Application};
+
+/// Text that preset codes start with, in any case.
+const PRESET_PREFIX: &str = "PRESET:";
+
+/// Starts a thread that reads codes from the scanner's input device, if that's how it's set up.
+/// Returns the receiving end for the scanned codes.
+pub(super) fn spawn_scanner_thread(config: &ScannerConfig, egui_ctx: egui::Context) -> Option<Receiver<String>> {
+    if config.source != Some(ScannerSource::Evdev) {
+        return None;
+    }
+    let mut scanner = match EvdevScanner::open(&config.device) {
+        Ok(scanner) => scanner,
+        Err(err) => {
+            eprintln!("could not open scanner {}: {}", config.device, err);
+            return None;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    // reading blocks until something is scanned, so this thread isn't stopped on exit; it ends with the process
+    let result = thread::Builder::new().name("Scanner".to_owned()).spawn(move || loop {
+        match scanner.read_line() {
+            Ok(line) => {
+                if sender.send(line).is_err() {
+                    return;
+                }
+                egui_ctx.request_repaint();
+            }
+            Err(err) => {
+                eprintln!("scanner stopped working: {}", err);
+                return;
+            }
+        }
+    });
+    match result {
+        Ok(_) => Some(receiver),
+        Err(err) => {
+            eprintln!("could not start scanner thread: {}", err);
+            None
+        }
+    }
+}
+
+impl Application {
+    /// Handles codes read since the last frame, from whichever source the scanner is set up to use.
+    pub(super) fn handle_scanner(&mut self, ctx: &egui::Context) {
+        let mut lines: Vec<String> = match &self.scanner {
+            Some(scanner) => scanner.try_iter().collect(),
+            None => Vec::new(),
+        };
+        if self.shared_state.config.scanner.source == Some(ScannerSource::Keyboard) {
+            // keystrokes only count while no text box has focus, otherwise they'd be typed into it as well
+            if ctx.memory(|memory| memory.focused().is_none()) {
+                ctx.input(|input| {
+                    for event in &input.events {
+                        match event {
+                            Event::Text(text) => self.scan_buffer.push_str(text),
+                            Event::Key { key: Key::Enter, pressed: true, .. } => {
+                                lines.push(std::mem::take(&mut self.scan_buffer));
+                            }
+                            _ => {}
+                        }
+                    }
+                });
+            }
+        }
+        lines.retain(|line| !line.trim().is_empty());
+        if lines.is_empty() {
+            return;
+        }
+        // codes only start orders from the customer screen. Anything scanned on the admin screens, or while
+        // attract mode is showing, is dropped (though a scan still wakes the screen up, like a touch would)
+        let attracting = self.shared_state.attract.load(Ordering::SeqCst);
+        self.last_activity = Instant::now();
+        if attracting || self.admin.screen != Screen::Customer {
+            self.scan_buffer.clear();
+            return;
+        }
+        for line in &lines {
+            self.scan_message = Some(match self.start_scanned_order(line) {
+                Ok(msg) => msg,
+                Err(msg) => self.language.format(Msg::ScanRejected, &[&msg]),
+            });
+        }
+    }
+
+    /// Starts an order from a scanned code. Returns a message saying what happened.
+    fn start_scanned_order(&mut self, line: &str) -> Result<String, String> {
+        let lang = self.language;
+        let line = line.trim();
+        if let Some(prefix) = line.get(..PRESET_PREFIX.len()) {
+            if prefix.eq_ignore_ascii_case(PRESET_PREFIX) {
+                // anyone could print a preset code, which would get around the signatures
+                if self.shared_state.config.scanner.key.is_some() {
+                    return Err(lang.text(Msg::PresetCodesOff).to_owned());
+                }
+                let name = line[PRESET_PREFIX.len()..].trim();
+                let preset = self
+                    .find_preset(name)
+                    .ok_or_else(|| lang.format(Msg::NoSuchPreset, &[&format!("{:?}", name)]))?;
+                self.start_counted_order(preset.red, preset.green)?;
+                return Ok(lang.format(Msg::StartedPreset, &[&preset.name]));
+            }
+        }
+
+        let key = self.shared_state.config.scanner.key.as_deref().map(str::as_bytes);
+        let order = parse_order_code(line, key).map_err(|err| match err {
+            OrderCodeError::NotAnOrder => lang.text(Msg::NotAnOrderCode).to_owned(),
+            OrderCodeError::BadField(field) => lang.format(Msg::InvalidField, &[&format!("{:?}", field)]),
+            OrderCodeError::MissingSignature => lang.text(Msg::CodeNotSigned).to_owned(),
+            OrderCodeError::BadSignature => lang.text(Msg::BadSignature).to_owned(),
+            OrderCodeError::MissingId => lang.text(Msg::CodeNoId).to_owned(),
+        })?;
+        if order.id.as_ref().is_some_and(|id| self.used_order_ids.ids.contains(id)) {
+            return Err(lang.text(Msg::CodeUsed).to_owned());
+        }
+        self.start_counted_order(order.red, order.green)?;
+        Ok(match order.id {
+            Some(id) => {
+                let msg = lang.format(Msg::StartedScannedOrderId, &[&id]);
+                self.used_order_ids.ids.insert(id);
+                if let Err(err) = save_toml(&self.shared_state.config.persist.used_codes_file, &self.used_order_ids) {
+                    eprintln!("could not save used order codes: {}", err);
+                }
+                msg
+            }
+            None => lang.text(Msg::StartedScannedOrder).to_owned(),
+        })
+    }
+
+    /// Puts a scanned order on the counters and starts it.
+    fn start_counted_order(&mut self, red: u64, green: u64) -> Result<(), String> {
+        if self.shared_state.is_processing.load(Ordering::SeqCst) {
+            return Err(self.language.text(Msg::OrderRunning).to_owned());
+        }
+
+        self.cnt_red.set_count(red);
+        self.cnt_green.set_count(green);
+        if !self.can_start() {
+            return Err(self.language.text(Msg::CantStartNow).to_owned());
+        }
+        self.start_order();
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+/*
+gui/service.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Reporting the app's state to systemd, when running as a service.
+//!
+//! This runs on its own thread rather than from the GUI, since the GUI only updates when it gets redrawn.
+//! A window that isn't being redrawn (or a watchdog that expects pings more often than the GUI's once a
+//! second) would otherwise get a perfectly healthy dispenser killed.
+//!
+//! READY is sent as soon as the GUI and the worker supervisor are up, even if the hardware isn't. Staff need
+//! the GUI to clear an E-stop or a servo fault, so holding READY back would just get the service killed at its
+//! start timeout, over and over. Anything wrong with the hardware is reported in STATUS instead.
+//!
+//! The watchdog is only pinged while every worker thread is alive. It can't tell a worker that's stuck (say,
+//! in a hardware call that never returns) from one that's waiting for something to do, since the GPIO thread
+//! sleeps until an order comes in; a hung worker still gets pinged for.
+
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    i18n::Language,
+    systemd::{self, Notifier},
+};
+
+use super::SharedState;
+
+/// How often the status is checked for changes, if the watchdog doesn't need pings more often than this.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps systemd up to date with whether the app is ready, what it's doing, and whether it's healthy.
+/// If the app isn't running as a systemd service, this does nothing.
+pub(super) struct ServiceReporter {
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ServiceReporter {
+    /// Connects to systemd's notify socket, if there is one, and starts reporting to it. This should be called
+    /// once the GUI and the worker threads have been set up.
+    /// `workers` is the number of supervised worker threads; the watchdog is only pinged while all of them
+    /// are alive.
+    pub fn new(state: &Arc<SharedState>, workers: usize) -> Self {
+        let notifier = Notifier::from_env().unwrap_or_else(|err| {
+            eprintln!("could not connect to systemd notify socket: {}", err);
+            None
+        });
+        let Some(notifier) = notifier else {
+            return Self { handle: None };
+        };
+        let state = Arc::clone(state);
+        let result = thread::Builder::new()
+            .name("Service".to_owned())
+            .spawn(move || run_service_thread(&state, &notifier, workers));
+        match result {
+            Ok(handle) => Self { handle: Some(handle) },
+            Err(err) => {
+                eprintln!("could not start service thread: {}", err);
+                Self { handle: None }
+            }
+        }
+    }
+
+    /// Tells systemd the app is shutting down. The exit flag should be set before calling this.
+    pub fn stopping(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            if handle.join().is_err() {
+                eprintln!("service thread panicked");
+            }
+        }
+    }
+}
+
+/// Sends notifications until the app exits, then says it's stopping.
+/// READY is sent straight away, and the status whenever it changes.
+fn run_service_thread(state: &SharedState, notifier: &Notifier, workers: usize) {
+    let watchdog_interval = systemd::watchdog_interval();
+    // ping at twice the rate systemd expects, so a late ping doesn't set off the watchdog
+    let tick = watchdog_interval.map_or(STATUS_INTERVAL, |interval| (interval / 2).min(STATUS_INTERVAL));
+    let mut last_status = String::new();
+
+    if let Err(err) = notifier.ready() {
+        eprintln!("could not notify systemd: {}", err);
+    }
+
+    while !state.exit_flag.load(Ordering::SeqCst) {
+        let hardware_ready = state.gpio_ready.load(Ordering::SeqCst) && state.music_ready.load(Ordering::SeqCst);
+        let problem = state.estop.load(Ordering::SeqCst) || state.fault.lock().unwrap().is_some();
+        let healthy = state.live_workers.load(Ordering::SeqCst) == workers;
+        // the status ends up in the logs, so it's always in English
+        let status =
+            if hardware_ready || problem { state.status_text(Language::English) } else { "Starting up".to_owned() };
+
+        let mut result = Ok(());
+        if status != last_status {
+            result = result.and(notifier.status(&status));
+            last_status = status;
+        }
+        if watchdog_interval.is_some() && healthy {
+            result = result.and(notifier.watchdog());
+        }
+        if let Err(err) = result {
+            eprintln!("could not notify systemd: {}", err);
+        }
+
+        thread::park_timeout(tick);
+    }
+
+    if let Err(err) = notifier.stopping() {
+        eprintln!("could not notify systemd: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::linux::net::SocketAddrExt,
+        os::unix::net::{SocketAddr, UnixDatagram},
+        process,
+    };
+
+    use super::*;
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 256];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn reports_ready_while_the_estop_is_latched() {
+        let name = format!("dispenser-service-{}", process::id());
+        let socket = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let notifier = Notifier::new(&format!("@{}", name)).unwrap();
+
+        // the E-stop was latched at boot, so the GPIO thread never got ready
+        let state = Arc::new(SharedState::default());
+        state.estop.store(true, Ordering::SeqCst);
+        let thread_state = Arc::clone(&state);
+        let handle = thread::spawn(move || run_service_thread(&thread_state, &notifier, 2));
+
+        assert_eq!(recv(&socket), "READY=1");
+        assert_eq!(recv(&socket), "STATUS=Emergency stop");
+
+        state.exit_flag.store(true, Ordering::SeqCst);
+        handle.thread().unpark();
+        handle.join().unwrap();
+        assert_eq!(recv(&socket), "STOPPING=1");
+    }
+}
@@ -0,0 +1,68 @@
+/*
+gui/shutdown.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Handling of SIGTERM and SIGINT, so stopping the service leaves the hardware in a safe state.
+
+use std::{
+    io,
+    process,
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::Duration,
+};
+
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use super::{music_thread::force_disable_buzzers, SharedState};
+
+/// Starts a thread that shuts the app down when SIGTERM or SIGINT is received.
+///
+/// Shutting down works the same way as the QUIT button: the window is closed, which drops the `Application`,
+/// which stops the worker threads. They abort whatever they're doing, return the servos to rest and turn off the
+/// buzzers on the way out. If that takes longer than the configured deadline (or a second signal arrives), the
+/// process exits anyway, after turning off the buzzers directly.
+pub(super) fn spawn_signal_thread(state: Arc<SharedState>, egui_ctx: egui::Context) -> io::Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    // fall back to the default deadline if the configured one makes no sense
+    let deadline = Duration::try_from_secs_f64(state.config.shutdown.deadline_secs)
+        .unwrap_or(Duration::from_secs(5));
+
+    thread::spawn(move || {
+        let mut signals = signals.forever();
+        let Some(signal) = signals.next() else {
+            return;
+        };
+        println!("received signal {}, shutting down", signal);
+
+        // stop the worker threads as soon as possible, then close the window like the QUIT button does
+        state.exit_flag.store(true, Ordering::SeqCst);
+        egui_ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        egui_ctx.request_repaint();
+
+        // if everything hasn't shut down in time, the process ends here
+        let pins = state.config.music.buzzer_pins.clone();
+        thread::spawn(move || {
+            thread::sleep(deadline);
+            force_exit(&format!("shutdown took longer than {:?}", deadline), &pins);
+        });
+        if signals.next().is_some() {
+            force_exit("received a second signal", &state.config.music.buzzer_pins);
+        }
+    });
+    Ok(())
+}
+
+/// Exits immediately, making sure the buzzers are off first. The servos can't be moved here since the GPIO
+/// thread still owns them, but with no control pulses, they just stay where they are.
+fn force_exit(reason: &str, buzzer_pins: &[u8]) -> ! {
+    eprintln!("{}, forcing exit", reason);
+    force_disable_buzzers(buzzer_pins);
+    process::exit(1);
+}
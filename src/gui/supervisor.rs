@@ -0,0 +1,214 @@
+/*
+gui/supervisor.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Supervision of the worker threads, restarting them if they panic.
+//!
+//! A crash is shown as a fault, which stops orders from starting. Once the restarted worker has set up its
+//! hardware again, the fault is cleared by itself, so staff only need to step in if the worker keeps crashing.
+
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::SharedState;
+
+/// Time to wait before restarting a worker the first time it dies. This doubles every time the worker
+/// dies again, up to [`MAX_BACKOFF`].
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest time to wait before restarting a worker.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// If a worker stays up this long, it's considered healthy again and the backoff resets.
+const HEALTHY_TIME: Duration = Duration::from_secs(120);
+
+/// Function that a worker thread runs.
+pub(super) type WorkerFn = fn(Arc<SharedState>, egui::Context);
+/// Function that cleans up after a worker thread dies, e.g. by putting its hardware into a safe state.
+pub(super) type RecoverFn = fn(&SharedState);
+/// Function that returns the flag a worker thread sets once its hardware is set up.
+pub(super) type ReadyFn = fn(&SharedState) -> &AtomicBool;
+
+/// What a worker thread is currently doing.
+pub(super) enum WorkerStatus {
+    Running,
+    /// The worker died, and will be restarted at the given time.
+    Restarting(Instant),
+    /// The worker exited normally, because the app is exiting.
+    Stopped,
+}
+
+/// A worker thread that is restarted with a backoff whenever it panics.
+pub(super) struct Worker {
+    pub name: &'static str,
+    run: WorkerFn,
+    recover: RecoverFn,
+    ready: ReadyFn,
+    handle: Option<JoinHandle<()>>,
+    started_at: Instant,
+    restart_at: Option<Instant>,
+    backoff: Duration,
+    /// Number of times this worker has died.
+    pub faults: u32,
+    /// Panic message from the last time this worker died.
+    pub last_panic: Option<String>,
+    // Fault set when the worker last died, until the restarted worker is ready again.
+    crash_fault: Option<String>,
+}
+
+/// Takes a worker thread off the count of live workers when it's dropped.
+struct AliveGuard(Arc<SharedState>);
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.live_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Extracts a readable message from a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+impl Worker {
+    /// Starts a worker thread.
+    pub fn spawn(
+        name: &'static str,
+        run: WorkerFn,
+        recover: RecoverFn,
+        ready: ReadyFn,
+        state: &Arc<SharedState>,
+        egui_ctx: &egui::Context,
+    ) -> Self {
+        let mut worker = Self {
+            name,
+            run,
+            recover,
+            ready,
+            handle: None,
+            started_at: Instant::now(),
+            restart_at: None,
+            backoff: MIN_BACKOFF,
+            faults: 0,
+            last_panic: None,
+            crash_fault: None,
+        };
+        worker.start(state, egui_ctx);
+        worker
+    }
+
+    fn start(&mut self, state: &Arc<SharedState>, egui_ctx: &egui::Context) {
+        let run = self.run;
+        let thread_state = Arc::clone(state);
+        let egui_ctx = egui_ctx.clone();
+        state.live_workers.fetch_add(1, Ordering::SeqCst);
+        let handle = thread::Builder::new()
+            .name(self.name.to_owned())
+            .spawn(move || {
+                // counts the thread as dead when it ends, even if it panics
+                let _alive = AliveGuard(Arc::clone(&thread_state));
+                run(thread_state, egui_ctx)
+            })
+            .expect("could not spawn worker thread");
+        // keep the shared list of worker threads up to date, replacing this worker's old thread
+        let mut threads = state.worker_threads.lock().unwrap();
+        threads.retain(|thread| thread.name() != Some(self.name));
+        threads.push(handle.thread().clone());
+
+        self.handle = Some(handle);
+        self.started_at = Instant::now();
+        self.restart_at = None;
+    }
+
+    /// Returns what the worker is currently doing.
+    pub fn status(&self) -> WorkerStatus {
+        match (&self.handle, self.restart_at) {
+            (Some(_), _) => WorkerStatus::Running,
+            (None, Some(restart_at)) => WorkerStatus::Restarting(restart_at),
+            (None, None) => WorkerStatus::Stopped,
+        }
+    }
+
+    /// Interrupts the worker thread, if it's running.
+    pub fn unpark(&self) {
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+
+    /// Checks whether the worker has died, and restarts it once its backoff has passed.
+    /// This should be called regularly from the GUI thread.
+    pub fn poll(&mut self, state: &Arc<SharedState>, egui_ctx: &egui::Context) {
+        if state.exit_flag.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if self.handle.as_ref().is_some_and(|handle| handle.is_finished()) {
+            let result = self.handle.take().unwrap().join();
+            let msg = match result {
+                Err(payload) => panic_message(&*payload),
+                Ok(()) => "exited unexpectedly".to_owned(),
+            };
+
+            // a worker that stayed up for a while is healthy, so don't hold its old crashes against it
+            if self.started_at.elapsed() >= HEALTHY_TIME {
+                self.backoff = MIN_BACKOFF;
+            }
+            self.faults += 1;
+            self.restart_at = Some(Instant::now() + self.backoff);
+            eprintln!("{} thread died: {}; restarting in {:?}", self.name, msg, self.backoff);
+            let fault = format!("{} thread crashed: {}", self.name, msg);
+            *state.fault.lock().unwrap() = Some(fault.clone());
+            self.crash_fault = Some(fault);
+            self.last_panic = Some(msg);
+            self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+
+            // the dead thread's hardware is gone, so it isn't ready until the new one sets it up again
+            (self.ready)(state).store(false, Ordering::SeqCst);
+            (self.recover)(state);
+            egui_ctx.request_repaint();
+        }
+
+        if self.restart_at.is_some_and(|restart_at| Instant::now() >= restart_at) {
+            self.start(state, egui_ctx);
+            egui_ctx.request_repaint();
+        }
+
+        // the restarted worker is back in business, so its crash no longer needs to hold up orders
+        if self.handle.is_some() && self.crash_fault.is_some() && (self.ready)(state).load(Ordering::SeqCst) {
+            let crash_fault = self.crash_fault.take();
+            let mut fault = state.fault.lock().unwrap();
+            // anything that went wrong since then is a different fault, which staff still need to see
+            if *fault == crash_fault {
+                *fault = None;
+                drop(fault);
+                state.unpark_workers();
+                egui_ctx.request_repaint();
+            }
+        }
+    }
+
+    /// Waits for the worker thread to exit. The exit flag should be set before calling this.
+    pub fn join(&mut self) {
+        self.restart_at = None;
+        if let Some(handle) = self.handle.take() {
+            if let Err(payload) = handle.join() {
+                eprintln!("{} thread panicked while exiting: {}", self.name, panic_message(&*payload));
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/*
+gui/surprise.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! The "surprise me" row on the order screen, which fills in the counters with a random order.
+
+use egui::{Button, Ui, Vec2};
+
+use crate::i18n::Msg;
+
+use super::{counter::Counter, Application};
+
+impl Application {
+    /// Fills in the counters with a random order of the size on the surprise counter, from what's in stock.
+    fn pick_surprise(&mut self) {
+        let config = &self.shared_state.config;
+        let lang = self.language;
+        let total = self.cnt_surprise.count();
+        let max_items = config.surprise.max_items.min(config.limits.order_max);
+        if total > max_items {
+            self.surprise_message = Some(lang.format(Msg::SurpriseTooMany, &[&lang.format_number(max_items)]));
+            return;
+        }
+
+        // a slot can't go over its limit, or what's left of it if it's tracked
+        let stock = self.shared_state.inventory.lock().unwrap().stock;
+        let capacities = config.inventory.capacities();
+        let maxes = [config.limits.red_max, config.limits.green_max];
+        let available = [0, 1].map(|slot| match capacities[slot] {
+            Some(_) => Some(stock[slot].min(maxes[slot])),
+            None => Some(maxes[slot]),
+        });
+        match self.surprise.pick(total, available) {
+            Some([red, green]) => {
+                self.cnt_red.set_count(red);
+                self.cnt_green.set_count(green);
+                self.surprise_message = None;
+            }
+            None => self.surprise_message = Some(lang.text(Msg::SurpriseUnavailable).to_owned()),
+        }
+    }
+
+    /// Draws the counter for the size of a surprise order, and the button that picks it.
+    pub(super) fn surprise_ui(&mut self, ui: &mut Ui, enabled: bool) {
+        ui.allocate_ui_with_layout(Vec2::new(300.0, 150.0), egui::Layout::left_to_right(egui::Align::Center), |ui| {
+            let lang = self.language;
+            let counter = Counter::new(&mut self.cnt_surprise).with_language(lang).with_header(lang.text(Msg::Surprise));
+            ui.add_enabled(enabled, counter);
+            if ui.add_enabled(enabled, Button::new(lang.text(Msg::SurpriseMe)).min_size(self.theme.button_size())).clicked() {
+                self.pick_surprise();
+            }
+        });
+        if let Some(msg) = &self.surprise_message {
+            ui.label(msg);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/*
+gui/theme.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Colours, fonts, logo and names for the GUI, so the dispenser can be dressed up for each venue.
+//!
+//! The theme is a separate TOML file, set with `theme_file` in the config. Like the config, every setting is
+//! optional, and anything missing keeps the default egui look.
+
+use std::{fs, io};
+
+use egui::{Color32, FontData, FontDefinitions, FontFamily, TextureHandle, TextureOptions, Vec2, Visuals};
+use serde::Deserialize;
+
+use crate::leds::Rgb;
+
+/// Everything that can be changed by a theme file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Theme {
+    /// Title of the window.
+    pub title: String,
+    /// Heading shown at the top of the order screen. Nothing is shown if it's empty.
+    pub heading: String,
+    /// True for light text on a dark background, false for the opposite. The colours below go on top of this.
+    pub dark: bool,
+    pub background: Option<Rgb>,
+    pub text: Option<Rgb>,
+    /// Colour of buttons and other things that can be pressed.
+    pub button: Option<Rgb>,
+    /// Colour of selected things, like the admin tabs.
+    pub highlight: Option<Rgb>,
+    /// TTF or OTF file to use for all text, instead of egui's own font.
+    pub font_file: Option<String>,
+    /// Factor to multiply every text size by.
+    pub text_scale: f32,
+    /// PNG image shown at the top of the order screen.
+    pub logo: Option<String>,
+    /// Height of the logo on screen. Its width follows from the image.
+    pub logo_height: f32,
+    /// Smallest size of the big buttons (START, PAUSE and so on). A height of 0 leaves it up to egui.
+    pub button_width: f32,
+    pub button_height: f32,
+    /// Names shown above each counter, instead of the translated "RED" and "GREEN".
+    pub red_name: Option<String>,
+    pub green_name: Option<String>,
+    /// Accent colours for each counter's header and buttons.
+    pub red_colour: Option<Rgb>,
+    pub green_colour: Option<Rgb>,
+    /// PNG images of what's in each slot, shown in attract mode. Slots without one are drawn as a circle
+    /// in their accent colour.
+    pub red_art: Option<String>,
+    pub green_art: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            title: "POOTIS PENCER HERE".to_owned(),
+            heading: String::new(),
+            dark: true,
+            background: None,
+            text: None,
+            button: None,
+            highlight: None,
+            font_file: None,
+            text_scale: 1.0,
+            logo: None,
+            logo_height: 40.0,
+            button_width: 150.0,
+            button_height: 0.0,
+            red_name: None,
+            green_name: None,
+            red_colour: None,
+            green_colour: None,
+            red_art: None,
+            green_art: None,
+        }
+    }
+}
+
+/// Converts a colour from the theme file to the kind egui uses.
+fn colour(rgb: Rgb) -> Color32 {
+    Color32::from_rgb(rgb.0, rgb.1, rgb.2)
+}
+
+impl Theme {
+    /// Loads the theme from a file. A missing or broken theme file isn't worth refusing to start over,
+    /// so this falls back to the default theme.
+    pub fn load(path: Option<&str>) -> Self {
+        let Some(path) = path else {
+            return Self::default();
+        };
+        let result = fs::read_to_string(path)
+            .and_then(|text| toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)));
+        let mut theme: Self = result.unwrap_or_else(|err| {
+            eprintln!("could not load theme {}: {}", path, err);
+            Self::default()
+        });
+        // a scale of 0 (or less) would make every bit of text vanish
+        if !(theme.text_scale.is_finite() && theme.text_scale > 0.0) {
+            eprintln!("ignoring text_scale in theme {}: must be a positive number", path);
+            theme.text_scale = 1.0;
+        }
+        theme
+    }
+
+    /// Returns the smallest size of the big buttons.
+    pub fn button_size(&self) -> Vec2 {
+        Vec2::new(self.button_width, self.button_height)
+    }
+
+    /// Returns the name (if the theme has one) and accent colour of a slot (0 is red, 1 is green).
+    pub fn slot(&self, slot: usize) -> (Option<&str>, Option<Color32>) {
+        match slot {
+            0 => (self.red_name.as_deref(), self.red_colour.map(colour)),
+            _ => (self.green_name.as_deref(), self.green_colour.map(colour)),
+        }
+    }
+
+    /// Sets up egui's colours and fonts from the theme.
+    pub fn apply(&self, ctx: &egui::Context) {
+        let mut visuals = if self.dark { Visuals::dark() } else { Visuals::light() };
+        if let Some(background) = self.background {
+            visuals.panel_fill = colour(background);
+            visuals.window_fill = colour(background);
+        }
+        visuals.override_text_color = self.text.map(colour);
+        if let Some(button) = self.button {
+            visuals.widgets.inactive.weak_bg_fill = colour(button);
+            visuals.widgets.inactive.bg_fill = colour(button);
+        }
+        if let Some(highlight) = self.highlight {
+            visuals.selection.bg_fill = colour(highlight);
+        }
+        ctx.set_visuals(visuals);
+
+        if let Some(path) = &self.font_file {
+            match load_font(path) {
+                Ok(data) => {
+                    let mut fonts = FontDefinitions::default();
+                    fonts.font_data.insert("theme".to_owned(), FontData::from_owned(data));
+                    // put the theme's font first, keeping egui's as a fallback for anything it doesn't have
+                    for family in [FontFamily::Proportional, FontFamily::Monospace] {
+                        fonts.families.entry(family).or_default().insert(0, "theme".to_owned());
+                    }
+                    ctx.set_fonts(fonts);
+                }
+                Err(err) => eprintln!("could not load font {}: {}", path, err),
+            }
+        }
+
+        if self.text_scale != 1.0 {
+            ctx.style_mut(|style| {
+                for font in style.text_styles.values_mut() {
+                    font.size *= self.text_scale;
+                }
+            });
+        }
+    }
+
+    /// Loads the logo into a texture, if there is one.
+    pub fn load_logo(&self, ctx: &egui::Context) -> Option<TextureHandle> {
+        load_image(ctx, "logo", self.logo.as_deref()?)
+    }
+
+    /// Loads the artwork for each slot into textures, for the ones that have it.
+    pub fn load_slot_art(&self, ctx: &egui::Context) -> [Option<TextureHandle>; 2] {
+        [
+            self.red_art.as_deref().and_then(|path| load_image(ctx, "red art", path)),
+            self.green_art.as_deref().and_then(|path| load_image(ctx, "green art", path)),
+        ]
+    }
+}
+
+/// Reads a font file, and checks it's a font egui can use. egui panics on a broken font, so it has to be checked
+/// before it gets there.
+fn load_font(path: &str) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    check_font(&data)?;
+    Ok(data)
+}
+
+/// Checks that some data is a TTF or OTF font.
+fn check_font(data: &[u8]) -> io::Result<()> {
+    ab_glyph::FontRef::try_from_slice(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(())
+}
+
+/// Loads an image into a texture, logging it if that fails.
+fn load_image(ctx: &egui::Context, name: &str, path: &str) -> Option<TextureHandle> {
+    let image = match image::open(path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => {
+            eprintln!("could not load {} {}: {}", name, path, err);
+            return None;
+        }
+    };
+    let size = [image.width() as usize, image.height() as usize];
+    let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+    Some(ctx.load_texture(name, image, TextureOptions::LINEAR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_fonts() {
+        // egui's own fonts are fine
+        for (name, font) in FontDefinitions::default().font_data {
+            assert!(check_font(&font.font).is_ok(), "rejected {}", name);
+        }
+        assert!(check_font(&[]).is_err());
+        assert!(check_font(b"definitely not a font").is_err());
+    }
+
+    #[test]
+    fn ignores_bad_text_scales() {
+        let path = std::env::temp_dir().join(format!("dispenser-theme-test-{}.toml", std::process::id()));
+        for (scale, expected) in [("1.5", 1.5), ("0", 1.0), ("-2", 1.0), ("inf", 1.0), ("nan", 1.0)] {
+            fs::write(&path, format!("text_scale = {}\n", scale)).unwrap();
+            let theme = Theme::load(path.to_str());
+            assert_eq!(theme.text_scale, expected, "text_scale = {}", scale);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
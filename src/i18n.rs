@@ -0,0 +1,502 @@
+/*
+i18n.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Translations of the text shown on the GUI, and formatting numbers the way each language writes them.
+//!
+//! Every piece of text is a [`Msg`], and each language has a table with the text for every message. Since the
+//! tables are `match`es, the compiler complains if a language is missing a message. Messages with `{}` in them
+//! have values filled in by [`Language::format`], in order.
+//!
+//! Everything on the screen is translated, admin mode included. Fault messages from the hardware stay in
+//! English, as does the status sent to systemd, since they end up in the logs.
+
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+/// Languages the GUI can be shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "fr")]
+    French,
+}
+
+/// Every piece of text on the GUI that gets translated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Msg {
+    // order screen
+    Red,
+    Green,
+    Start,
+    Pause,
+    Resume,
+    Cancel,
+    Admin,
+    EmergencyStop,
+    OutOfOrder,
+    UnfinishedOrder,
+    ResumeOrder,
+    Discard,
+    Credit,
+    InsertMore,
+    NotEnoughStock,
+    SlotMax,
+    OrderMax,
+    OrderMin,
+    MusicBar,
+    // presets and surprise orders
+    PresetButton,
+    Surprise,
+    SurpriseMe,
+    SurpriseTooMany,
+    SurpriseUnavailable,
+    // badges
+    BadgeGreeting,
+    NoLimit,
+    OverBadgeLimit,
+    LogOut,
+    TapBadge,
+    UnknownBadge,
+    BadgeReaderDown,
+    // scanner
+    ScanRejected,
+    NoSuchPreset,
+    PresetCodesOff,
+    StartedPreset,
+    StartedScannedOrder,
+    StartedScannedOrderId,
+    CodeUsed,
+    OrderRunning,
+    CantStartNow,
+    NotAnOrderCode,
+    InvalidField,
+    CodeNotSigned,
+    BadSignature,
+    CodeNoId,
+    // keypads
+    Count,
+    Clear,
+    Delete,
+    Ok,
+    EnterPin,
+    WrongPin,
+    TooManyWrongPins,
+    TryAgainIn,
+    // admin mode navigation
+    Status,
+    Calibration,
+    Inventory,
+    History,
+    Tests,
+    Settings,
+    CustomerMode,
+    Quit,
+    // admin mode
+    CurrentStatus,
+    StatusEstopUnavailable,
+    StatusEstop,
+    StatusFault,
+    StatusIdle,
+    StatusDispensing,
+    StatusDispensingPaused,
+    StatusFinishing,
+    Thread,
+    ThreadStatus,
+    Faults,
+    LastCrash,
+    Running,
+    RestartingIn,
+    Stopped,
+    EstopUnavailable,
+    EstopOk,
+    EstopLatched,
+    EstopStillPressed,
+    ResetEstop,
+    ResetFault,
+    SimulateCoinPulse,
+    BadgeUid,
+    SimulateTap,
+    RestPosition,
+    PushPosition,
+    PushTime,
+    GapBetweenItems,
+    Save,
+    Defaults,
+    StockLeft,
+    NotTracked,
+    SetStock,
+    Refill,
+    NoOrdersYet,
+    Time,
+    Outcome,
+    OutcomeDone,
+    OutcomeCancelled,
+    OutcomeInterrupted,
+    OutcomeFault,
+    OutcomeEstop,
+    TestsHelp,
+    PushRed,
+    PushGreen,
+    MuteMusic,
+    AdminTimeout,
+    DefaultPin,
+    SavedPresets,
+    PresetName,
+    SavePreset,
+    PresetDetails,
+    DeletePreset,
+    // attract mode
+    TapToStart,
+}
+
+impl Language {
+    /// Every language, in the order they're offered on the screen.
+    pub const ALL: [Language; 2] = [Language::English, Language::French];
+
+    /// Returns the name of the language, in that language.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::French => "Français",
+        }
+    }
+
+    /// Returns the text for a message.
+    pub fn text(self, msg: Msg) -> &'static str {
+        match self {
+            Language::English => english(msg),
+            Language::French => french(msg),
+        }
+    }
+
+    /// Returns the text for a message, with each `{}` in it replaced by the next value.
+    pub fn format(self, msg: Msg, values: &[&dyn Display]) -> String {
+        let mut values = values.iter();
+        let mut parts = self.text(msg).split("{}");
+        let mut text = parts.next().unwrap_or_default().to_owned();
+        for part in parts {
+            if let Some(value) = values.next() {
+                text.push_str(&value.to_string());
+            }
+            text.push_str(part);
+        }
+        text
+    }
+
+    /// Formats a whole number, with the thousands separated the way the language does it.
+    pub fn format_number(self, number: u64) -> String {
+        let separator = match self {
+            Language::English => ",",
+            // a no-break space, so a number is never split across lines
+            Language::French => "\u{a0}",
+        };
+        let digits = number.to_string();
+        // groups of three digits, counting from the right
+        let groups: Vec<&str> = digits
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .map(|group| std::str::from_utf8(group).expect("digits are ASCII"))
+            .collect();
+        groups.join(separator)
+    }
+
+    /// Formats an amount of money in cents, e.g. `$1,234.50` in English or `1 234,50 $` in French.
+    pub fn format_money(self, currency: &str, cents: u64) -> String {
+        let units = self.format_number(cents / 100);
+        match self {
+            Language::English => format!("{}{}.{:02}", currency, units, cents % 100),
+            Language::French => format!("{},{:02}\u{a0}{}", units, cents % 100, currency),
+        }
+    }
+}
+
+fn english(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Red => "RED",
+        Msg::Green => "GREEN",
+        Msg::Start => "START",
+        Msg::Pause => "PAUSE",
+        Msg::Resume => "RESUME",
+        Msg::Cancel => "CANCEL",
+        Msg::Admin => "ADMIN",
+        Msg::EmergencyStop => "EMERGENCY STOP - please call staff",
+        Msg::OutOfOrder => "Out of order: {}. Please call staff.",
+        Msg::UnfinishedOrder => "Unfinished order from last time: {}/{} red, {}/{} green dispensed",
+        Msg::ResumeOrder => "RESUME ORDER",
+        Msg::Discard => "DISCARD",
+        Msg::Credit => "Credit: {}    Order: {}",
+        Msg::InsertMore => "Insert {} more",
+        Msg::NotEnoughStock => "Not enough left in stock",
+        Msg::SlotMax => "Max {}",
+        Msg::OrderMax => "An order can have at most {} items",
+        Msg::OrderMin => "An order needs at least {} items",
+        Msg::MusicBar => "Now playing: bar {}",
+        Msg::PresetButton => "{} ({}R {}G)",
+        Msg::Surprise => "SURPRISE",
+        Msg::SurpriseMe => "SURPRISE ME",
+        Msg::SurpriseTooMany => "A surprise can have at most {} items",
+        Msg::SurpriseUnavailable => "Can't pick that many right now",
+        Msg::BadgeGreeting => "Hi {}! Left today: {} (red {}, green {})",
+        Msg::NoLimit => "no limit",
+        Msg::OverBadgeLimit => "That's more than you have left today",
+        Msg::LogOut => "LOG OUT",
+        Msg::TapBadge => "Tap your badge to order",
+        Msg::UnknownBadge => "Unknown badge {}",
+        Msg::BadgeReaderDown => "The badge reader isn't working, so orders can't be started",
+        Msg::ScanRejected => "Scan rejected: {}",
+        Msg::NoSuchPreset => "there's no preset called {}",
+        Msg::PresetCodesOff => "preset codes can't be used here",
+        Msg::StartedPreset => "Started preset {}",
+        Msg::StartedScannedOrder => "Started scanned order",
+        Msg::StartedScannedOrderId => "Started scanned order {}",
+        Msg::CodeUsed => "this code has already been used",
+        Msg::OrderRunning => "an order is already running",
+        Msg::CantStartNow => "the order can't be started right now",
+        Msg::NotAnOrderCode => "not an order code",
+        Msg::InvalidField => "invalid field {} in order code",
+        Msg::CodeNotSigned => "order code is not signed",
+        Msg::BadSignature => "order code has an invalid signature",
+        Msg::CodeNoId => "order code has no ID",
+        Msg::Count => "Count",
+        Msg::Clear => "CLR",
+        Msg::Delete => "DEL",
+        Msg::Ok => "OK",
+        Msg::EnterPin => "ENTER PIN",
+        Msg::WrongPin => "Wrong PIN",
+        Msg::TooManyWrongPins => "Too many wrong PINs",
+        Msg::TryAgainIn => "Try again in {}s",
+        Msg::Status => "STATUS",
+        Msg::Calibration => "CALIBRATION",
+        Msg::Inventory => "INVENTORY",
+        Msg::History => "HISTORY",
+        Msg::Tests => "TESTS",
+        Msg::Settings => "SETTINGS",
+        Msg::CustomerMode => "CUSTOMER MODE",
+        Msg::Quit => "QUIT",
+        Msg::CurrentStatus => "Status: {}",
+        Msg::StatusEstopUnavailable => "Emergency stop unavailable",
+        Msg::StatusEstop => "Emergency stop",
+        Msg::StatusFault => "Fault: {}",
+        Msg::StatusIdle => "Idle",
+        Msg::StatusDispensing => "Dispensing {} red, {} green",
+        Msg::StatusDispensingPaused => "Dispensing {} red, {} green (paused)",
+        Msg::StatusFinishing => "Finishing order",
+        Msg::Thread => "Thread",
+        Msg::ThreadStatus => "Status",
+        Msg::Faults => "Faults",
+        Msg::LastCrash => "Last crash",
+        Msg::Running => "running",
+        Msg::RestartingIn => "restarting in {}s",
+        Msg::Stopped => "stopped",
+        Msg::EstopUnavailable => "Emergency stop: UNAVAILABLE (fix the input and restart)",
+        Msg::EstopOk => "Emergency stop: ok",
+        Msg::EstopLatched => "Emergency stop: LATCHED",
+        Msg::EstopStillPressed => "Emergency stop: LATCHED (still pressed)",
+        Msg::ResetEstop => "RESET E-STOP",
+        Msg::ResetFault => "RESET FAULT",
+        Msg::SimulateCoinPulse => "SIMULATE COIN PULSE",
+        Msg::BadgeUid => "Badge UID:",
+        Msg::SimulateTap => "SIMULATE TAP",
+        Msg::RestPosition => "{} rest",
+        Msg::PushPosition => "{} push",
+        Msg::PushTime => "Push time",
+        Msg::GapBetweenItems => "Gap between items",
+        Msg::Save => "SAVE",
+        Msg::Defaults => "DEFAULTS",
+        Msg::StockLeft => "{}: {} of {} left",
+        Msg::NotTracked => "{}: not tracked",
+        Msg::SetStock => "SET",
+        Msg::Refill => "REFILL",
+        Msg::NoOrdersYet => "No orders yet",
+        Msg::Time => "Time",
+        Msg::Outcome => "Outcome",
+        Msg::OutcomeDone => "done",
+        Msg::OutcomeCancelled => "cancelled",
+        Msg::OutcomeInterrupted => "interrupted",
+        Msg::OutcomeFault => "fault",
+        Msg::OutcomeEstop => "E-stop",
+        Msg::TestsHelp => "Dispenses one item from a slot, using the current calibration.",
+        Msg::PushRed => "PUSH RED",
+        Msg::PushGreen => "PUSH GREEN",
+        Msg::MuteMusic => "Mute music",
+        Msg::AdminTimeout => "Admin mode locks itself after {}s without any input",
+        Msg::DefaultPin => "The admin PIN is still the default. Change it in the config file!",
+        Msg::SavedPresets => "Saved presets ({} of {}):",
+        Msg::PresetName => "Preset name",
+        Msg::SavePreset => "SAVE PRESET",
+        Msg::PresetDetails => "{}: {} red, {} green",
+        Msg::DeletePreset => "DELETE",
+        Msg::TapToStart => "TAP TO START",
+    }
+}
+
+fn french(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Red => "ROUGE",
+        Msg::Green => "VERT",
+        Msg::Start => "DÉMARRER",
+        Msg::Pause => "PAUSE",
+        Msg::Resume => "REPRENDRE",
+        Msg::Cancel => "ANNULER",
+        Msg::Admin => "ADMIN",
+        Msg::EmergencyStop => "ARRÊT D'URGENCE - veuillez appeler le personnel",
+        Msg::OutOfOrder => "Hors service : {}. Veuillez appeler le personnel.",
+        Msg::UnfinishedOrder => "Commande inachevée de la dernière fois : {}/{} rouges, {}/{} verts distribués",
+        Msg::ResumeOrder => "REPRENDRE LA COMMANDE",
+        Msg::Discard => "ABANDONNER",
+        Msg::Credit => "Crédit : {}    Commande : {}",
+        Msg::InsertMore => "Insérez encore {}",
+        Msg::NotEnoughStock => "Pas assez en stock",
+        Msg::SlotMax => "Max {}",
+        Msg::OrderMax => "Une commande peut avoir au plus {} articles",
+        Msg::OrderMin => "Une commande doit avoir au moins {} articles",
+        Msg::MusicBar => "Musique : mesure {}",
+        Msg::PresetButton => "{} ({}R {}V)",
+        Msg::Surprise => "SURPRISE",
+        Msg::SurpriseMe => "SURPRENEZ-MOI",
+        Msg::SurpriseTooMany => "Une surprise peut avoir au plus {} articles",
+        Msg::SurpriseUnavailable => "Impossible d'en choisir autant pour l'instant",
+        Msg::BadgeGreeting => "Bonjour {} ! Reste aujourd'hui : {} (rouges {}, verts {})",
+        Msg::NoLimit => "illimité",
+        Msg::OverBadgeLimit => "C'est plus que ce qu'il vous reste aujourd'hui",
+        Msg::LogOut => "DÉCONNEXION",
+        Msg::TapBadge => "Passez votre badge pour commander",
+        Msg::UnknownBadge => "Badge inconnu {}",
+        Msg::BadgeReaderDown => "Le lecteur de badges ne fonctionne pas, les commandes sont impossibles",
+        Msg::ScanRejected => "Code refusé : {}",
+        Msg::NoSuchPreset => "aucun favori nommé {}",
+        Msg::PresetCodesOff => "les codes de favori ne sont pas acceptés ici",
+        Msg::StartedPreset => "Favori {} lancé",
+        Msg::StartedScannedOrder => "Commande scannée lancée",
+        Msg::StartedScannedOrderId => "Commande scannée {} lancée",
+        Msg::CodeUsed => "ce code a déjà été utilisé",
+        Msg::OrderRunning => "une commande est déjà en cours",
+        Msg::CantStartNow => "la commande ne peut pas être lancée pour l'instant",
+        Msg::NotAnOrderCode => "ce n'est pas un code de commande",
+        Msg::InvalidField => "champ {} invalide dans le code de commande",
+        Msg::CodeNotSigned => "le code de commande n'est pas signé",
+        Msg::BadSignature => "la signature du code de commande est invalide",
+        Msg::CodeNoId => "le code de commande n'a pas d'identifiant",
+        Msg::Count => "Nombre",
+        Msg::Clear => "EFF",
+        Msg::Delete => "SUPPR",
+        Msg::Ok => "OK",
+        Msg::EnterPin => "ENTREZ LE CODE",
+        Msg::WrongPin => "Code incorrect",
+        Msg::TooManyWrongPins => "Trop de codes incorrects",
+        Msg::TryAgainIn => "Réessayez dans {} s",
+        Msg::Status => "ÉTAT",
+        Msg::Calibration => "CALIBRAGE",
+        Msg::Inventory => "STOCK",
+        Msg::History => "HISTORIQUE",
+        Msg::Tests => "TESTS",
+        Msg::Settings => "RÉGLAGES",
+        Msg::CustomerMode => "MODE CLIENT",
+        Msg::Quit => "QUITTER",
+        Msg::CurrentStatus => "État : {}",
+        Msg::StatusEstopUnavailable => "Arrêt d'urgence indisponible",
+        Msg::StatusEstop => "Arrêt d'urgence",
+        Msg::StatusFault => "Panne : {}",
+        Msg::StatusIdle => "En attente",
+        Msg::StatusDispensing => "Distribution de {} rouges, {} verts",
+        Msg::StatusDispensingPaused => "Distribution de {} rouges, {} verts (en pause)",
+        Msg::StatusFinishing => "Fin de la commande",
+        Msg::Thread => "Tâche",
+        Msg::ThreadStatus => "État",
+        Msg::Faults => "Pannes",
+        Msg::LastCrash => "Dernier plantage",
+        Msg::Running => "en marche",
+        Msg::RestartingIn => "redémarrage dans {} s",
+        Msg::Stopped => "arrêtée",
+        Msg::EstopUnavailable => "Arrêt d'urgence : INDISPONIBLE (réparez l'entrée et redémarrez)",
+        Msg::EstopOk => "Arrêt d'urgence : ok",
+        Msg::EstopLatched => "Arrêt d'urgence : VERROUILLÉ",
+        Msg::EstopStillPressed => "Arrêt d'urgence : VERROUILLÉ (toujours enfoncé)",
+        Msg::ResetEstop => "RÉARMER L'ARRÊT D'URGENCE",
+        Msg::ResetFault => "EFFACER LA PANNE",
+        Msg::SimulateCoinPulse => "SIMULER UNE PIÈCE",
+        Msg::BadgeUid => "UID du badge :",
+        Msg::SimulateTap => "SIMULER UN BADGE",
+        Msg::RestPosition => "{} repos",
+        Msg::PushPosition => "{} poussée",
+        Msg::PushTime => "Durée de poussée",
+        Msg::GapBetweenItems => "Pause entre les articles",
+        Msg::Save => "ENREGISTRER",
+        Msg::Defaults => "PAR DÉFAUT",
+        Msg::StockLeft => "{} : {} sur {} restants",
+        Msg::NotTracked => "{} : non suivi",
+        Msg::SetStock => "DÉFINIR",
+        Msg::Refill => "REMPLIR",
+        Msg::NoOrdersYet => "Aucune commande pour l'instant",
+        Msg::Time => "Heure",
+        Msg::Outcome => "Résultat",
+        Msg::OutcomeDone => "terminée",
+        Msg::OutcomeCancelled => "annulée",
+        Msg::OutcomeInterrupted => "interrompue",
+        Msg::OutcomeFault => "panne",
+        Msg::OutcomeEstop => "arrêt d'urgence",
+        Msg::TestsHelp => "Distribue un article d'un compartiment, avec le calibrage actuel.",
+        Msg::PushRed => "POUSSER ROUGE",
+        Msg::PushGreen => "POUSSER VERT",
+        Msg::MuteMusic => "Couper la musique",
+        Msg::AdminTimeout => "Le mode admin se verrouille après {} s sans activité",
+        Msg::DefaultPin => "Le code admin est celui par défaut. Changez-le dans le fichier de configuration !",
+        Msg::SavedPresets => "Favoris enregistrés ({} sur {}) :",
+        Msg::PresetName => "Nom du favori",
+        Msg::SavePreset => "ENREGISTRER LE FAVORI",
+        Msg::PresetDetails => "{} : {} rouges, {} verts",
+        Msg::DeletePreset => "SUPPRIMER",
+        Msg::TapToStart => "TOUCHEZ POUR COMMENCER",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_values_in_order() {
+        let text = Language::English.format(Msg::UnfinishedOrder, &[&1, &2, &"3", &4]);
+        assert_eq!(text, "Unfinished order from last time: 1/2 red, 3/4 green dispensed");
+        let text = Language::French.format(Msg::StartedPreset, &[&"Petit"]);
+        assert_eq!(text, "Favori Petit lancé");
+        // missing values are left out, and extra ones are ignored
+        assert_eq!(Language::English.format(Msg::InsertMore, &[]), "Insert  more");
+        assert_eq!(Language::English.format(Msg::OrderMax, &[&5, &6]), "An order can have at most 5 items");
+        // messages without any values come out as is
+        assert_eq!(Language::French.format(Msg::Start, &[&1]), Language::French.text(Msg::Start));
+    }
+
+    #[test]
+    fn formats_numbers() {
+        let english = Language::English;
+        assert_eq!(english.format_number(0), "0");
+        assert_eq!(english.format_number(999), "999");
+        assert_eq!(english.format_number(1000), "1,000");
+        assert_eq!(english.format_number(1_000_000), "1,000,000");
+        assert_eq!(english.format_number(u64::MAX), "18,446,744,073,709,551,615");
+
+        // French uses a no-break space, so numbers aren't split across lines
+        let french = Language::French;
+        assert_eq!(french.format_number(999), "999");
+        assert_eq!(french.format_number(1000), "1\u{a0}000");
+        assert_eq!(french.format_number(1_000_000), "1\u{a0}000\u{a0}000");
+    }
+
+    #[test]
+    fn formats_money() {
+        assert_eq!(Language::English.format_money("$", 0), "$0.00");
+        assert_eq!(Language::English.format_money("$", 5), "$0.05");
+        assert_eq!(Language::English.format_money("$", 123_450), "$1,234.50");
+        assert_eq!(Language::French.format_money("$", 123_450), "1\u{a0}234,50\u{a0}$");
+        assert_eq!(Language::French.format_money("€", 99), "0,99\u{a0}€");
+    }
+}
@@ -0,0 +1,245 @@
+/*
+leds.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Status lights: plain LEDs on GPIO pins, or a WS2812 ("NeoPixel") strip on the SPI bus.
+//!
+//! Both kinds of lights are treated as a row of pixels. Plain LEDs only have one colour, so they just use
+//! the brightness of their pixel.
+
+use std::{f64::consts::PI, fs, str::FromStr};
+
+use rppal::{
+    gpio::OutputPin,
+    spi::{Bus, Mode, SlaveSelect, Spi},
+};
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    gpio,
+};
+
+/// A colour, with 8 bits per channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const OFF: Rgb = Rgb(0, 0, 0);
+
+    /// Scales the colour's brightness by a factor from 0 to 1.
+    pub fn scale(self, factor: f64) -> Rgb {
+        let factor = factor.clamp(0.0, 1.0);
+        let channel = |c: u8| (c as f64 * factor).round() as u8;
+        Rgb(channel(self.0), channel(self.1), channel(self.2))
+    }
+
+    /// Returns the brightness of the brightest channel, from 0 to 1.
+    pub fn brightness(self) -> f64 {
+        self.0.max(self.1).max(self.2) as f64 / 255.0
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = String;
+
+    /// Parses a colour written like `"#ff8000"`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("invalid colour {:?}, expected something like \"#ff8000\"", s);
+        let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6).ok_or_else(invalid)?;
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid());
+        Ok(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// How the lights animate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// All lights off.
+    Off,
+    /// All lights on, without changing.
+    Solid,
+    /// All lights slowly fade in and out.
+    Breathe,
+    /// A lit section runs along the row of lights.
+    Chase,
+    /// All lights blink on and off.
+    Blink,
+}
+
+impl Pattern {
+    /// Works out the colour of every light at a point in time.
+    /// ## Parameters
+    /// - `colour`: Colour of the lights when they're fully on.
+    /// - `time`: Time since the pattern started, in seconds.
+    /// - `pixels`: Output for the colours, one per light.
+    pub fn render(self, colour: Rgb, time: f64, pixels: &mut [Rgb]) {
+        let count = pixels.len();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let level = match self {
+                Pattern::Off => 0.0,
+                Pattern::Solid => 1.0,
+                // one breath every 4 seconds, never quite going out
+                Pattern::Breathe => 0.1 + 0.9 * (0.5 - 0.5 * (time * PI / 2.0).cos()),
+                // the head moves 10 lights per second, with a tail fading out behind it
+                Pattern::Chase => {
+                    let head = time * 10.0;
+                    let behind = (head - i as f64).rem_euclid(count.max(4) as f64);
+                    (1.0 - behind / 4.0).max(0.0)
+                }
+                // 2 blinks per second
+                Pattern::Blink => {
+                    if (time * 2.0).fract() < 0.5 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            *pixel = colour.scale(level);
+        }
+    }
+}
+
+/// Something that can show a row of pixels.
+pub trait Lights {
+    /// Number of pixels.
+    fn pixel_count(&self) -> usize;
+    /// Shows the given colours, one per pixel.
+    fn show(&mut self, pixels: &[Rgb]) -> Result<()>;
+}
+
+/// Plain LEDs on GPIO pins, dimmed with software PWM.
+pub struct GpioLeds {
+    pins: Vec<OutputPin>,
+}
+
+impl GpioLeds {
+    // Fast enough to not flicker, slow enough to not load the CPU too much.
+    const PWM_FREQUENCY: f64 = 200.0;
+
+    /// Sets up LEDs on the given pins. The LEDs should light up when the pin is high.
+    pub fn new(pins: &[u8]) -> Result<GpioLeds> {
+        let pins = pins
+            .iter()
+            .map(|&pin| Ok(gpio::instance()?.get(pin)?.into_output_low()))
+            .collect::<Result<Vec<OutputPin>>>()?;
+        Ok(Self { pins })
+    }
+}
+
+impl Lights for GpioLeds {
+    fn pixel_count(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn show(&mut self, pixels: &[Rgb]) -> Result<()> {
+        for (pin, pixel) in self.pins.iter_mut().zip(pixels) {
+            match pixel.brightness() {
+                level if level <= 0.0 => pin.clear_pwm()?,
+                level => pin.set_pwm_frequency(Self::PWM_FREQUENCY, level)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A strip of WS2812 LEDs, with its data line on the SPI0 MOSI pin (GPIO 10).
+///
+/// WS2812s read a bit as 0 or 1 depending on how long the data line stays high. With the SPI clock at
+/// 2.4 MHz, every bit for the LEDs can be sent as 3 SPI bits: `100` for a 0, and `110` for a 1.
+pub struct Ws2812Strip {
+    spi: Spi,
+    len: usize,
+    buffer: Vec<u8>,
+}
+
+impl Ws2812Strip {
+    const CLOCK_SPEED: u32 = 2_400_000;
+    // The strip latches the colours after the line stays low for a while (up to 280us on newer LEDs).
+    const RESET_BYTES: usize = 90;
+    // Each LED takes 3 colours of 24 SPI bits.
+    const LED_BYTES: usize = 9;
+
+    /// Sets up a strip with the given number of LEDs. The whole strip goes out in one SPI transfer, so this fails
+    /// if it doesn't fit in spidev's buffer (4096 bytes unless `spidev.bufsiz` is set), about 450 LEDs.
+    pub fn new(len: usize) -> Result<Ws2812Strip> {
+        let max = spidev_bufsiz().saturating_sub(Self::RESET_BYTES) / Self::LED_BYTES;
+        if len > max {
+            return Err(Error::StripTooLong(len, max));
+        }
+        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, Self::CLOCK_SPEED, Mode::Mode0)?;
+        Ok(Self { spi, len, buffer: Vec::with_capacity(len * Self::LED_BYTES + Self::RESET_BYTES) })
+    }
+
+    /// Adds one byte of colour to the buffer, as 24 SPI bits.
+    fn encode(buffer: &mut Vec<u8>, byte: u8) {
+        let mut bits: u32 = 0;
+        for i in (0..8).rev() {
+            let bit = (byte >> i) & 1;
+            bits = (bits << 3) | 0b100 | ((bit as u32) << 1);
+        }
+        buffer.extend_from_slice(&bits.to_be_bytes()[1..]);
+    }
+}
+
+/// Returns the most bytes spidev can send in one transfer.
+fn spidev_bufsiz() -> usize {
+    const DEFAULT_BUFSIZ: usize = 4096;
+    fs::read_to_string("/sys/module/spidev/parameters/bufsiz")
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(DEFAULT_BUFSIZ)
+}
+
+impl Lights for Ws2812Strip {
+    fn pixel_count(&self) -> usize {
+        self.len
+    }
+
+    fn show(&mut self, pixels: &[Rgb]) -> Result<()> {
+        self.buffer.clear();
+        for pixel in pixels.iter().take(self.len) {
+            // WS2812s take green first
+            for byte in [pixel.1, pixel.0, pixel.2] {
+                Self::encode(&mut self.buffer, byte);
+            }
+        }
+        self.buffer.resize(self.buffer.len() + Self::RESET_BYTES, 0);
+        self.spi.write(&self.buffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_strips_too_long_for_spidev() {
+        // this is checked before the SPI bus is opened, so it works without one
+        let max = (spidev_bufsiz() - Ws2812Strip::RESET_BYTES) / Ws2812Strip::LED_BYTES;
+        assert!(matches!(Ws2812Strip::new(max + 1), Err(Error::StripTooLong(len, m)) if len == max + 1 && m == max));
+    }
+
+    #[test]
+    fn encodes_bits_as_spi_pulses() {
+        let mut buffer = Vec::new();
+        Ws2812Strip::encode(&mut buffer, 0b1000_0001);
+        // 110 100 100 100 100 100 100 110
+        assert_eq!(buffer, [0b1101_0010, 0b0100_1001, 0b0010_0110]);
+    }
+}
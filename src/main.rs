@@ -8,14 +8,27 @@ Date: Jun. 17, 2024
 //! Contains the main function, as well as a couple of nice functions for waiting
 //! with the possibility of an interrupt.
 
-use std::{thread, time::{Duration, Instant}};
+use std::{env, process, thread, time::{Duration, Instant}};
 
+use config::Config;
 use gui::Application;
 
+mod clock;
+mod coin;
+mod config;
+mod display;
+mod error;
 mod gpio;
 mod gui;
+mod i18n;
+mod leds;
 mod pwm;
+mod rfid;
 mod music;
+mod order_code;
+mod scanner;
+mod surprise;
+mod systemd;
 
 // NOTE BELOW: In Rust, threads can be "parked", or put to sleep in a way that allows them to be interrupted.
 // Interrupting a thread is done by calling Thread::unpark(). If a thread is unparked without already being parked, the next park will immediately end.
@@ -74,5 +87,60 @@ fn main() {
     //     }
     // }
 
-    Application::run();
+    let args: Vec<String> = env::args().collect();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    // `dispenser render <song> <out.wav> [measure] [buzzers]` renders a song to a file instead of starting the GUI.
+    // This doesn't touch GPIO at all, so it works on any computer. By default, it renders for as many buzzers as the config has.
+    // The song can be a built-in song or a song file, and is transformed according to the config like it would be on the dispenser.
+    if args.get(1).map(String::as_str) == Some("render") {
+        let (Some(name), Some(out_path)) = (args.get(2), args.get(3)) else {
+            eprintln!("usage: {} render <song> <out.wav> [measure] [buzzers]", args[0]);
+            process::exit(2);
+        };
+        let Ok(from_measure) = args.get(4).map_or(Ok(0), |arg| arg.parse::<u32>()) else {
+            eprintln!("measure must be a non-negative number");
+            process::exit(2);
+        };
+        let default_buzzers = config.music.buzzer_pins.len();
+        let Ok(buzzers) = args.get(5).map_or(Ok(default_buzzers), |arg| arg.parse::<usize>()) else {
+            eprintln!("buzzers must be a non-negative number");
+            process::exit(2);
+        };
+        let song = match music::load_song(name, &config.music.transform()) {
+            Ok(song) => song,
+            Err(err) => {
+                let names: Vec<&str> = music::SONGS.iter().map(|song| &*song.name).collect();
+                eprintln!("{}\nbuilt-in songs: {}", err, names.join(", "));
+                process::exit(2);
+            }
+        };
+        if let Err(err) = music::render::render_wav(out_path, &song, from_measure, buzzers) {
+            eprintln!("could not write {}: {}", out_path, err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // `dispenser sign-order <code>` signs an order code with the key from the config, for making QR codes.
+    if args.get(1).map(String::as_str) == Some("sign-order") {
+        let Some(payload) = args.get(2) else {
+            eprintln!("usage: {} sign-order \"DISPENSE;R=<red>;G=<green>[;ID=<id>]\"", args[0]);
+            process::exit(2);
+        };
+        let Some(key) = &config.scanner.key else {
+            eprintln!("no signing key set in the config (scanner.key)");
+            process::exit(2);
+        };
+        println!("{}", order_code::sign_order_code(payload, key.as_bytes()));
+        return;
+    }
+
+    Application::run(config);
 }
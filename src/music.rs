@@ -8,13 +8,217 @@ Date: Jun. 17, 2024
 //! Contains utilities for programming and playing music 
 //! on buzzers via the Pi's PWM channels.
 
-use std::{time::Duration};
+use std::{borrow::Cow, time::Duration};
 
+use crate::error;
 use crate::pwm::PwmToneBuzzer;
 use crate::wait_interruptible;
 
+use timing::{TempoChange, TimeSignature, Timeline};
+
 pub mod rick;
 pub mod badapple;
+pub mod file;
+pub mod render;
+pub mod timing;
+
+/// How a note is played in relation to the notes around it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Articulation {
+    /// Held for its full length, except for a short break if the next note is the same pitch.
+    Normal,
+    /// Detached: only sounds for the first half of its length.
+    Staccato,
+    /// Slurred: held for its full length with no break, even if the next note is the same pitch.
+    Legato,
+    /// Tied to the next note: if it has the same pitch, both sound as one continuous note.
+    Tie,
+}
+
+/// A single note (or rest) in a song.
+#[derive(Clone, Copy, Debug)]
+pub struct Note {
+    /// MIDI index of the note, or 0 for a rest.
+    pub midi: u32,
+    /// Length of the note, in beats.
+    pub beats: f64,
+    /// Articulation of the note, or `None` to use the song's default.
+    pub articulation: Option<Articulation>,
+    /// Accented notes are played louder than the notes around them.
+    pub accent: bool,
+    /// Volume of the note from 0 to 1, or `None` to use the song's default.
+    pub volume: Option<f64>,
+}
+
+impl Note {
+    /// Creates a note from a MIDI index and a length in beats, using the song's defaults for everything else.
+    pub const fn new(midi: u32, beats: f64) -> Self {
+        Self {
+            midi,
+            beats,
+            articulation: None,
+            accent: false,
+            volume: None,
+        }
+    }
+
+    /// Plays this note staccato.
+    pub const fn staccato(self) -> Self {
+        Self { articulation: Some(Articulation::Staccato), ..self }
+    }
+
+    /// Plays this note legato.
+    pub const fn legato(self) -> Self {
+        Self { articulation: Some(Articulation::Legato), ..self }
+    }
+
+    /// Ties this note to the next one.
+    pub const fn tie(self) -> Self {
+        Self { articulation: Some(Articulation::Tie), ..self }
+    }
+
+    /// Accents this note.
+    pub const fn accent(self) -> Self {
+        Self { accent: true, ..self }
+    }
+
+    /// Sets the volume of this note, from 0 to 1.
+    pub const fn volume(self, volume: f64) -> Self {
+        Self { volume: Some(volume), ..self }
+    }
+}
+
+/// Shorthand for a note, given its name (see [`note2midi`]) and length in beats.
+pub const fn note(name: &str, beats: f64) -> Note {
+    Note::new(note2midi(name), beats)
+}
+
+/// Shorthand for a rest, given its length in beats.
+pub const fn rest(beats: f64) -> Note {
+    Note::new(0, beats)
+}
+
+/// A song, along with the defaults used for notes that don't specify their own articulation or volume.
+/// Built-in songs borrow their data, while songs loaded from files or transformed at load time own theirs.
+#[derive(Clone, Debug)]
+pub struct Song {
+    pub name: Cow<'static, str>,
+    /// Tempo at the start of the song, in BPM.
+    pub bpm: f64,
+    /// Changes in tempo, in order.
+    pub tempo_changes: Cow<'static, [TempoChange]>,
+    /// Time signatures, in order. Songs without a time signature are in 4/4.
+    pub time_signatures: Cow<'static, [TimeSignature]>,
+    /// Length of the pickup (the notes before measure 1), in beats.
+    pub pickup: f64,
+    pub articulation: Articulation,
+    pub volume: f64,
+    /// The notes in each voice of the song. The first voice is the melody; if there are more voices
+    /// than buzzers, the extra voices are arpeggiated on the last buzzer.
+    pub voices: Cow<'static, [Cow<'static, [Note]>]>,
+}
+
+/// Changes applied to a song when it's loaded, so it sounds better on the buzzers.
+#[derive(Clone, Copy, Debug)]
+pub struct SongTransform {
+    /// Number of semitones to transpose by. Positive numbers go up, negative numbers go down.
+    pub transpose: i32,
+    /// Factor to multiply every tempo in the song by.
+    pub tempo_scale: f64,
+    /// Lowest playable note. Anything lower (after transposing) is moved up by octaves until it's in range.
+    pub lowest: Option<u32>,
+    /// Highest playable note. Anything higher (after transposing) is moved down by octaves until it's in range.
+    pub highest: Option<u32>,
+}
+
+impl Default for SongTransform {
+    fn default() -> Self {
+        Self {
+            transpose: 0,
+            tempo_scale: 1.0,
+            lowest: None,
+            highest: None,
+        }
+    }
+}
+
+impl SongTransform {
+    /// Checks that the transform makes sense, returning what's wrong with it if it doesn't.
+    pub fn check(&self) -> Result<(), &'static str> {
+        if !(self.tempo_scale.is_finite() && self.tempo_scale > 0.0) {
+            return Err("tempo scale must be a positive number");
+        }
+        Ok(())
+    }
+
+    /// Applies the transform to a single MIDI note. Rests are left alone.
+    fn apply_midi(&self, midi: u32) -> u32 {
+        if midi == 0 {
+            return 0;
+        }
+        // MIDI notes only go up to 127, and 0 is reserved for rests.
+        let mut midi = (midi as i32 + self.transpose).clamp(1, 127) as u32;
+        if let Some(lowest) = self.lowest {
+            while midi < lowest && midi + 12 <= 127 {
+                midi += 12;
+            }
+        }
+        if let Some(highest) = self.highest {
+            while midi > highest && midi > 12 {
+                midi -= 12;
+            }
+        }
+        midi
+    }
+}
+
+impl Song {
+    /// Returns a copy of this song with a transform applied.
+    pub fn transformed(&self, transform: &SongTransform) -> Song {
+        let tempo_changes: Vec<TempoChange> = self
+            .tempo_changes
+            .iter()
+            .map(|change| TempoChange { bpm: change.bpm * transform.tempo_scale, ..*change })
+            .collect();
+        let voices: Vec<Cow<'static, [Note]>> = self
+            .voices
+            .iter()
+            .map(|data| {
+                let data: Vec<Note> = data
+                    .iter()
+                    .map(|note| Note { midi: transform.apply_midi(note.midi), ..*note })
+                    .collect();
+                Cow::Owned(data)
+            })
+            .collect();
+
+        Song {
+            bpm: self.bpm * transform.tempo_scale,
+            tempo_changes: Cow::Owned(tempo_changes),
+            voices: Cow::Owned(voices),
+            ..self.clone()
+        }
+    }
+}
+
+/// All songs built into the program.
+pub static SONGS: [Song; 2] = [badapple::SONG, rick::SONG];
+
+/// Looks up a built-in song by name.
+pub fn find_song(name: &str) -> Option<&'static Song> {
+    SONGS.iter().find(|song| song.name == name)
+}
+
+/// Loads a song, which can either be the name of a built-in song or the path to a song file
+/// (see [`file`] for the format), and applies a transform to it.
+pub fn load_song(name_or_path: &str, transform: &SongTransform) -> Result<Song, file::SongFileError> {
+    transform.check().map_err(file::SongFileError::Transform)?;
+    let song = match find_song(name_or_path) {
+        Some(song) => song.clone(),
+        None => file::read_song(name_or_path)?,
+    };
+    Ok(song.transformed(transform))
+}
 
 /// Converts a note name to its MIDI value.
 /// 
@@ -34,11 +238,24 @@ pub mod badapple;
 /// F#6
 /// ```
 pub(crate) const fn note2midi(name_str: &str) -> u32 {
+    match try_note2midi(name_str) {
+        Ok(midi) => midi,
+        Err(msg) => panic!("{}", msg),
+    }
+}
+
+/// Converts a note name to its MIDI value, returning an error message if the name is invalid.
+/// This is used for note names that are only known at runtime, such as those from song files.
+/// See [`note2midi`] for the format.
+pub(crate) const fn try_note2midi(name_str: &str) -> Result<u32, &'static str> {
     if !name_str.is_ascii() {
-        panic!("note names must be ASCII");
+        return Err("note names must be ASCII");
     }
 
     let name = name_str.as_bytes();
+    if name.len() < 2 {
+        return Err("note names must have a letter and a number");
+    }
 
     let mut idx: usize;
 
@@ -51,7 +268,7 @@ pub(crate) const fn note2midi(name_str: &str) -> u32 {
         b'G' => 7,
         b'A' => 9,
         b'B' => 11,
-        _ => panic!("note names must start with a letter A-G")
+        _ => return Err("note names must start with a letter A-G")
     };
     // Add in any accidental (sharp '#' or flat 'b') if present
     match name[1] {
@@ -70,73 +287,489 @@ pub(crate) const fn note2midi(name_str: &str) -> u32 {
                 1.. => midi - 1,
             }
         }
-        _ => panic!("note names must have a number, optionally preceded by an accidental (# or b)")
+        _ => return Err("note names must have a number, optionally preceded by an accidental (# or b)")
+    }
+    if idx >= name.len() {
+        return Err("note names must end with a number");
     }
 
     // Treat the remaining digits as the octave number. Parsing needs to be done manually due to
     // ensure the function can run at compile-time.
     {
+        const TOO_HIGH: &str = "note is too high; MIDI notes only go up to G9";
         let mut octave: u32 = 0;
         while idx < name.len() {
-            octave = octave * 10 + match name[idx] {
+            let digit = match name[idx] {
                 b'0'..=b'9' => (name[idx] - b'0') as u32,
-                _ => panic!("note names must end with a number")
+                _ => return Err("note names must end with a number")
+            };
+            octave = match octave.checked_mul(10) {
+                Some(octave) => match octave.checked_add(digit) {
+                    Some(octave) => octave,
+                    None => return Err(TOO_HIGH),
+                },
+                None => return Err(TOO_HIGH),
             };
             idx += 1;
         }
 
         // compute the final MIDI note by adding the correct number of semitones
-        midi += (octave + 1) * 12;
+        midi = match octave.checked_add(1) {
+            Some(octaves) => match octaves.checked_mul(12) {
+                Some(semitones) => match midi.checked_add(semitones) {
+                    Some(midi) => midi,
+                    None => return Err(TOO_HIGH),
+                },
+                None => return Err(TOO_HIGH),
+            },
+            None => return Err(TOO_HIGH),
+        };
+        if midi > 127 {
+            return Err(TOO_HIGH);
+        }
     }
 
-    midi
+    Ok(midi)
+}
+
+/// Converts a MIDI note to its frequency in Hz, assuming A4 = 440 Hz.
+pub fn midi2freq(midi: u32) -> f64 {
+    // this optimized formula converts a MIDI note to a frequency.
+    (midi as f64 / 12.0).exp2() * 8.175_798_915_643_707
 }
 
-/// Plays music as defined by an array of pairs, each pair indicating note and duration.  
-/// Returns true if the music was interrupted, or false if it played through to the end.
+/// Converts a volume from 0 to 1 into a PWM duty cycle. A square wave is loudest at 50% duty,
+/// so volumes map onto 0-0.5. Duty cycle also affects the timbre of the note; I found a volume
+/// of 0.5 (0.25 duty) to be less harsh than full volume.
+pub fn volume2duty(volume: f64) -> f64 {
+    volume.clamp(0.0, 1.0) * 0.5
+}
+
+/// One stretch of constant output from the buzzer.
+#[derive(Clone, Copy, Debug)]
+pub struct Segment {
+    /// MIDI index of the note being played, or 0 for silence.
+    pub midi: u32,
+    /// Duty cycle to play the note at.
+    pub duty: f64,
+    /// How long the segment lasts.
+    pub dur: Duration,
+    /// Measure the segment starts in.
+    pub measure: u32,
+}
+
+/// Splits one voice of a song into the segments a buzzer actually plays, applying articulations, volumes
+/// and tempo changes.
+/// 
+/// Segments before `from_measure` are skipped, so playback can start partway through a song.
+pub fn note_segments(song: &Song, data: &[Note], from_measure: u32) -> Vec<Segment> {
+    // Volume added to accented notes.
+    const ACCENT_VOLUME: f64 = 0.25;
+    // Length of the break before a repeated note, in beats.
+    // I chose the arbitrary duration of 1/8th of a beat, or a 32nd note. This is short enough to not be too obvious but not long enough for it to be obvious either.
+    const REPEAT_GAP: f64 = 0.125;
+
+    let timeline = Timeline::new(song);
+    let seek_beat = timeline.measure_start(from_measure);
+
+    let mut segments = Vec::with_capacity(data.len());
+    // Adds a segment between two beats, converting them to real time. Anything before the seek point is cut off.
+    let mut push = |midi: u32, duty: f64, start: f64, end: f64| {
+        if end <= seek_beat {
+            return;
+        }
+        let start = start.max(seek_beat);
+        let secs = timeline.seconds_at(end) - timeline.seconds_at(start);
+        // Duration panics on negative or non-finite seconds; a segment like that has no length anyway
+        if !secs.is_finite() || secs <= 0.0 {
+            return;
+        }
+        segments.push(Segment {
+            midi,
+            duty,
+            dur: Duration::from_secs_f64(secs),
+            measure: timeline.measure_at(start),
+        });
+    };
+
+    // Position of the current note, in beats from the start of the song.
+    let mut beat = 0.0;
+    // Length of any notes tied into the current one, in beats.
+    let mut tied_beats = 0.0;
+    // The note that started a tie; its volume and accent are used for the whole tied note.
+    let mut tie_start: Option<Note> = None;
+
+    for i in 0..data.len() {
+        let cur = data[i];
+        let next_midi = data.get(i + 1).map(|next| next.midi);
+        let repeated = cur.midi != 0 && next_midi == Some(cur.midi);
+
+        // rests are just silence.
+        if cur.midi == 0 {
+            push(0, 0.0, beat, beat + cur.beats);
+            beat += cur.beats;
+            continue;
+        }
+
+        let articulation = cur.articulation.unwrap_or(song.articulation);
+        // a tie into the same note carries its length over to the next note.
+        if articulation == Articulation::Tie && repeated {
+            tie_start.get_or_insert(cur);
+            tied_beats += cur.beats;
+            continue;
+        }
+
+        let first = tie_start.take().unwrap_or(cur);
+        let beats = tied_beats + cur.beats;
+        tied_beats = 0.0;
+
+        let mut volume = first.volume.unwrap_or(song.volume);
+        if first.accent {
+            volume += ACCENT_VOLUME;
+        }
+        let duty = volume2duty(volume);
+
+        // work out how much of the note is actually held
+        let held = match articulation {
+            // very short notes get a shorter gap, so there's still something left to hold
+            Articulation::Normal if repeated => (beats - REPEAT_GAP.min(beats * 0.5)).max(0.0),
+            Articulation::Normal => beats,
+            Articulation::Staccato => beats * 0.5,
+            Articulation::Legato | Articulation::Tie => beats,
+        };
+        push(cur.midi, duty, beat, beat + held);
+        if held < beats {
+            push(0, 0.0, beat + held, beat + beats);
+        }
+        beat += beats;
+    }
+    segments
+}
+
+/// What every buzzer is playing for a stretch of time.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// MIDI index and duty cycle for each buzzer. A MIDI index of 0 means that buzzer is silent.
+    pub outputs: Vec<(u32, f64)>,
+    /// How long the frame lasts.
+    pub dur: Duration,
+    /// Measure the frame starts in.
+    pub measure: u32,
+}
+
+/// Works out what each of a number of buzzers should play for a song. Voice `n` is played on buzzer `n`,
+/// except for the last buzzer, which plays all remaining voices by rapidly cycling between whichever of them
+/// are sounding (an arpeggio). With a single buzzer, this means all voices are arpeggiated.
+/// 
+/// This is shared between live playback and offline rendering, so both produce exactly the same output.
+pub fn buzzer_frames(song: &Song, from_measure: u32, buzzers: usize) -> Vec<Frame> {
+    // Time spent on each note of an arpeggio. This is fast enough to blur into a chord, 
+    // but slow enough that the PWM has time to actually change frequency.
+    const ARPEGGIO_STEP: Duration = Duration::from_millis(25);
+
+    let buzzers = buzzers.max(1);
+    let voices: Vec<Vec<Segment>> = song
+        .voices
+        .iter()
+        .map(|data| note_segments(song, data, from_measure))
+        .collect();
+
+    // Position in each voice, and time left in the current segment of that voice.
+    let mut pos = vec![0usize; voices.len()];
+    let mut remaining: Vec<Duration> = voices
+        .iter()
+        .map(|segments| segments.first().map_or(Duration::ZERO, |seg| seg.dur))
+        .collect();
+
+    let mut frames = Vec::new();
+    loop {
+        // skip over any segments that have finished (including empty ones)
+        for (v, segments) in voices.iter().enumerate() {
+            while pos[v] < segments.len() && remaining[v].is_zero() {
+                pos[v] += 1;
+                remaining[v] = segments.get(pos[v]).map_or(Duration::ZERO, |seg| seg.dur);
+            }
+        }
+        let active: Vec<&Segment> = voices
+            .iter()
+            .zip(&pos)
+            .filter_map(|(segments, &i)| segments.get(i))
+            .collect();
+        if active.is_empty() {
+            break;
+        }
+
+        // the chord lasts until the next segment in any voice ends
+        let dur = (0..voices.len())
+            .filter(|&v| pos[v] < voices[v].len())
+            .map(|v| remaining[v])
+            .min()
+            .unwrap();
+        let measure = active[0].measure;
+        let chord: Vec<(u32, f64)> = (0..voices.len())
+            .map(|v| voices[v].get(pos[v]).map_or((0, 0.0), |seg| (seg.midi, seg.duty)))
+            .collect();
+        for v in 0..voices.len() {
+            if pos[v] < voices[v].len() {
+                remaining[v] -= dur;
+            }
+        }
+
+        // the last buzzer shares every voice that doesn't have a buzzer of its own
+        let mut outputs: Vec<(u32, f64)> = (0..buzzers - 1)
+            .map(|b| chord.get(b).copied().unwrap_or((0, 0.0)))
+            .collect();
+        let shared: Vec<(u32, f64)> = chord
+            .iter()
+            .skip(buzzers - 1)
+            .copied()
+            .filter(|&(midi, _)| midi != 0)
+            .collect();
+
+        if shared.len() <= 1 {
+            outputs.push(shared.first().copied().unwrap_or((0, 0.0)));
+            frames.push(Frame { outputs, dur, measure });
+            continue;
+        }
+        // arpeggiate the shared voices
+        let mut left = dur;
+        let mut step = 0;
+        while !left.is_zero() {
+            let step_dur = left.min(ARPEGGIO_STEP);
+            let mut step_outputs = outputs.clone();
+            step_outputs.push(shared[step % shared.len()]);
+            frames.push(Frame { outputs: step_outputs, dur: step_dur, measure });
+            left -= step_dur;
+            step += 1;
+        }
+    }
+    frames
+}
+
+/// Plays a song on one or more buzzers. Returns true if the music was interrupted, or false if it played through to the end.
+/// If a buzzer returns an error, playback stops and the error is returned.
 /// 
 /// ## Parameters
 /// 
-/// - `buzzer``: the buzzer to play music on
-/// - `bpm`: The tempo of the music, in BPM. 120 BPM corresponds to a beat every 0.5 seconds.
-/// - `data`: A data array containing the actual music. See section "Data Format" for details.
+/// - `buzzers`: the buzzers to play music on. See [`buzzer_frames`] for how voices are assigned to buzzers.
+/// - `song`: The song to play. See section "Data Format" for details.
+/// - `from_measure`: The measure to start playing from. Measure 0 is the pickup, if the song has one.
 /// - `cancel`: A function which can determine if an interrupt happens; allowing the music to be stopped whenever.
+/// - `on_measure`: Called with the measure number whenever a new measure starts.
 /// 
 /// ## Data Format
-/// Data is stored as a list of pairs. The first element of each pair is the MIDI index of the note being played,
-/// and the second element is its length, in beats. As an example: `(60, 1.0)` is middle C played for the length
-/// of a quarter note.
+/// Songs store their starting tempo in BPM (120 BPM corresponds to a beat every 0.5 seconds), and their data as one or 
+/// more voices, each a list of [`Note`]s with a MIDI index and a length in beats. As an example: `note("C4", 1.0)` is 
+/// middle C played for the length of a quarter note. Notes can additionally be given an articulation, accent or volume; 
+/// any note that doesn't specify one uses the song's default.
+/// 
+/// Tempo changes and time signatures are listed separately, and refer to positions by measure. Measures are counted
+/// from the end of the pickup.
 /// 
 #[inline(always)]
-pub fn buzzer_play_array(buzzer: &mut PwmToneBuzzer, bpm: f64, data: &[(u32, f64)], cancel: &impl Fn() -> bool) -> bool {
-    // Compute the length of a beat in nanoseconds based on BPM.
-    let beat_ns: f64 = 60_000_000_000f64 / bpm;
-
-    // Macro for later: wait, if interrupted return true.
-    macro_rules! delay {
-        ($dur:expr) => {
-            if wait_interruptible($dur, cancel) {
-                return true;
+pub fn buzzer_play_song(
+    buzzers: &mut [PwmToneBuzzer],
+    song: &Song,
+    from_measure: u32,
+    cancel: &impl Fn() -> bool,
+    on_measure: &mut impl FnMut(u32),
+) -> error::Result<bool> {
+    let mut cur_measure = None;
+    // what each buzzer is currently playing, so buzzers are only updated when they change
+    let mut cur_outputs: Vec<Option<(u32, f64)>> = vec![None; buzzers.len()];
+    for frame in buzzer_frames(song, from_measure, buzzers.len()) {
+        if cur_measure != Some(frame.measure) {
+            cur_measure = Some(frame.measure);
+            on_measure(frame.measure);
+        }
+        for (b, buzzer) in buzzers.iter_mut().enumerate() {
+            if cur_outputs[b] != Some(frame.outputs[b]) {
+                cur_outputs[b] = Some(frame.outputs[b]);
+                buzzer.play_midi(frame.outputs[b].0, frame.outputs[b].1)?;
             }
-        };
+        }
+        // wait, if interrupted return true.
+        if wait_interruptible(frame.dur, cancel) {
+            return Ok(true);
+        }
     }
+    Ok(false)
+}
 
-    for i in 0..data.len() {
-        let (note, len) = data[i];
-        
-        // if playing a repeated note, stop a bit at the end to give pause before the next beat.
-        // I chose the arbitrary duration of 1/8th of a beat, or a 32nd note. This is short enough to not be too obvious but not long enough for it to be obvious either.
-        if i < (data.len() - 1) && data[i + 1].0 == note {
-            buzzer.play_midi(note);
-            delay!(Duration::from_nanos((beat_ns * (len - 0.125)) as u64));
-            buzzer.stop();
-            delay!(Duration::from_nanos((beat_ns * 0.125) as u64));
-        }
-        else {
-            // otherwise just play the note for its full duration.
-            buzzer.play_midi(note);
-            delay!(Duration::from_nanos((beat_ns * len) as u64));
-        }
-    }
-    false
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A song at 60 BPM in 4/4, so every beat is a second long.
+    fn song(voices: Vec<Vec<Note>>) -> Song {
+        Song {
+            name: Cow::Borrowed("test"),
+            bpm: 60.0,
+            tempo_changes: Cow::Owned(Vec::new()),
+            time_signatures: Cow::Owned(Vec::new()),
+            pickup: 0.0,
+            articulation: Articulation::Normal,
+            volume: 0.5,
+            voices: Cow::Owned(voices.into_iter().map(Cow::Owned).collect()),
+        }
+    }
+
+    /// Turns segments into (MIDI, duty, seconds, measure), which are easier to compare.
+    fn summary(segments: &[Segment]) -> Vec<(u32, f64, f64, u32)> {
+        segments.iter().map(|seg| (seg.midi, seg.duty, seg.dur.as_secs_f64(), seg.measure)).collect()
+    }
+
+    fn segments(notes: Vec<Note>) -> Vec<(u32, f64, f64, u32)> {
+        let song = song(vec![notes.clone()]);
+        summary(&note_segments(&song, &notes, 0))
+    }
+
+    #[test]
+    fn holds_normal_notes() {
+        let c4 = note2midi("C4");
+        let d4 = note2midi("D4");
+        assert_eq!(segments(vec![note("C4", 1.0), note("D4", 2.0)]), [(c4, 0.25, 1.0, 1), (d4, 0.25, 2.0, 1)]);
+        // a repeated note gets a short break before it
+        assert_eq!(
+            segments(vec![note("C4", 1.0), note("C4", 1.0)]),
+            [(c4, 0.25, 0.875, 1), (0, 0.0, 0.125, 1), (c4, 0.25, 1.0, 1)]
+        );
+    }
+
+    #[test]
+    fn shortens_staccato_notes() {
+        let c4 = note2midi("C4");
+        assert_eq!(
+            segments(vec![note("C4", 2.0).staccato(), rest(1.0)]),
+            [(c4, 0.25, 1.0, 1), (0, 0.0, 1.0, 1), (0, 0.0, 1.0, 1)]
+        );
+    }
+
+    #[test]
+    fn slurs_legato_notes() {
+        let c4 = note2midi("C4");
+        assert_eq!(segments(vec![note("C4", 1.0).legato(), note("C4", 1.0)]), [(c4, 0.25, 1.0, 1), (c4, 0.25, 1.0, 1)]);
+    }
+
+    #[test]
+    fn plays_accents_louder() {
+        let c4 = note2midi("C4");
+        let e4 = note2midi("E4");
+        assert_eq!(
+            segments(vec![note("C4", 1.0).accent(), note("E4", 1.0).volume(0.2).accent()]),
+            [(c4, 0.375, 1.0, 1), (e4, volume2duty(0.45), 1.0, 1)]
+        );
+        // accents can't go past full volume
+        assert_eq!(segments(vec![note("C4", 1.0).volume(0.9).accent()]), [(c4, 0.5, 1.0, 1)]);
+    }
+
+    #[test]
+    fn ties_notes_across_a_bar() {
+        let c4 = note2midi("C4");
+        let e4 = note2midi("E4");
+        // the tie starts in measure 1 and runs 2 beats into measure 2; the first note's accent carries over
+        let notes = vec![rest(2.0), note("C4", 2.0).tie().accent(), note("C4", 2.0), note("E4", 2.0)];
+        assert_eq!(segments(notes), [(0, 0.0, 2.0, 1), (c4, 0.375, 4.0, 1), (e4, 0.25, 2.0, 2)]);
+        // a tie into a different note is just a normal note
+        let notes = vec![note("C4", 1.0).tie(), note("E4", 1.0)];
+        assert_eq!(segments(notes), [(c4, 0.25, 1.0, 1), (e4, 0.25, 1.0, 1)]);
+    }
+
+    #[test]
+    fn seeks_to_a_measure() {
+        let c4 = note2midi("C4");
+        let notes = vec![note("C4", 6.0), rest(2.0)];
+        let song = song(vec![notes.clone()]);
+        assert_eq!(summary(&note_segments(&song, &notes, 2)), [(c4, 0.25, 2.0, 2), (0, 0.0, 2.0, 2)]);
+    }
+
+    /// Turns frames into (outputs, milliseconds), which are easier to compare.
+    fn frames(song: &Song, buzzers: usize) -> Vec<(Vec<u32>, u128)> {
+        buzzer_frames(song, 0, buzzers)
+            .into_iter()
+            .map(|frame| (frame.outputs.iter().map(|&(midi, _)| midi).collect(), frame.dur.as_millis()))
+            .collect()
+    }
+
+    #[test]
+    fn plays_single_notes_as_is() {
+        let c4 = note2midi("C4");
+        let song = song(vec![vec![note("C4", 1.0), rest(0.5)]]);
+        assert_eq!(frames(&song, 1), [(vec![c4], 1000), (vec![0], 500)]);
+        // buzzers without a voice stay silent
+        assert_eq!(frames(&song, 2), [(vec![c4, 0], 1000), (vec![0, 0], 500)]);
+    }
+
+    #[test]
+    fn arpeggiates_chords_on_one_buzzer() {
+        let [c4, e4, g4] = [note2midi("C4"), note2midi("E4"), note2midi("G4")];
+        let song = song(vec![vec![note("C4", 0.1)], vec![note("E4", 0.1)], vec![note("G4", 0.1)]]);
+        // 100ms of chord is 4 steps of 25ms, cycling through the voices in order
+        let expected = [(vec![c4], 25), (vec![e4], 25), (vec![g4], 25), (vec![c4], 25)];
+        assert_eq!(frames(&song, 1), expected);
+
+        // with two buzzers, the melody keeps its own and the other two share the last one
+        let expected = [(vec![c4, e4], 25), (vec![c4, g4], 25), (vec![c4, e4], 25), (vec![c4, g4], 25)];
+        assert_eq!(frames(&song, 2), expected);
+    }
+
+    #[test]
+    fn ends_arpeggios_with_the_chord() {
+        let [c4, e4] = [note2midi("C4"), note2midi("E4")];
+        // the chord lasts 60ms, so the last step is cut short; then the melody carries on alone
+        let song = song(vec![vec![note("C4", 0.1)], vec![note("E4", 0.06)]]);
+        let expected = [(vec![c4], 25), (vec![e4], 25), (vec![c4], 10), (vec![c4], 40)];
+        assert_eq!(frames(&song, 1), expected);
+    }
+
+    #[test]
+    fn transposes_notes() {
+        let transform = SongTransform { transpose: -3, ..Default::default() };
+        assert_eq!(transform.apply_midi(60), 57);
+        // rests stay rests, however far things are transposed
+        assert_eq!(transform.apply_midi(0), 0);
+        assert_eq!(SongTransform { transpose: 200, ..Default::default() }.apply_midi(0), 0);
+    }
+
+    #[test]
+    fn folds_notes_into_range() {
+        let transform = SongTransform { lowest: Some(60), highest: Some(72), ..Default::default() };
+        // notes in range, including both edges, stay where they are
+        for midi in [60, 66, 72] {
+            assert_eq!(transform.apply_midi(midi), midi);
+        }
+        // everything else moves by whole octaves until it fits
+        assert_eq!(transform.apply_midi(59), 71);
+        assert_eq!(transform.apply_midi(48), 60);
+        assert_eq!(transform.apply_midi(35), 71);
+        assert_eq!(transform.apply_midi(73), 61);
+        assert_eq!(transform.apply_midi(84), 72);
+        assert_eq!(transform.apply_midi(97), 61);
+        assert_eq!(transform.apply_midi(0), 0);
+    }
+
+    #[test]
+    fn folds_notes_transposed_out_of_range() {
+        // way past the top of MIDI, then folded back down
+        let transform = SongTransform { transpose: 40, highest: Some(100), ..Default::default() };
+        assert_eq!(transform.apply_midi(100), 91);
+        // way past the bottom, then folded back up
+        let transform = SongTransform { transpose: -100, lowest: Some(24), ..Default::default() };
+        assert_eq!(transform.apply_midi(60), 25);
+        // without a range, notes are still kept to valid MIDI notes and never turn into rests
+        let transform = SongTransform { transpose: -100, ..Default::default() };
+        assert_eq!(transform.apply_midi(60), 1);
+        let transform = SongTransform { transpose: 100, ..Default::default() };
+        assert_eq!(transform.apply_midi(60), 127);
+    }
+
+    #[test]
+    fn transforms_whole_songs() {
+        let song = song(vec![vec![note("C4", 1.0), rest(1.0)]]);
+        let transform = SongTransform { transpose: 12, tempo_scale: 2.0, ..Default::default() };
+        let transformed = song.transformed(&transform);
+        assert_eq!(transformed.bpm, 120.0);
+        assert_eq!(transformed.voices[0][0].midi, note2midi("C5"));
+        assert_eq!(transformed.voices[0][1].midi, 0);
+    }
+}
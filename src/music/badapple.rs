@@ -8,131 +8,144 @@ Date: Jun. 17, 2024
 //! Tempo and music data for _Bad Apple!!_ ft. Nomico.
 
 
-use super::note2midi;
+use std::borrow::Cow;
+
+use super::{note, rest, timing::TimeSignature, Articulation, Note, Song};
 
 pub const BPM: f64 = 138.0;
-pub const DATA: [(u32, f64); 107] = [
+pub const SONG: Song = Song {
+    name: Cow::Borrowed("badapple"),
+    bpm: BPM,
+    tempo_changes: Cow::Borrowed(&[]),
+    time_signatures: Cow::Borrowed(&[TimeSignature { measure: 1, count: 4, unit: 4 }]),
+    pickup: 0.0,
+    articulation: Articulation::Normal,
+    volume: 0.5,
+    voices: Cow::Borrowed(&[Cow::Borrowed(&DATA)]),
+};
+
+pub const DATA: [Note; 107] = [
     // m. 1
-    (note2midi("Eb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Db6"), 0.5),
+    note("Eb5", 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Eb6", 0.5),
+    note("Db6", 0.5),
     // m. 2
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("F5"), 0.5),
+    note("Bb5", 1.0),
+    note("Eb5", 1.0),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
+    note("F5", 0.5),
     // m. 3
-    (note2midi("Eb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
+    note("Eb5", 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
     // m. 4
-    (note2midi("F5"), 0.5),
-    (note2midi("Eb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Eb5"), 0.5),
-    (note2midi("D5"), 0.5),
-    (note2midi("F5"), 0.5),
+    note("F5", 0.5),
+    note("Eb5", 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("F5", 0.5),
+    note("Eb5", 0.5),
+    note("D5", 0.5),
+    note("F5", 0.5),
     // m. 5
-    (note2midi("Eb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Db6"), 0.5),
+    note("Eb5", 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Eb6", 0.5),
+    note("Db6", 0.5),
     // m. 6
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("F5"), 0.5),
+    note("Bb5", 1.0),
+    note("Eb5", 1.0),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
+    note("F5", 0.5),
     // m. 7
-    (note2midi("Eb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
+    note("Eb5", 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
     // m. 8
-    (note2midi("F5"), 1.0),
-    (note2midi("Gb5"), 1.0),
-    (note2midi("Ab5"), 1.0),
-    (note2midi("Bb5"), 1.0),
+    note("F5", 1.0),
+    note("Gb5", 1.0),
+    note("Ab5", 1.0),
+    note("Bb5", 1.0),
     // m. 9
-    (note2midi("Db6"), 0.5),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 0.5),
+    note("Db6", 0.5),
+    note("Eb6", 0.5),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Bb5", 0.5),
     // m. 10
-    (note2midi("Db6"), 0.5),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 0.5),
+    note("Db6", 0.5),
+    note("Eb6", 0.5),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Bb5", 0.5),
     // m. 11
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Db5"), 0.5),
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Db5"), 0.5),
-    (note2midi("Eb5"), 0.5),
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
+    note("F5", 0.5),
+    note("Db5", 0.5),
+    note("Eb5", 1.0),
+    note("Db5", 0.5),
+    note("Eb5", 0.5),
     // m. 12
-    (note2midi("F5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Db6"), 0.5),
+    note("F5", 0.5),
+    note("Gb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 0.5),
+    note("Eb5", 1.0),
+    note("Bb5", 0.5),
+    note("Db6", 0.5),
     // m. 13
-    (note2midi("Db6"), 0.5),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 0.5),
+    note("Db6", 0.5),
+    note("Eb6", 0.5),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Bb5", 0.5),
     // m. 14
-    (note2midi("Db6"), 0.5),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Bb5"), 0.5),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("F6"), 0.5),
+    note("Db6", 0.5),
+    note("Eb6", 0.5),
+    note("Bb5", 0.5),
+    note("Ab5", 0.5),
+    note("Bb5", 1.0),
+    note("Eb6", 0.5),
+    note("F6", 0.5),
     // m. 15
-    (note2midi("Gb6"), 0.5),
-    (note2midi("F6"), 0.5),
-    (note2midi("Eb6"), 0.5),
-    (note2midi("Db6"), 0.5),
-    (note2midi("Bb5"), 1.0),
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Bb5"), 0.5),
+    note("Gb6", 0.5),
+    note("F6", 0.5),
+    note("Eb6", 0.5),
+    note("Db6", 0.5),
+    note("Bb5", 1.0),
+    note("Ab5", 0.5),
+    note("Bb5", 0.5),
     // m. 16
-    (note2midi("Ab5"), 0.5),
-    (note2midi("Gb5"), 0.5),
-    (note2midi("F5"), 0.5),
-    (note2midi("Db5"), 0.5),
-    (note2midi("Eb5"), 1.0),
-    (0, 1.0),
-];
\ No newline at end of file
+    note("Ab5", 0.5),
+    note("Gb5", 0.5),
+    note("F5", 0.5),
+    note("Db5", 0.5),
+    note("Eb5", 1.0),
+    rest(1.0),
+];
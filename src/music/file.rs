@@ -0,0 +1,290 @@
+/*
+music/file.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Reading songs from text files, so new songs can be added without recompiling.
+//!
+//! ## Format
+//! Each line of a song file is either a setting, a note, or blank. Anything after `//` is a comment.
+//! ```text
+//! // settings; all of these are optional
+//! name rick
+//! bpm 113
+//! pickup 1
+//! articulation normal         // normal, staccato, legato or tie
+//! volume 0.5
+//! time 1 4/4                  // time <measure> <count>/<unit>
+//! tempo 8 0 95 3              // tempo <measure> <beat> <bpm> [ramp]
+//!
+//! // notes: <name> <beats> [articulation] [accent] [volume=<volume>]
+//! Ab4 0.25 legato volume=0.4
+//! F5 0.75 accent
+//! R 1                         // rests are written as R
+//!
+//! // `voice` starts the next voice
+//! voice
+//! Db3 2
+//! ```
+
+use std::{borrow::Cow, error::Error, fmt, fs, io, path::Path};
+
+use super::{
+    timing::{TempoChange, TimeSignature},
+    try_note2midi, Articulation, Note, Song,
+};
+
+/// Error while reading a song file.
+#[derive(Debug)]
+pub enum SongFileError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file has a mistake on a line (counting from 1).
+    Parse { line: usize, msg: String },
+    /// The transform to apply to the song doesn't make sense.
+    Transform(&'static str),
+}
+
+impl fmt::Display for SongFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SongFileError::Io(err) => write!(f, "could not read song file: {}", err),
+            SongFileError::Parse { line, msg } => write!(f, "song file line {}: {}", line, msg),
+            SongFileError::Transform(msg) => write!(f, "invalid song transform: {}", msg),
+        }
+    }
+}
+
+impl Error for SongFileError {}
+
+impl From<io::Error> for SongFileError {
+    fn from(err: io::Error) -> Self {
+        SongFileError::Io(err)
+    }
+}
+
+/// Reads a song from a file. If the file doesn't set a name, the file name is used.
+pub fn read_song(path: impl AsRef<Path>) -> Result<Song, SongFileError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let default_name = path
+        .file_stem()
+        .map_or("song".to_owned(), |stem| stem.to_string_lossy().into_owned());
+    parse_song(&text, default_name)
+}
+
+/// Parses an articulation by name.
+fn parse_articulation(word: &str) -> Option<Articulation> {
+    match word {
+        "normal" => Some(Articulation::Normal),
+        "staccato" => Some(Articulation::Staccato),
+        "legato" => Some(Articulation::Legato),
+        "tie" => Some(Articulation::Tie),
+        _ => None,
+    }
+}
+
+/// Returns true for a tempo: a positive number.
+fn is_tempo(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// Returns true for a length or position in beats: a number that isn't negative.
+fn is_beats(value: f64) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+/// Returns true for a volume, which goes from 0 to 1.
+fn is_volume(value: f64) -> bool {
+    (0.0..=1.0).contains(&value)
+}
+
+/// Returns true for either number in a time signature, which can't be 0.
+fn is_time_signature(value: u32) -> bool {
+    value > 0
+}
+
+/// Parses the text of a song file. Numbers are checked, so anything that could stop a song from being played
+/// (like a tempo of 0 or a 0/4 time signature) is an error.
+pub fn parse_song(text: &str, default_name: String) -> Result<Song, SongFileError> {
+    let mut song = Song {
+        name: Cow::Owned(default_name),
+        bpm: 120.0,
+        tempo_changes: Cow::Owned(Vec::new()),
+        time_signatures: Cow::Owned(Vec::new()),
+        pickup: 0.0,
+        articulation: Articulation::Normal,
+        volume: 0.5,
+        voices: Cow::Owned(Vec::new()),
+    };
+    let mut tempo_changes: Vec<TempoChange> = Vec::new();
+    let mut time_signatures: Vec<TimeSignature> = Vec::new();
+    let mut voices: Vec<Vec<Note>> = vec![Vec::new()];
+
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: &str| SongFileError::Parse { line: i + 1, msg: msg.to_owned() };
+        // parses a number, or gives an error saying what it was supposed to be.
+        // With a check, numbers it doesn't accept are an error as well.
+        macro_rules! num {
+            ($word:expr, $what:literal) => {
+                $word
+                    .ok_or_else(|| err(concat!("missing ", $what)))?
+                    .parse()
+                    .map_err(|_| err(concat!("invalid ", $what)))?
+            };
+            ($word:expr, $what:literal, $check:expr) => {{
+                let value = num!($word, $what);
+                if !$check(value) {
+                    return Err(err(concat!($what, " is out of range")));
+                }
+                value
+            }};
+        }
+
+        // strip comments
+        let line = line.split("//").next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+
+        match first {
+            "name" => {
+                let name: Vec<&str> = words.collect();
+                song.name = Cow::Owned(name.join(" "));
+                continue;
+            }
+            "bpm" => song.bpm = num!(words.next(), "tempo", is_tempo),
+            "pickup" => song.pickup = num!(words.next(), "pickup length", is_beats),
+            "volume" => song.volume = num!(words.next(), "volume", is_volume),
+            "articulation" => {
+                let word = words.next().ok_or_else(|| err("missing articulation"))?;
+                song.articulation = parse_articulation(word).ok_or_else(|| err("unknown articulation"))?;
+            }
+            "time" => {
+                let measure = num!(words.next(), "measure");
+                let sig = words.next().ok_or_else(|| err("missing time signature"))?;
+                let (count, unit) = sig.split_once('/').ok_or_else(|| err("time signatures must look like 4/4"))?;
+                time_signatures.push(TimeSignature {
+                    measure,
+                    count: num!(Some(count), "time signature", is_time_signature),
+                    unit: num!(Some(unit), "time signature", is_time_signature),
+                });
+            }
+            "tempo" => {
+                let measure = num!(words.next(), "measure");
+                let beat = num!(words.next(), "beat", is_beats);
+                let bpm = num!(words.next(), "tempo", is_tempo);
+                let ramp = match words.next() {
+                    Some(word) => num!(Some(word), "ramp length", is_beats),
+                    None => 0.0,
+                };
+                tempo_changes.push(TempoChange { measure, beat, bpm, ramp });
+            }
+            "voice" => {
+                if !voices.last().unwrap().is_empty() {
+                    voices.push(Vec::new());
+                }
+            }
+            // anything else should be a note
+            name => {
+                let midi = if name == "R" {
+                    0
+                } else {
+                    try_note2midi(name).map_err(err)?
+                };
+                let mut note = Note::new(midi, num!(words.next(), "note length", is_beats));
+                for word in words.by_ref() {
+                    if let Some(articulation) = parse_articulation(word) {
+                        note.articulation = Some(articulation);
+                    } else if word == "accent" {
+                        note.accent = true;
+                    } else if let Some(volume) = word.strip_prefix("volume=") {
+                        note.volume = Some(num!(Some(volume), "volume", is_volume));
+                    } else {
+                        return Err(err("unknown note option"));
+                    }
+                }
+                voices.last_mut().unwrap().push(note);
+            }
+        }
+        // settings only take a fixed number of words
+        if words.next().is_some() {
+            return Err(err("too many words on this line"));
+        }
+    }
+
+    voices.retain(|voice| !voice.is_empty());
+    song.tempo_changes = Cow::Owned(tempo_changes);
+    song.time_signatures = Cow::Owned(time_signatures);
+    song.voices = Cow::Owned(voices.into_iter().map(Cow::Owned).collect());
+    Ok(song)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the line of the error from parsing a song, if there is one.
+    fn error_line(text: &str) -> Option<usize> {
+        match parse_song(text, "test".to_owned()) {
+            Ok(_) => None,
+            Err(SongFileError::Parse { line, .. }) => Some(line),
+            Err(err) => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn parses_song() {
+        let text = concat!(
+            "name test song\nbpm 90\ntime 1 3/4\ntempo 2 0 60 2\n",
+            "C4 1 staccato accent\nR 0.5\nvoice\nG9 2 volume=0.25\n",
+        );
+        let song = parse_song(text, "file".to_owned()).unwrap();
+        assert_eq!(song.name, "test song");
+        assert_eq!(song.voices.len(), 2);
+        assert_eq!(song.voices[0][0].midi, 60);
+        assert_eq!(song.voices[0][0].articulation, Some(Articulation::Staccato));
+        assert_eq!(song.voices[1][0].midi, 127);
+        assert_eq!(song.voices[1][0].volume, Some(0.25));
+    }
+
+    #[test]
+    fn rejects_bad_numbers() {
+        for text in [
+            "bpm 0",
+            "bpm -1",
+            "bpm inf",
+            "bpm NaN",
+            "time 1 0/4",
+            "time 1 4/0",
+            "tempo 1 0 0",
+            "tempo 1 -1 120",
+            "tempo 1 0 120 NaN",
+            "pickup -1",
+            "volume 2",
+            "C4 NaN",
+            "C4 -1",
+            "C4 inf",
+            "C4 1 volume=inf",
+        ] {
+            assert_eq!(error_line(&format!("C4 1\n{}\n", text)), Some(2), "{:?} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn rejects_notes_out_of_range() {
+        assert_eq!(error_line("G#9 1"), Some(1));
+        assert_eq!(error_line("C10 1"), Some(1));
+        assert_eq!(error_line("C99999999999 1"), Some(1));
+        assert_eq!(error_line("C9 1"), None);
+    }
+
+    #[test]
+    fn rejects_bad_transform() {
+        let transform = crate::music::SongTransform { tempo_scale: 0.0, ..Default::default() };
+        assert!(matches!(crate::music::load_song("rick", &transform), Err(SongFileError::Transform(_))));
+    }
+}
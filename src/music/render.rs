@@ -0,0 +1,140 @@
+/*
+music/render.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Offline rendering of buzzer music to WAV files, so songs can be heard without a Pi.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::{buzzer_frames, midi2freq, Song};
+
+/// Sample rate used when rendering, in Hz.
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Peak amplitude of each buzzer's square wave. Kept below full scale so the output isn't painfully loud,
+/// and so that two buzzers playing at once don't clip.
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+/// Synthesizes a song as 16-bit mono samples at [`SAMPLE_RATE`], as it would sound on a number of buzzers.
+/// Each buzzer outputs a square wave at the same duty cycles the real buzzers use, and follows the exact same timing
+/// as [`super::buzzer_play_song`]. Rendering starts from `from_measure`.
+pub fn render_samples(song: &Song, from_measure: u32, buzzers: usize) -> Vec<i16> {
+    let mut samples = Vec::new();
+    // Phase is kept between notes, like the PWM output which keeps running when the frequency changes.
+    let mut phases = vec![0.0f64; buzzers.max(1)];
+    // Track time in samples from the start of the song, so rounding errors don't add up over a long song.
+    let mut elapsed = 0.0f64;
+
+    for frame in buzzer_frames(song, from_measure, buzzers) {
+        elapsed += frame.dur.as_secs_f64() * SAMPLE_RATE as f64;
+        let count = elapsed.round() as usize - samples.len();
+
+        let steps: Vec<f64> = frame
+            .outputs
+            .iter()
+            .map(|&(midi, _)| midi2freq(midi) / SAMPLE_RATE as f64)
+            .collect();
+        for _ in 0..count {
+            let mut sample = 0i16;
+            for (b, &(midi, duty)) in frame.outputs.iter().enumerate() {
+                // Notes of 0 are silence.
+                if midi == 0 {
+                    continue;
+                }
+                sample = sample.saturating_add(if phases[b] < duty { AMPLITUDE } else { -AMPLITUDE });
+                phases[b] = (phases[b] + steps[b]).fract();
+            }
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// Writes 16-bit mono samples at [`SAMPLE_RATE`] as a WAV file.
+pub fn write_wav(out: &mut impl Write, samples: &[i16]) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+    let data_len = (samples.len() * BLOCK_ALIGN as usize) as u32;
+
+    // RIFF header
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+    // format chunk (PCM)
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&CHANNELS.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * BLOCK_ALIGN as u32).to_le_bytes())?;
+    out.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+    out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+    // data chunk
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Renders a song to a WAV file at the given path, as it would sound on a number of buzzers,
+/// starting from `from_measure`.
+pub fn render_wav(path: impl AsRef<Path>, song: &Song, from_measure: u32, buzzers: usize) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_wav(&mut out, &render_samples(song, from_measure, buzzers))?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::file::parse_song;
+
+    #[test]
+    fn renders_square_wave_at_note_duty() {
+        // one beat at 120 BPM is half a second; the default volume of 0.5 is a 25% duty cycle
+        let song = parse_song("bpm 120\nA4 1\n", "test".to_owned()).unwrap();
+        let samples = render_samples(&song, 0, 1);
+        assert_eq!(samples.len(), SAMPLE_RATE as usize / 2);
+        assert!(samples.iter().all(|&sample| sample == AMPLITUDE || sample == -AMPLITUDE));
+        let high = samples.iter().filter(|&&sample| sample == AMPLITUDE).count();
+        let duty = high as f64 / samples.len() as f64;
+        assert!((duty - 0.25).abs() < 0.01, "duty was {}", duty);
+    }
+
+    #[test]
+    fn renders_rests_as_silence() {
+        let song = parse_song("bpm 60\nR 1\nC4 1\n", "test".to_owned()).unwrap();
+        let samples = render_samples(&song, 0, 2);
+        assert_eq!(samples.len(), 2 * SAMPLE_RATE as usize);
+        assert!(samples[..SAMPLE_RATE as usize].iter().all(|&sample| sample == 0));
+        assert!(samples[SAMPLE_RATE as usize..].iter().any(|&sample| sample != 0));
+    }
+
+    #[test]
+    fn renders_short_repeated_notes() {
+        // a repeated note shorter than the usual gap before a repeat
+        let song = parse_song("bpm 120\nR 1\nC4 0.0625\nC4 1\n", "test".to_owned()).unwrap();
+        let samples = render_samples(&song, 0, 1);
+        assert_eq!(samples.len(), (2.0625 * 0.5 * SAMPLE_RATE as f64).round() as usize);
+    }
+
+    #[test]
+    fn writes_wav_header() {
+        let mut out = Vec::new();
+        write_wav(&mut out, &[0, 1, -1]).unwrap();
+        assert_eq!(out.len(), 44 + 3 * 2);
+        assert_eq!(&out[..4], b"RIFF");
+        assert_eq!(&out[8..12], b"WAVE");
+        assert_eq!(u32::from_le_bytes(out[40..44].try_into().unwrap()), 3 * 2);
+    }
+}
@@ -7,77 +7,130 @@ Date: Jun. 17, 2024
 
 //! Tempo and music data for _Never Gonna Give You Up_ by Rick Astley.
 
-use super::note2midi;
+use std::borrow::Cow;
+
+use super::{
+    note, rest,
+    timing::{TempoChange, TimeSignature},
+    Articulation, Note, Song,
+};
 
 pub const BPM: f64 = 113.0;
-pub const DATA: [(u32, f64); 61] = [
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Db5"), 0.25),
-    (note2midi("Bb4"), 0.25),
+pub const SONG: Song = Song {
+    name: Cow::Borrowed("rick"),
+    bpm: BPM,
+    tempo_changes: Cow::Borrowed(&[
+        // slow down over the last measure
+        TempoChange { measure: 8, beat: 0.0, bpm: 95.0, ramp: 3.0 },
+    ]),
+    time_signatures: Cow::Borrowed(&[TimeSignature { measure: 1, count: 4, unit: 4 }]),
+    pickup: 1.0,
+    articulation: Articulation::Normal,
+    volume: 0.5,
+    voices: Cow::Borrowed(&[Cow::Borrowed(&DATA), Cow::Borrowed(&BASS)]),
+};
+
+pub const DATA: [Note; 62] = [
+    note("Ab4", 0.25).legato().volume(0.4),
+    note("Bb4", 0.25).legato().volume(0.4),
+    note("Db5", 0.25).legato().volume(0.4),
+    note("Bb4", 0.25).legato().volume(0.4),
+    // m. 1
+    note("F5", 0.75).accent(),
+    note("F5", 0.75),
+    note("Eb5", 0.5),
+    rest(1.0),
+    note("Ab4", 0.25),
+    note("Bb4", 0.25),
+    note("C5", 0.25),
+    note("Ab4", 0.25),
+    // m. 2
+    note("Eb5", 0.75),
+    note("Eb5", 0.75),
+    note("Db5", 0.75),
+    note("C5", 0.25),
+    note("Bb4", 0.5),
+    note("Ab4", 0.25),
+    note("Bb4", 0.25),
+    note("Db5", 0.25),
+    note("Bb4", 0.25),
+    // m. 3
+    note("Db5", 1.0),
+    note("Eb5", 0.5),
+    note("C5", 0.75),
+    note("Bb4", 0.25),
+    note("Ab4", 0.5).staccato(),
+    rest(0.5),
+    note("Ab4", 0.5),
+    // m. 4
+    note("Eb5", 1.0),
+    note("Db5", 2.0),
+    note("Ab4", 0.25),
+    note("Bb4", 0.25),
+    note("Db5", 0.25),
+    note("Bb4", 0.25),
+    // m. 5
+    note("F5", 0.75).accent(),
+    note("F5", 0.75),
+    note("Eb5", 0.5),
+    rest(1.0),
+    note("Ab4", 0.25),
+    note("Bb4", 0.25),
+    note("C5", 0.25),
+    note("Ab4", 0.25),
+    // m. 6
+    note("Ab5", 0.75),
+    note("C5", 0.75),
+    note("Db5", 0.75),
+    note("C5", 0.25),
+    note("Bb4", 0.5),
+    note("Ab4", 0.25),
+    note("Bb4", 0.25),
+    note("Db5", 0.25),
+    note("Bb4", 0.25),
+    // m. 7
+    note("Db5", 1.0),
+    note("Eb5", 0.5),
+    note("C5", 0.75),
+    note("Bb4", 0.25),
+    note("Ab4", 0.5).staccato(),
+    rest(0.5),
+    note("Ab4", 0.5),
+    // m. 8
+    note("Eb5", 1.0),
+    note("Db5", 1.0).tie(),
+    note("Db5", 0.75),
+    rest(0.25)
+];
+
+/// Bass line, played on the second buzzer.
+pub const BASS: [Note; 20] = [
+    rest(1.0),
     // m. 1
-    (note2midi("F5"), 0.75),
-    (note2midi("F5"), 0.75),
-    (note2midi("Eb5"), 0.5),
-    (0, 1.0),
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("C5"), 0.25),
-    (note2midi("Ab4"), 0.25),
+    note("Db3", 2.0),
+    note("Eb3", 2.0),
     // m. 2
-    (note2midi("Eb5"), 0.75),
-    (note2midi("Eb5"), 0.75),
-    (note2midi("Db5"), 0.75),
-    (note2midi("C5"), 0.25),
-    (note2midi("Bb4"), 0.5),
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Db5"), 0.25),
-    (note2midi("Bb4"), 0.25),
+    note("C3", 2.0),
+    note("F3", 2.0),
     // m. 3
-    (note2midi("Db5"), 1.0),
-    (note2midi("Eb5"), 0.5),
-    (note2midi("C5"), 0.75),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Ab4"), 0.5),
-    (0, 0.5),
-    (note2midi("Ab4"), 0.5),
+    note("Db3", 2.0),
+    note("Eb3", 2.0),
     // m. 4
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Db5"), 2.0),
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Db5"), 0.25),
-    (note2midi("Bb4"), 0.25),
+    note("Ab3", 1.0),
+    note("Ab3", 1.0),
+    note("Ab3", 1.0),
+    note("Ab3", 1.0),
     // m. 5
-    (note2midi("F5"), 0.75),
-    (note2midi("F5"), 0.75),
-    (note2midi("Eb5"), 0.5),
-    (0, 1.0),
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("C5"), 0.25),
-    (note2midi("Ab4"), 0.25),
+    note("Db3", 2.0),
+    note("Eb3", 2.0),
     // m. 6
-    (note2midi("Ab5"), 0.75),
-    (note2midi("C5"), 0.75),
-    (note2midi("Db5"), 0.75),
-    (note2midi("C5"), 0.25),
-    (note2midi("Bb4"), 0.5),
-    (note2midi("Ab4"), 0.25),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Db5"), 0.25),
-    (note2midi("Bb4"), 0.25),
+    note("C3", 2.0),
+    note("F3", 2.0),
     // m. 7
-    (note2midi("Db5"), 1.0),
-    (note2midi("Eb5"), 0.5),
-    (note2midi("C5"), 0.75),
-    (note2midi("Bb4"), 0.25),
-    (note2midi("Ab4"), 0.5),
-    (0, 0.5),
-    (note2midi("Ab4"), 0.5),
+    note("Db3", 2.0),
+    note("Eb3", 2.0),
     // m. 8
-    (note2midi("Eb5"), 1.0),
-    (note2midi("Db5"), 1.75),
-    (0, 0.25)
-];
\ No newline at end of file
+    note("Ab3", 1.0),
+    note("Ab3", 1.0),
+    note("Ab3", 1.0),
+];
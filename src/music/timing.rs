@@ -0,0 +1,274 @@
+/*
+music/timing.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Tempo maps, time signatures and measures. These convert positions in a song (in beats)
+//! to measure numbers and real time.
+
+use super::Song;
+
+/// A change in tempo, starting at a specific beat in a measure.
+#[derive(Clone, Copy, Debug)]
+pub struct TempoChange {
+    /// Measure the change starts in.
+    pub measure: u32,
+    /// Beat within the measure the change starts on, counting from 0.
+    pub beat: f64,
+    /// The new tempo, in BPM.
+    pub bpm: f64,
+    /// Number of beats to gradually reach the new tempo over (a ritardando or accelerando).
+    /// If this is 0, the tempo changes instantly.
+    pub ramp: f64,
+}
+
+/// A time signature, starting at a specific measure.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeSignature {
+    /// Measure the time signature starts at.
+    pub measure: u32,
+    /// Number of beats in a measure (top number).
+    pub count: u32,
+    /// Note value of each beat (bottom number). 4 is a quarter note, 8 is an eighth note, etc.
+    pub unit: u32,
+}
+
+impl TimeSignature {
+    /// Length of a measure in this time signature, in quarter-note beats (which is what note lengths use).
+    pub fn measure_beats(&self) -> f64 {
+        self.count as f64 * 4.0 / self.unit as f64
+    }
+}
+
+/// One stretch of the tempo map, where the tempo is either constant or changing linearly.
+struct TempoSpan {
+    start: f64,
+    end: f64,
+    start_bpm: f64,
+    end_bpm: f64,
+}
+
+impl TempoSpan {
+    /// Time taken from the start of this span to `beat`, in seconds.
+    fn seconds_to(&self, beat: f64) -> f64 {
+        let beats = beat.min(self.end) - self.start;
+        if beats <= 0.0 {
+            return 0.0;
+        }
+        if self.start_bpm == self.end_bpm || !self.end.is_finite() {
+            return beats * 60.0 / self.start_bpm;
+        }
+        // Tempo changes linearly in beats, so time is the integral of 60/bpm, which comes out as a logarithm.
+        let slope = (self.end_bpm - self.start_bpm) / (self.end - self.start);
+        let bpm = self.start_bpm + slope * beats;
+        60.0 / slope * (bpm / self.start_bpm).ln()
+    }
+}
+
+/// Slowest tempo the timeline uses, in BPM. Slower (or nonsensical) tempos are clamped to this, so every beat
+/// takes a finite amount of time.
+const MIN_BPM: f64 = 1.0;
+
+/// Clamps a tempo to something the timeline can work with.
+fn clamp_bpm(bpm: f64) -> f64 {
+    if bpm.is_nan() {
+        MIN_BPM
+    } else {
+        bpm.max(MIN_BPM)
+    }
+}
+
+/// Converts between beats (counted from the start of the song), measures, and real time.
+///
+/// Songs can come from anywhere, so the timeline doesn't trust them: time signatures with a 0 in them are
+/// ignored, tempos are clamped to at least [`MIN_BPM`], a pickup that isn't a positive number is dropped, and
+/// time signatures and tempo changes are sorted rather than assumed to be in order.
+pub struct Timeline {
+    pickup: f64,
+    // Usable time signatures, in order of measure.
+    time_signatures: Vec<TimeSignature>,
+    spans: Vec<TempoSpan>,
+}
+
+impl Timeline {
+    /// Builds the timeline for a song.
+    pub fn new(song: &Song) -> Self {
+        let pickup = if song.pickup.is_finite() { song.pickup.max(0.0) } else { 0.0 };
+        let mut time_signatures: Vec<TimeSignature> =
+            song.time_signatures.iter().filter(|sig| sig.count > 0 && sig.unit > 0).copied().collect();
+        time_signatures.sort_by_key(|sig| sig.measure);
+        let mut timeline = Self { pickup, time_signatures, spans: Vec::new() };
+
+        // changes are applied in the order they happen, whatever order the song lists them in
+        let mut changes: Vec<(f64, &TempoChange)> = song
+            .tempo_changes
+            .iter()
+            .map(|change| (timeline.measure_start(change.measure) + change.beat, change))
+            .filter(|(change_start, _)| change_start.is_finite())
+            .collect();
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut start = 0.0;
+        let mut bpm = clamp_bpm(song.bpm);
+        for (change_start, change) in changes {
+            // a change that starts during the previous one's ramp waits for the ramp to finish
+            let change_start = change_start.max(start);
+            let change_bpm = clamp_bpm(change.bpm);
+            // constant tempo up to the change
+            timeline.spans.push(TempoSpan { start, end: change_start, start_bpm: bpm, end_bpm: bpm });
+            start = change_start;
+            // followed by a ramp if there is one
+            if change.ramp > 0.0 && change.ramp.is_finite() {
+                timeline.spans.push(TempoSpan {
+                    start,
+                    end: start + change.ramp,
+                    start_bpm: bpm,
+                    end_bpm: change_bpm,
+                });
+                start += change.ramp;
+            }
+            bpm = change_bpm;
+        }
+        // the last tempo lasts until the end of the song
+        timeline.spans.push(TempoSpan { start, end: f64::INFINITY, start_bpm: bpm, end_bpm: bpm });
+
+        timeline
+    }
+
+    /// Returns the time signature in effect for a measure.
+    pub fn time_signature(&self, measure: u32) -> TimeSignature {
+        self.time_signatures
+            .iter()
+            .rev()
+            .find(|sig| sig.measure <= measure)
+            .copied()
+            .unwrap_or(TimeSignature { measure: 0, count: 4, unit: 4 })
+    }
+
+    /// Returns the measure after `measure` where the time signature changes, if it changes again.
+    fn next_change(&self, measure: u32) -> Option<u32> {
+        self.time_signatures.iter().map(|sig| sig.measure).find(|&m| m > measure)
+    }
+
+    /// Returns the beat that a measure starts on. Measure 0 is the pickup (if there is one),
+    /// so measure 1 starts right after it.
+    pub fn measure_start(&self, measure: u32) -> f64 {
+        if measure == 0 {
+            return 0.0;
+        }
+        // jump from one time signature change to the next, rather than counting every measure
+        let mut cur = 1;
+        let mut beat = self.pickup;
+        while let Some(next) = self.next_change(cur).filter(|&next| next < measure) {
+            beat += (next - cur) as f64 * self.time_signature(cur).measure_beats();
+            cur = next;
+        }
+        beat + (measure - cur) as f64 * self.time_signature(cur).measure_beats()
+    }
+
+    /// Returns the measure that a beat falls in.
+    pub fn measure_at(&self, beat: f64) -> u32 {
+        if beat.is_nan() || beat < self.pickup {
+            return 0;
+        }
+        let mut cur = 1;
+        let mut start = self.pickup;
+        loop {
+            let measure_beats = self.time_signature(cur).measure_beats();
+            // whole measures since `cur` started; the cast saturates for beats way past the end
+            let measures = ((beat - start) / measure_beats) as u32;
+            match self.next_change(cur) {
+                Some(next) if measures >= next - cur => {
+                    start += (next - cur) as f64 * measure_beats;
+                    cur = next;
+                }
+                _ => return cur.saturating_add(measures),
+            }
+        }
+    }
+
+    /// Returns the time from the start of the song to a beat, in seconds.
+    pub fn seconds_at(&self, beat: f64) -> f64 {
+        self.spans.iter().map(|span| span.seconds_to(beat)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::music::Articulation;
+
+    fn song(bpm: f64, pickup: f64, tempo_changes: Vec<TempoChange>, time_signatures: Vec<TimeSignature>) -> Song {
+        Song {
+            name: Cow::Borrowed("test"),
+            bpm,
+            tempo_changes: Cow::Owned(tempo_changes),
+            time_signatures: Cow::Owned(time_signatures),
+            pickup,
+            articulation: Articulation::Normal,
+            volume: 0.5,
+            voices: Cow::Owned(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn counts_measures_across_time_signatures() {
+        let song = song(120.0, 1.0, Vec::new(), vec![TimeSignature { measure: 3, count: 3, unit: 4 }]);
+        let timeline = Timeline::new(&song);
+        assert_eq!(timeline.measure_at(0.5), 0);
+        assert_eq!(timeline.measure_at(1.0), 1);
+        assert_eq!(timeline.measure_at(8.9), 2);
+        assert_eq!(timeline.measure_at(9.0), 3);
+        assert_eq!(timeline.measure_at(12.0), 4);
+        assert_eq!(timeline.measure_start(4), 12.0);
+        assert_eq!(timeline.measure_at(f64::INFINITY), u32::MAX);
+    }
+
+    #[test]
+    fn ignores_degenerate_time_signatures() {
+        let sigs = vec![
+            TimeSignature { measure: 1, count: 0, unit: 4 },
+            TimeSignature { measure: 2, count: 4, unit: 0 },
+        ];
+        let timeline = Timeline::new(&song(120.0, f64::INFINITY, Vec::new(), sigs));
+        assert_eq!(timeline.measure_at(1e9), 250_000_001);
+        assert_eq!(timeline.measure_start(3), 8.0);
+    }
+
+    #[test]
+    fn clamps_tempos() {
+        let changes = vec![TempoChange { measure: 2, beat: 0.0, bpm: 0.0, ramp: 4.0 }];
+        let timeline = Timeline::new(&song(f64::NAN, 0.0, changes, Vec::new()));
+        let secs = timeline.seconds_at(12.0);
+        assert!(secs.is_finite() && secs > 0.0);
+    }
+
+    #[test]
+    fn sorts_tempo_changes() {
+        let a = TempoChange { measure: 2, beat: 0.0, bpm: 120.0, ramp: 0.0 };
+        let b = TempoChange { measure: 3, beat: 0.0, bpm: 30.0, ramp: 0.0 };
+        let sorted = Timeline::new(&song(60.0, 0.0, vec![a, b], Vec::new()));
+        let unsorted = Timeline::new(&song(60.0, 0.0, vec![b, a], Vec::new()));
+        // 4 beats at 60, 4 at 120, then 4 at 30
+        for timeline in [sorted, unsorted] {
+            assert_eq!(timeline.seconds_at(4.0), 4.0);
+            assert_eq!(timeline.seconds_at(8.0), 6.0);
+            assert_eq!(timeline.seconds_at(12.0), 14.0);
+        }
+    }
+
+    #[test]
+    fn waits_for_ramps_to_finish() {
+        // the second change starts halfway through the first one's ramp, so it's pushed back to where it ends
+        let ramp = TempoChange { measure: 2, beat: 0.0, bpm: 120.0, ramp: 4.0 };
+        let jump = TempoChange { measure: 2, beat: 2.0, bpm: 30.0, ramp: 0.0 };
+        let timeline = Timeline::new(&song(60.0, 0.0, vec![ramp, jump], Vec::new()));
+        let ramp_secs = timeline.seconds_at(8.0) - timeline.seconds_at(4.0);
+        assert!(ramp_secs > 2.0 && ramp_secs < 4.0, "ramp took {}s", ramp_secs);
+        assert_eq!(timeline.seconds_at(10.0) - timeline.seconds_at(8.0), 4.0);
+    }
+}
@@ -0,0 +1,178 @@
+/*
+order_code.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Orders encoded as text, for barcodes and QR codes.
+//!
+//! An order code looks like `DISPENSE;R=2;G=1;ID=EVT-0042;SIG=0123...`. It starts with `DISPENSE`, followed by
+//! `KEY=VALUE` fields separated by semicolons, in any order:
+//! - `R` and `G`: number of red and green items. A missing slot counts as 0.
+//! - `ID`: identifies the order, so the same code can't be used twice. This is optional unless the dispenser has
+//!   a key set, since a signed code without an ID could be used over and over.
+//! - `SIG` (optional): HMAC-SHA1 of everything before `;SIG=`, in hex, using a key shared with whoever makes the
+//!   codes. If the dispenser has a key set, codes without a valid signature are rejected.
+//!
+//! Keys and the `DISPENSE` prefix can be in any case, since some scanners change the case of what they read.
+
+use std::{error::Error, fmt};
+
+use sha1::{Digest, Sha1};
+
+/// Text every order code starts with.
+const PREFIX: &str = "DISPENSE";
+/// Text that separates the signature from the signed part of the code.
+const SIG_FIELD: &str = ";SIG=";
+
+/// An order read from a code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderCode {
+    pub red: u64,
+    pub green: u64,
+    pub id: Option<String>,
+}
+
+/// Reasons an order code can be rejected.
+#[derive(Debug)]
+pub enum OrderCodeError {
+    /// The text isn't an order code at all.
+    NotAnOrder,
+    /// One of the fields is unknown or has an invalid value.
+    BadField(String),
+    /// The dispenser requires signed codes, and this one isn't signed.
+    MissingSignature,
+    /// The signature doesn't match the order.
+    BadSignature,
+    /// The dispenser requires signed codes, and this one has no ID to stop it being used again.
+    MissingId,
+}
+
+impl fmt::Display for OrderCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderCodeError::NotAnOrder => write!(f, "not an order code"),
+            OrderCodeError::BadField(field) => write!(f, "invalid field {:?} in order code", field),
+            OrderCodeError::MissingSignature => write!(f, "order code is not signed"),
+            OrderCodeError::BadSignature => write!(f, "order code has an invalid signature"),
+            OrderCodeError::MissingId => write!(f, "order code has no ID"),
+        }
+    }
+}
+
+impl Error for OrderCodeError {}
+
+/// Reads an order code. If `key` is set, the code must be signed with it and have an ID.
+pub fn parse_order_code(text: &str, key: Option<&[u8]>) -> Result<OrderCode, OrderCodeError> {
+    let text = text.trim();
+    // split off the signature, matching its name in any case
+    let (payload, signature) = match text.to_ascii_uppercase().rfind(SIG_FIELD) {
+        Some(i) => (&text[..i], Some(&text[i + SIG_FIELD.len()..])),
+        None => (text, None),
+    };
+    match (key, signature) {
+        (Some(key), Some(signature)) => {
+            let expected = hex_encode(&hmac_sha1(key, payload.as_bytes()));
+            if !constant_time_eq(expected.as_bytes(), signature.to_ascii_lowercase().as_bytes()) {
+                return Err(OrderCodeError::BadSignature);
+            }
+        }
+        (Some(_), None) => return Err(OrderCodeError::MissingSignature),
+        // without a key, there's nothing to check a signature against
+        (None, _) => {}
+    }
+
+    let mut fields = payload.split(';');
+    if !fields.next().is_some_and(|prefix| prefix.eq_ignore_ascii_case(PREFIX)) {
+        return Err(OrderCodeError::NotAnOrder);
+    }
+    let mut order = OrderCode { red: 0, green: 0, id: None };
+    for field in fields.filter(|field| !field.is_empty()) {
+        let bad_field = || OrderCodeError::BadField(field.to_owned());
+        let (name, value) = field.split_once('=').ok_or_else(bad_field)?;
+        match name.to_ascii_uppercase().as_str() {
+            "R" => order.red = value.parse().map_err(|_| bad_field())?,
+            "G" => order.green = value.parse().map_err(|_| bad_field())?,
+            "ID" if !value.is_empty() => order.id = Some(value.to_owned()),
+            _ => return Err(bad_field()),
+        }
+    }
+    if key.is_some() && order.id.is_none() {
+        return Err(OrderCodeError::MissingId);
+    }
+    Ok(order)
+}
+
+/// Signs an order code with a key, returning the code with its signature on the end.
+pub fn sign_order_code(payload: &str, key: &[u8]) -> String {
+    format!("{}{}{}", payload, SIG_FIELD, hex_encode(&hmac_sha1(key, payload.as_bytes())))
+}
+
+/// Computes an HMAC (RFC 2104) using SHA-1.
+fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    const BLOCK_SIZE: usize = 64;
+    // keys longer than a block are hashed first; shorter ones are padded with zeros
+    let mut block_key = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block_key[..20].copy_from_slice(&Sha1::digest(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let inner = Sha1::new()
+        .chain_update(block_key.map(|b| b ^ 0x36))
+        .chain_update(message)
+        .finalize();
+    Sha1::new()
+        .chain_update(block_key.map(|b| b ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compares two byte strings in a time that doesn't depend on where they differ, so the right signature
+/// can't be worked out by timing how long it takes to be rejected.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"secret";
+
+    #[test]
+    fn parses_unsigned_codes_without_a_key() {
+        let order = parse_order_code("dispense;r=2;G=1", None).unwrap();
+        assert_eq!(order, OrderCode { red: 2, green: 1, id: None });
+        assert!(matches!(parse_order_code("hello", None), Err(OrderCodeError::NotAnOrder)));
+        assert!(matches!(parse_order_code("DISPENSE;R=x", None), Err(OrderCodeError::BadField(_))));
+    }
+
+    #[test]
+    fn checks_signatures() {
+        let code = sign_order_code("DISPENSE;R=2;ID=EVT-1", KEY);
+        let order = parse_order_code(&code, Some(KEY)).unwrap();
+        assert_eq!(order, OrderCode { red: 2, green: 0, id: Some("EVT-1".to_owned()) });
+
+        let tampered = code.replace("R=2", "R=9");
+        assert!(matches!(parse_order_code(&tampered, Some(KEY)), Err(OrderCodeError::BadSignature)));
+        assert!(matches!(
+            parse_order_code("DISPENSE;R=2;ID=EVT-1", Some(KEY)),
+            Err(OrderCodeError::MissingSignature)
+        ));
+    }
+
+    #[test]
+    fn requires_ids_with_a_key() {
+        let code = sign_order_code("DISPENSE;R=2", KEY);
+        assert!(matches!(parse_order_code(&code, Some(KEY)), Err(OrderCodeError::MissingId)));
+        assert!(parse_order_code(&code, None).is_ok());
+    }
+}
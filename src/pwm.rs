@@ -9,16 +9,19 @@ Date: Jun. 17, 2024
 
 use rppal::pwm::{self, Pwm};
 
-type PwmError = rppal::pwm::Error;
+use crate::{
+    error::{Error, Result},
+    music::midi2freq,
+};
 
 
 /// Converts a pin number to a PWM channel. This didn't have to be done, it
 /// just makes the code easier to understand where I'm actually using it.
-fn to_pwm_channel(pin: u8) -> pwm::Channel {
+fn to_pwm_channel(pin: u8) -> Result<pwm::Channel> {
     match pin {
-        18 => pwm::Channel::Pwm0,
-        19 => pwm::Channel::Pwm1,
-        _ => panic!("Specified pin cannot be configured for PWM"),
+        18 => Ok(pwm::Channel::Pwm0),
+        19 => Ok(pwm::Channel::Pwm1),
+        _ => Err(Error::NotPwmPin(pin)),
     }
 }
 
@@ -29,13 +32,13 @@ pub struct PwmToneBuzzer {
 
 impl PwmToneBuzzer {
     /// Allocates a PWM channel for a tone buzzer on the desired pin.
-    /// Note that only pins 18 and 19 support PWM; using any other pin results in a panic.
+    /// Note that only pins 18 and 19 support PWM; using any other pin results in an error.
     /// If PWM is not set up, it will not return.
-    pub fn new(pin: u8) -> Result<PwmToneBuzzer, PwmError> {
+    pub fn new(pin: u8) -> Result<PwmToneBuzzer> {
         // Setup PWM on the desired pin. This can fail, so we use the ?
         // operator to return an error if it occurs.
         let pwm = Pwm::with_frequency(
-            to_pwm_channel(pin),   // PWM channel (0 = pin 18, 1 = pin 19)
+            to_pwm_channel(pin)?,  // PWM channel (0 = pin 18, 1 = pin 19)
             440.0,                 // frequency (changing affects pitch)
             0.5,                   // duty cycle (changing it affects timbre)
             pwm::Polarity::Normal, // polarity
@@ -45,24 +48,33 @@ impl PwmToneBuzzer {
         Ok(Self { pwm })
     }
 
-    /// Sets this tone buzzer to play a note. If the note is 0, stops the tone buzzer instead.
-    pub fn play_midi(&mut self, midi: u32) {
+    /// Sets this tone buzzer to play a note at a specific duty cycle, which controls its volume.
+    /// If the note is 0, stops the tone buzzer instead.
+    pub fn play_midi(&mut self, midi: u32, duty: f64) -> Result<()> {
         // Stop the tone buzzer if the note is 0.
         if midi == 0 {
-            self.stop();
-            return
+            return self.stop();
         }
-        // this optimized formula converts a MIDI note to a frequency.
-        let freq = (midi as f64 / 12.0).exp2() * 8.175_798_915_643_707_33;
+        let freq = midi2freq(midi);
         // Set the frequency from above; the 2nd parameter is duty cycle.
-        // This affects the timbre of the resulting note, I found 0.25 to be less harsh than 0.5.
-        self.pwm.set_frequency(freq, 0.25).unwrap();
+        self.pwm.set_frequency(freq, duty)?;
         // Enable the tone buzzer.
-        self.pwm.enable().unwrap();
+        self.pwm.enable()?;
+        Ok(())
     }
 
-    pub fn stop(&mut self) {
+    /// Stops this tone buzzer.
+    pub fn stop(&mut self) -> Result<()> {
         // Disable the tone buzzer (stopping output).
-        self.pwm.disable().unwrap();
+        self.pwm.disable()?;
+        Ok(())
     }
 }
+
+/// Turns off the PWM output on a pin, even if another part of the program has it open.
+/// This is a last resort for making sure a buzzer is silent when the program can't exit cleanly.
+pub fn force_disable(pin: u8) -> Result<()> {
+    let pwm = Pwm::new(to_pwm_channel(pin)?)?;
+    pwm.disable()?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+/*
+rfid.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! RFID badge readers: the common RC522 (MFRC522) module on the SPI bus, and a simulated reader for testing.
+//!
+//! Only the badge's UID is read, which is all that's needed to tell people apart. That works with any
+//! ISO 14443A badge (MIFARE Classic, NTAG, most building access badges), without needing its keys.
+
+use std::{
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use crate::error::Result;
+
+/// Something that reads badges.
+pub trait BadgeReader {
+    /// Checks for a badge, returning its UID (in hex) if one is in range. This is called a few times a second.
+    fn poll(&mut self) -> Result<Option<String>>;
+}
+
+/// An RC522 module on SPI0.
+pub struct Rc522 {
+    spi: Spi,
+}
+
+impl Rc522 {
+    const CLOCK_SPEED: u32 = 1_000_000;
+
+    // registers
+    const COMMAND: u8 = 0x01;
+    const COM_IRQ: u8 = 0x04;
+    const ERROR: u8 = 0x06;
+    const FIFO_DATA: u8 = 0x09;
+    const FIFO_LEVEL: u8 = 0x0a;
+    const BIT_FRAMING: u8 = 0x0d;
+    const MODE: u8 = 0x11;
+    const TX_CONTROL: u8 = 0x14;
+    const TX_ASK: u8 = 0x15;
+    const T_MODE: u8 = 0x2a;
+    const T_PRESCALER: u8 = 0x2b;
+    const T_RELOAD_H: u8 = 0x2c;
+    const T_RELOAD_L: u8 = 0x2d;
+
+    // commands
+    const IDLE: u8 = 0x00;
+    const TRANSCEIVE: u8 = 0x0c;
+    const SOFT_RESET: u8 = 0x0f;
+
+    // commands sent to badges
+    const REQA: u8 = 0x26;
+    const ANTICOLLISION: [u8; 2] = [0x93, 0x20];
+
+    /// Sets up a reader on SPI0, with its SDA pin connected to the given chip select (0 or 1).
+    pub fn new(chip_select: u8) -> Result<Rc522> {
+        let slave_select = if chip_select == 1 { SlaveSelect::Ss1 } else { SlaveSelect::Ss0 };
+        let spi = Spi::new(Bus::Spi0, slave_select, Self::CLOCK_SPEED, Mode::Mode0)?;
+        let mut reader = Self { spi };
+
+        reader.write(Self::COMMAND, Self::SOFT_RESET)?;
+        thread::sleep(Duration::from_millis(50));
+        // timer of about 25ms, so a transceive gives up if no badge answers
+        reader.write(Self::T_MODE, 0x8d)?;
+        reader.write(Self::T_PRESCALER, 0x3e)?;
+        reader.write(Self::T_RELOAD_H, 0)?;
+        reader.write(Self::T_RELOAD_L, 30)?;
+        // 100% ASK modulation, CRC preset 0x6363 as ISO 14443A wants
+        reader.write(Self::TX_ASK, 0x40)?;
+        reader.write(Self::MODE, 0x3d)?;
+        // antenna on
+        let tx_control = reader.read(Self::TX_CONTROL)?;
+        reader.write(Self::TX_CONTROL, tx_control | 0x03)?;
+        Ok(reader)
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u8> {
+        let mut buffer = [0u8; 2];
+        self.spi.transfer(&mut buffer, &[0x80 | (reg << 1), 0])?;
+        Ok(buffer[1])
+    }
+
+    fn write(&mut self, reg: u8, value: u8) -> Result<()> {
+        self.spi.write(&[reg << 1, value])?;
+        Ok(())
+    }
+
+    /// Sends data to a badge and returns its answer, or `None` if nothing answered properly.
+    /// `last_bits` is the number of bits to send from the last byte (0 means all 8).
+    fn transceive(&mut self, data: &[u8], last_bits: u8) -> Result<Option<Vec<u8>>> {
+        self.write(Self::COMMAND, Self::IDLE)?;
+        self.write(Self::COM_IRQ, 0x7f)?;
+        self.write(Self::FIFO_LEVEL, 0x80)?;
+        for &byte in data {
+            self.write(Self::FIFO_DATA, byte)?;
+        }
+        self.write(Self::COMMAND, Self::TRANSCEIVE)?;
+        self.write(Self::BIT_FRAMING, 0x80 | last_bits)?;
+
+        // wait for an answer (RxIRq or IdleIRq), or for the timer to run out (TimerIRq)
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let irq = loop {
+            let irq = self.read(Self::COM_IRQ)?;
+            if irq & 0x31 != 0 || Instant::now() > deadline {
+                break irq;
+            }
+        };
+        self.write(Self::BIT_FRAMING, 0)?;
+        // no answer, or a garbled one (buffer overflow, parity or protocol error)
+        if irq & 0x30 == 0 || self.read(Self::ERROR)? & 0x13 != 0 {
+            return Ok(None);
+        }
+
+        let len = self.read(Self::FIFO_LEVEL)?;
+        let mut answer = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            answer.push(self.read(Self::FIFO_DATA)?);
+        }
+        Ok(Some(answer))
+    }
+}
+
+impl BadgeReader for Rc522 {
+    fn poll(&mut self) -> Result<Option<String>> {
+        // wake up any badges in range (REQA is a short frame of 7 bits)
+        if self.transceive(&[Self::REQA], 7)?.is_none() {
+            return Ok(None);
+        }
+        // ask for the UID: 4 bytes, then a check byte that's all of them XORed together
+        let Some(answer) = self.transceive(&Self::ANTICOLLISION, 0)? else {
+            return Ok(None);
+        };
+        match answer[..] {
+            [a, b, c, d, check] if a ^ b ^ c ^ d == check => {
+                Ok(Some(format!("{:02X}{:02X}{:02X}{:02X}", a, b, c, d)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Badges "tapped" from elsewhere in the program, for testing without a reader.
+pub struct SimulatedReader {
+    receiver: Receiver<String>,
+}
+
+impl SimulatedReader {
+    /// Creates a reader that reads every UID sent down the channel.
+    pub fn new(receiver: Receiver<String>) -> SimulatedReader {
+        Self { receiver }
+    }
+}
+
+impl BadgeReader for SimulatedReader {
+    fn poll(&mut self) -> Result<Option<String>> {
+        Ok(self.receiver.try_recv().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn simulated_reader_reads_one_tap_per_poll() {
+        let (sender, receiver) = mpsc::channel();
+        let mut reader = SimulatedReader::new(receiver);
+        assert_eq!(reader.poll().unwrap(), None);
+        sender.send("04A1B2C3".to_owned()).unwrap();
+        sender.send("DEADBEEF".to_owned()).unwrap();
+        assert_eq!(reader.poll().unwrap().as_deref(), Some("04A1B2C3"));
+        assert_eq!(reader.poll().unwrap().as_deref(), Some("DEADBEEF"));
+        assert_eq!(reader.poll().unwrap(), None);
+        // the reader keeps working (with nothing in range) after the sending end is gone
+        drop(sender);
+        assert_eq!(reader.poll().unwrap(), None);
+    }
+}
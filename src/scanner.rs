@@ -0,0 +1,86 @@
+/*
+scanner.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! Reading a USB barcode scanner directly through its input device (`/dev/input/event*`).
+//!
+//! Barcode scanners pretend to be keyboards, typing what they scan followed by Enter. Reading the input device
+//! directly means the scanner works even when the window isn't focused. The device is grabbed, so what it types
+//! doesn't also end up in the GUI.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    mem,
+    os::fd::AsRawFd,
+};
+
+/// `EVIOCGRAB` ioctl, which gives this program exclusive access to an input device.
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+/// Event type for key presses.
+const EV_KEY: u16 = 1;
+/// Key codes for the shift keys and both Enter keys.
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_RIGHTSHIFT: u16 = 54;
+const KEY_ENTER: u16 = 28;
+const KEY_KPENTER: u16 = 96;
+
+/// A barcode scanner's input device.
+pub struct EvdevScanner {
+    file: File,
+    shift: bool,
+    line: String,
+}
+
+impl EvdevScanner {
+    /// Opens and grabs an input device.
+    pub fn open(path: &str) -> io::Result<EvdevScanner> {
+        let file = File::open(path)?;
+        // SAFETY: EVIOCGRAB takes an int, and the file descriptor stays open for as long as `file` exists
+        if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, 1 as libc::c_int) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { file, shift: false, line: String::new() })
+    }
+
+    /// Waits until a whole line has been scanned, and returns it (without the Enter at the end).
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let mut buffer = [0u8; mem::size_of::<libc::input_event>()];
+        loop {
+            self.file.read_exact(&mut buffer)?;
+            // SAFETY: input_event is plain data, and the kernel always sends whole events
+            let event: libc::input_event = unsafe { std::ptr::read_unaligned(buffer.as_ptr().cast()) };
+            if event.type_ != EV_KEY {
+                continue;
+            }
+            // value is 1 for a press, 2 for a key repeat and 0 for a release
+            match (event.code, event.value) {
+                (KEY_LEFTSHIFT | KEY_RIGHTSHIFT, value) => self.shift = value != 0,
+                (KEY_ENTER | KEY_KPENTER, 1) => return Ok(mem::take(&mut self.line)),
+                (code, 1 | 2) => self.line.extend(key_char(code, self.shift)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Converts a key code to the character it types on a US keyboard, which is what most scanners emulate.
+fn key_char(code: u16, shift: bool) -> Option<char> {
+    // each row of the keyboard, starting at the key code of its first key
+    const ROWS: [(u16, &str, &str); 4] = [
+        (2, "1234567890-=", "!@#$%^&*()_+"),
+        (16, "qwertyuiop[]", "QWERTYUIOP{}"),
+        (30, "asdfghjkl;'`", "ASDFGHJKL:\"~"),
+        (43, "\\zxcvbnm,./", "|ZXCVBNM<>?"),
+    ];
+    if code == 57 {
+        return Some(' ');
+    }
+    ROWS.iter().find_map(|&(start, normal, shifted)| {
+        let chars = if shift { shifted } else { normal };
+        chars.chars().nth(code.checked_sub(start)? as usize)
+    })
+}
@@ -0,0 +1,111 @@
+/*
+surprise.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! "Surprise me" orders: the user picks how many items they want, and the dispenser picks the slots.
+//!
+//! Each item goes to a random slot, with the chance of each slot proportional to its weight. Slots that run
+//! out of stock stop being picked. The random number generator can be given a fixed seed, so the same
+//! sequence of orders comes out every time (e.g. to check the weights do what they should).
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, SeedableRng};
+
+/// Picks random orders.
+pub struct SurprisePicker {
+    rng: StdRng,
+    weights: [f64; 2],
+}
+
+impl SurprisePicker {
+    /// Creates a picker with a weight for each slot (red, green). Without a seed, the orders are different
+    /// every time the app starts. Weights that aren't a number of 0 or more count as 0.
+    pub fn new(weights: [f64; 2], seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        // the weights have to add up to a finite number, or picking panics
+        let weights =
+            weights.map(|weight| if weight.is_finite() && weight > 0.0 { weight.min(f64::MAX / 2.0) } else { 0.0 });
+        Self { rng, weights }
+    }
+
+    /// Picks how many items to take from each slot, for `total` items in all. `stock` is how many items are
+    /// left in each slot, or `None` if the slot isn't tracked. Returns `None` if there isn't enough left in
+    /// the slots that can be picked.
+    pub fn pick(&mut self, total: u64, stock: [Option<u64>; 2]) -> Option<[u64; 2]> {
+        let mut counts = [0; 2];
+        for _ in 0..total {
+            // only slots with a weight and something left in them can be picked
+            let weights: Vec<f64> = (0..2)
+                .map(|slot| {
+                    let has_stock = !matches!(stock[slot], Some(stock) if counts[slot] >= stock);
+                    if has_stock && self.weights[slot] > 0.0 { self.weights[slot] } else { 0.0 }
+                })
+                .collect();
+            // this fails if every weight is 0
+            let index = WeightedIndex::new(&weights).ok()?;
+            counts[index.sample(&mut self.rng)] += 1;
+        }
+        Some(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_total_asked_for() {
+        let mut picker = SurprisePicker::new([1.0, 3.0], Some(42));
+        for total in [0, 1, 7, 100] {
+            let counts = picker.pick(total, [None, None]).unwrap();
+            assert_eq!(counts[0] + counts[1], total);
+        }
+        // with a big enough order, the weights show
+        let counts = picker.pick(10_000, [None, None]).unwrap();
+        assert!((2_000..3_000).contains(&counts[0]), "picked {:?}", counts);
+    }
+
+    #[test]
+    fn repeats_with_the_same_seed() {
+        let mut a = SurprisePicker::new([1.0, 1.0], Some(7));
+        let mut b = SurprisePicker::new([1.0, 1.0], Some(7));
+        for _ in 0..10 {
+            assert_eq!(a.pick(5, [None, None]), b.pick(5, [None, None]));
+        }
+    }
+
+    #[test]
+    fn stays_within_stock() {
+        let mut picker = SurprisePicker::new([100.0, 1.0], Some(1));
+        assert_eq!(picker.pick(5, [Some(2), None]), Some([2, 3]));
+        assert_eq!(picker.pick(5, [Some(2), Some(3)]), Some([2, 3]));
+        assert_eq!(picker.pick(6, [Some(2), Some(3)]), None);
+    }
+
+    #[test]
+    fn picks_nothing_without_weights() {
+        let mut picker = SurprisePicker::new([0.0, 0.0], Some(1));
+        assert_eq!(picker.pick(1, [None, None]), None);
+        assert_eq!(picker.pick(0, [None, None]), Some([0, 0]));
+        // a slot with no weight is never picked, even if the other runs out
+        let mut picker = SurprisePicker::new([1.0, 0.0], Some(1));
+        assert_eq!(picker.pick(3, [None, None]), Some([3, 0]));
+        assert_eq!(picker.pick(3, [Some(2), None]), None);
+    }
+
+    #[test]
+    fn ignores_bad_weights() {
+        let mut picker = SurprisePicker::new([f64::INFINITY, f64::NAN], Some(1));
+        assert_eq!(picker.pick(1, [None, None]), None);
+        let mut picker = SurprisePicker::new([f64::MAX, f64::MAX], Some(1));
+        let counts = picker.pick(10, [None, None]).unwrap();
+        assert_eq!(counts[0] + counts[1], 10);
+        let mut picker = SurprisePicker::new([-1.0, 1.0], Some(1));
+        assert_eq!(picker.pick(4, [None, None]), Some([0, 4]));
+    }
+}
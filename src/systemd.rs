@@ -0,0 +1,131 @@
+/*
+systemd.rs
+Language: Rust 1.78.0
+Author: Jacky Guo
+Date: Jun. 17, 2024
+*/
+
+//! A minimal implementation of systemd's service notification protocol (`sd_notify`).
+//!
+//! systemd passes the path to a datagram socket in `NOTIFY_SOCKET`; each message is a newline-separated
+//! list of `KEY=VALUE` pairs, like `READY=1` or `STATUS=Idle`. Paths starting with `@` are abstract sockets.
+
+use std::{
+    env, io,
+    os::{linux::net::SocketAddrExt, unix::net::{SocketAddr, UnixDatagram}},
+    process,
+    time::Duration,
+};
+
+/// Sends notifications to systemd (or anything else listening on a notify socket).
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// Connects to the socket given by `NOTIFY_SOCKET`. Returns `None` if the variable isn't set,
+    /// i.e. the program isn't running as a systemd service.
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        match env::var("NOTIFY_SOCKET") {
+            Ok(path) => Notifier::new(&path).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Creates a notifier that sends to a socket at a specific path. Paths starting with `@` are abstract sockets.
+    /// Any datagram socket bound to this path will receive the notifications, which is useful for testing.
+    pub fn new(path: &str) -> io::Result<Notifier> {
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Self { socket: UnixDatagram::unbound()?, addr })
+    }
+
+    /// Sends a raw notification, made up of newline-separated `KEY=VALUE` pairs.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.addr)?;
+        Ok(())
+    }
+
+    /// Tells systemd the service has finished starting up.
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Tells systemd the service is shutting down.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    /// Sets the status line shown by `systemctl status`.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        // the status can't span multiple lines, since each line is a separate field
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+
+    /// Tells systemd the service is still healthy.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+}
+
+/// Returns how often systemd expects a watchdog ping, if the watchdog is enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    // the watchdog may be meant for a different process (e.g. a parent shell)
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Binds a stand-in for systemd's notify socket, returning it along with its path.
+    fn bind_socket(name: &str) -> (UnixDatagram, String) {
+        let path = env::temp_dir().join(format!("dispenser-{}-{}.sock", name, process::id()));
+        let _ = fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        (socket, path.to_string_lossy().into_owned())
+    }
+
+    /// Receives one datagram as text.
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 256];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn sends_notifications() {
+        let (socket, path) = bind_socket("notify");
+        let notifier = Notifier::new(&path).unwrap();
+        notifier.ready().unwrap();
+        notifier.status("Dispensing\n2 red").unwrap();
+        notifier.watchdog().unwrap();
+        notifier.stopping().unwrap();
+        assert_eq!(recv(&socket), "READY=1");
+        assert_eq!(recv(&socket), "STATUS=Dispensing 2 red");
+        assert_eq!(recv(&socket), "WATCHDOG=1");
+        assert_eq!(recv(&socket), "STOPPING=1");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sends_to_abstract_sockets() {
+        let name = format!("dispenser-abstract-{}", process::id());
+        let socket = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        Notifier::new(&format!("@{}", name)).unwrap().watchdog().unwrap();
+        assert_eq!(recv(&socket), "WATCHDOG=1");
+    }
+}